@@ -0,0 +1,508 @@
+//! Configuration and Connection Checks
+//!
+//! Backs the `check-config` and `test-connection` subcommands, which let deployment
+//! pipelines catch mistakes before the exporter is started:
+//!
+//! - [`check_config`] looks for settings that deserialize fine but cannot work, such as
+//!   an API key sent over an unencrypted connection or a port that does not match
+//!   `use_tls`
+//! - [`test_connection`] connects and logs in to every configured host and runs each
+//!   enabled collector once
+//!
+//! Both return a [`Report`] that is printed as a table; any failed check makes the
+//! subcommand exit non-zero.
+
+use crate::collectors::{self, CollectionContext, CollectionStatus, Registry};
+use crate::config::{Config, TrueNasConfig};
+use crate::metrics::MetricsCollector;
+use crate::truenas::{auth, tls, TrueNasClient};
+use crate::web::WebConfig;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    /// Works, but probably not as intended
+    Warn,
+    Fail,
+    /// Not run, e.g. a disabled collector
+    Skip,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+            Status::Skip => "SKIP",
+        })
+    }
+}
+
+/// One row of a [`Report`]
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// Host or probe module the check applies to, `-` for global settings
+    pub target: String,
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+/// Results of [`check_config`] or [`test_connection`]
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn add(&mut self, target: &str, name: &str, status: Status, detail: impl Into<String>) {
+        self.checks.push(Check {
+            target: target.to_string(),
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    /// The check with this target and name, if it was run
+    pub fn get(&self, target: &str, name: &str) -> Option<&Check> {
+        self.checks
+            .iter()
+            .find(|check| check.target == target && check.name == name)
+    }
+
+    /// True if no check failed; warnings do not count
+    pub fn passed(&self) -> bool {
+        !self.checks.iter().any(|check| check.status == Status::Fail)
+    }
+
+    /// The checks as an aligned table, followed by a summary line
+    pub fn table(&self) -> String {
+        let headers = ["TARGET", "CHECK", "RESULT", "DETAIL"];
+        let rows: Vec<[String; 4]> = self
+            .checks
+            .iter()
+            .map(|check| {
+                [
+                    check.target.clone(),
+                    check.name.clone(),
+                    check.status.to_string(),
+                    check.detail.clone(),
+                ]
+            })
+            .collect();
+
+        let mut widths = headers.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        let line = |cells: [&str; 4]| {
+            let mut line = String::new();
+            for (i, cell) in cells.iter().enumerate().take(3) {
+                line.push_str(&format!("{:<width$}  ", cell, width = widths[i]));
+            }
+            line.push_str(cells[3]);
+            line.trim_end().to_string() + "\n"
+        };
+
+        let mut table = line(headers);
+        for row in &rows {
+            table.push_str(&line([&row[0], &row[1], &row[2], &row[3]]));
+        }
+        let count = |status| {
+            self.checks
+                .iter()
+                .filter(|check| check.status == status)
+                .count()
+        };
+        table.push_str(&format!(
+            "\n{} passed, {} warned, {} failed, {} skipped\n",
+            count(Status::Pass),
+            count(Status::Warn),
+            count(Status::Fail),
+            count(Status::Skip)
+        ));
+        table
+    }
+}
+
+/// Look for settings that load fine but cannot work as configured
+pub fn check_config(config: &Config) -> Report {
+    let mut report = Report::default();
+
+    for truenas in config.truenas.hosts() {
+        let target = target_name(truenas);
+        check_host(&mut report, &target, truenas, config);
+        check_tls(&mut report, &target, truenas);
+        check_auth(&mut report, &target, truenas);
+    }
+
+    // The host of a probe module is replaced by the `target` parameter
+    let mut modules: Vec<_> = config.probe.modules.iter().collect();
+    modules.sort_by_key(|(name, _)| name.as_str());
+    for (name, module) in modules {
+        let target = format!("probe module {}", name);
+        check_tls(&mut report, &target, &module.truenas);
+        check_auth(&mut report, &target, &module.truenas);
+    }
+
+    check_targets(&mut report, config);
+    check_collectors(&mut report, config);
+    check_intervals(&mut report, config);
+    if let Some(path) = &config.server.web_config_file {
+        match WebConfig::load(path) {
+            Ok(web) => {
+                let tls = if web.tls_server_config.is_some() {
+                    "HTTPS"
+                } else {
+                    "HTTP"
+                };
+                let auth = if web.requires_auth() {
+                    "authentication required"
+                } else {
+                    "no authentication"
+                };
+                report.add(
+                    "-",
+                    "web config",
+                    Status::Pass,
+                    format!("{}, {}", tls, auth),
+                );
+            }
+            Err(e) => report.add("-", "web config", Status::Fail, format!("{:#}", e)),
+        }
+    }
+
+    report
+}
+
+/// Connect to every configured host and run each enabled collector once
+pub async fn test_connection(config: &Config) -> Report {
+    let mut report = Report::default();
+    let registry = Registry::new(&config.metrics);
+
+    for truenas in config.truenas.hosts() {
+        let target = target_name(truenas);
+        if truenas.host.is_empty() && truenas.replay_dir.is_none() {
+            report.add(&target, "connect", Status::Skip, "no host configured");
+            continue;
+        }
+
+        let client = TrueNasClient::new(truenas.clone());
+        let started = Instant::now();
+        if let Err(e) = client.connect().await {
+            report.add(&target, "connect", Status::Fail, e.to_string());
+            continue;
+        }
+        let version = client
+            .server_version()
+            .map_or_else(|| "unknown version".to_string(), |v| v.to_string());
+        report.add(
+            &target,
+            "connect",
+            Status::Pass,
+            format!("{} in {}", version, elapsed(started.elapsed())),
+        );
+
+        test_collectors(&mut report, &target, &client, &registry, config).await;
+        client.close().await;
+    }
+
+    report
+}
+
+/// Run the enabled collectors against `client`, reporting them in registry order
+async fn test_collectors(
+    report: &mut Report,
+    target: &str,
+    client: &TrueNasClient,
+    registry: &Registry,
+    config: &Config,
+) {
+    // Collectors write into a scratch registry; only their outcome matters here
+    let metrics = match MetricsCollector::new() {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            report.add(target, "collectors", Status::Fail, e.to_string());
+            return;
+        }
+    };
+    let ctx = CollectionContext {
+        client,
+        metrics: &metrics,
+        config: &config.metrics,
+    };
+
+    let mut queue = Vec::new();
+    for collector in registry.enabled() {
+        let unsupported = collector
+            .capability()
+            .filter(|capability| !client.supports(*capability));
+        if unsupported.is_none() {
+            queue.push((collector.name(), collector.collect(&ctx)));
+        }
+    }
+    let timeout = Duration::from_secs(config.metrics.collector_timeout_seconds);
+    let runs =
+        collectors::run_collectors(queue, config.metrics.max_concurrent_collectors, timeout).await;
+
+    for collector in registry.all() {
+        let name = collector.name();
+        if !collector.enabled() {
+            report.add(target, name, Status::Skip, "disabled");
+            continue;
+        }
+        if let Some(capability) = collector.capability() {
+            if !client.supports(capability) {
+                let (major, minor) = capability.min_version();
+                report.add(
+                    target,
+                    name,
+                    Status::Skip,
+                    format!("needs TrueNAS {}.{:02}", major, minor),
+                );
+                continue;
+            }
+        }
+        let Some((_, result, duration)) = runs.iter().find(|(run, _, _)| *run == name) else {
+            continue;
+        };
+        match result {
+            Ok(CollectionStatus::Success) => {
+                report.add(target, name, Status::Pass, elapsed(*duration));
+            }
+            Ok(CollectionStatus::Failed) => {
+                report.add(target, name, Status::Fail, "failed, see the log above");
+            }
+            Err(e) => report.add(target, name, Status::Fail, format!("{:#}", e)),
+        }
+    }
+}
+
+/// Name of a host in reports
+fn target_name(truenas: &TrueNasConfig) -> String {
+    match (&truenas.replay_dir, truenas.host.is_empty()) {
+        (Some(dir), true) => format!("replay {}", dir.display()),
+        (None, true) => "-".to_string(),
+        (_, false) => truenas.host.clone(),
+    }
+}
+
+/// `host` must be `host[:port]` with a port that matches `use_tls`
+fn check_host(report: &mut Report, target: &str, truenas: &TrueNasConfig, config: &Config) {
+    if let Some(dir) = &truenas.replay_dir {
+        if dir.is_dir() {
+            report.add(target, "host", Status::Pass, "replaying a recording");
+        } else {
+            report.add(
+                target,
+                "host",
+                Status::Fail,
+                format!("replay directory {} does not exist", dir.display()),
+            );
+        }
+        return;
+    }
+
+    let host = truenas.host.as_str();
+    if host.is_empty() {
+        let detail = "no host set, only /probe collects metrics";
+        if config.probe.modules.is_empty() {
+            report.add(target, "host", Status::Warn, detail);
+        } else {
+            report.add(target, "host", Status::Pass, detail);
+        }
+        return;
+    }
+    if host.contains("://") || host.contains('/') {
+        report.add(
+            target,
+            "host",
+            Status::Fail,
+            "host must be host[:port], without scheme or path",
+        );
+        return;
+    }
+
+    let scheme = if truenas.use_tls { "wss" } else { "ws" };
+    match port(host) {
+        Some(Err(port)) => {
+            report.add(
+                target,
+                "host",
+                Status::Fail,
+                format!("{:?} is not a port number", port),
+            );
+        }
+        Some(Ok(80)) if truenas.use_tls => report.add(
+            target,
+            "host",
+            Status::Fail,
+            "port 80 serves plain HTTP, but use_tls = true",
+        ),
+        Some(Ok(443)) if !truenas.use_tls => report.add(
+            target,
+            "host",
+            Status::Fail,
+            "port 443 expects TLS, but use_tls = false",
+        ),
+        _ => report.add(
+            target,
+            "host",
+            Status::Pass,
+            format!("{}://{}", scheme, host),
+        ),
+    }
+}
+
+/// Port given in `host`, or the text that should have been one
+fn port(host: &str) -> Option<Result<u16, &str>> {
+    let port = match host.strip_prefix('[') {
+        // Bracketed IPv6 address
+        Some(rest) => rest.split_once("]:")?.1,
+        None if host.matches(':').count() == 1 => host.split_once(':')?.1,
+        None => return None,
+    };
+    Some(port.parse().map_err(|_| port))
+}
+
+/// TLS files must load; disabled verification is worth a warning
+fn check_tls(report: &mut Report, target: &str, truenas: &TrueNasConfig) {
+    if truenas.replay_dir.is_some() {
+        return;
+    }
+    if !truenas.use_tls {
+        if truenas.tls != Default::default() {
+            report.add(
+                target,
+                "tls",
+                Status::Warn,
+                "[truenas.tls] is ignored because use_tls = false",
+            );
+        } else {
+            report.add(target, "tls", Status::Skip, "use_tls = false");
+        }
+        return;
+    }
+
+    if let Err(e) = tls::connector(truenas).and_then(|_| tls::pin(&truenas.tls)) {
+        report.add(target, "tls", Status::Fail, e.to_string());
+    } else if truenas.tls.pin_sha256.is_some() {
+        report.add(target, "tls", Status::Pass, "certificate pinned");
+    } else if !truenas.verify_ssl {
+        report.add(
+            target,
+            "tls",
+            Status::Warn,
+            "certificate verification is disabled (verify_ssl = false)",
+        );
+    } else {
+        report.add(target, "tls", Status::Pass, "certificate verified");
+    }
+}
+
+/// Credentials must be complete, and API keys only sent over TLS
+fn check_auth(report: &mut Report, target: &str, truenas: &TrueNasConfig) {
+    if truenas.replay_dir.is_some() {
+        return;
+    }
+    let login = match auth::credential_login(truenas) {
+        Ok(login) => login,
+        Err(e) => {
+            report.add(target, "auth", Status::Fail, e.to_string());
+            return;
+        }
+    };
+
+    if truenas.use_tls {
+        report.add(target, "auth", Status::Pass, login.credential);
+    } else if login.credential == "API key" {
+        // TrueNAS revokes an API key that arrives over an unencrypted connection
+        report.add(
+            target,
+            "auth",
+            Status::Fail,
+            "TrueNAS revokes API keys sent without TLS; set use_tls = true",
+        );
+    } else {
+        report.add(
+            target,
+            "auth",
+            Status::Warn,
+            format!("{} is sent without TLS", login.credential),
+        );
+    }
+}
+
+/// Hosts of a `[[truenas]]` list must be set and unique, probe modules need targets,
+/// and no connection timeout may be zero
+fn check_targets(report: &mut Report, config: &Config) {
+    match config.validate_targets() {
+        Ok(()) => report.add(
+            "-",
+            "targets",
+            Status::Pass,
+            format!(
+                "{} host(s), {} probe module(s)",
+                config.truenas.hosts().len(),
+                config.probe.modules.len()
+            ),
+        ),
+        Err(e) => report.add("-", "targets", Status::Fail, e.to_string()),
+    }
+}
+
+/// Collector names must be known, and something must be collected
+fn check_collectors(report: &mut Report, config: &Config) {
+    let metrics = &config.metrics;
+    if let Err(e) = metrics.validate_collectors() {
+        report.add("-", "collectors", Status::Fail, e.to_string());
+        return;
+    }
+
+    let known: Vec<&str> = collectors::registry::names().collect();
+    let enabled = known
+        .iter()
+        .filter(|name| collectors::registry::is_enabled(metrics, name))
+        .count();
+    if enabled == 0 {
+        report.add(
+            "-",
+            "collectors",
+            Status::Fail,
+            "every collector is disabled",
+        );
+    } else {
+        report.add(
+            "-",
+            "collectors",
+            Status::Pass,
+            format!("{} of {} enabled", enabled, known.len()),
+        );
+    }
+}
+
+/// Intervals and timeouts must not be zero
+fn check_intervals(report: &mut Report, config: &Config) {
+    let metrics = &config.metrics;
+    match metrics.validate_intervals() {
+        Ok(()) => report.add(
+            "-",
+            "intervals",
+            Status::Pass,
+            format!("every {}s", metrics.scrape_interval_seconds),
+        ),
+        Err(e) => report.add("-", "intervals", Status::Fail, e.to_string()),
+    }
+}
+
+/// Short duration for the detail column
+fn elapsed(duration: Duration) -> String {
+    format!("{} ms", duration.as_millis())
+}
@@ -9,6 +9,8 @@
 //!   - Labels: level, message, uuid, active
 
 use super::{collect_with_handler, CollectionContext, CollectionResult};
+use crate::metrics::MetricsCollector;
+use crate::truenas::types::TruenasAlert;
 use std::collections::HashMap;
 
 /// Collects alert metrics from TrueNAS
@@ -39,47 +41,55 @@ use std::collections::HashMap;
 /// ```
 pub async fn collect_alert_metrics(ctx: &CollectionContext<'_>) -> CollectionResult {
     collect_with_handler("alerts", ctx.client.query_alerts(), |alerts| {
-        // Initialize alert counts to 0 for all levels and statuses to ensure
-        // metrics reset if alerts are cleared.
-        // Pre-size for 4 levels × 2 states = 8 entries to reduce allocations
-        let mut alert_counts: HashMap<(String, bool), f64> = HashMap::with_capacity(8);
+        update_alert_metrics(ctx.metrics, alerts)
+    })
+    .await
+}
+
+/// Rebuilds alert metrics from the complete list of current alerts
+///
+/// Shared by the polling collector and the `alert.list` event subscription.
+pub fn update_alert_metrics(metrics: &MetricsCollector, alerts: Vec<TruenasAlert>) {
+    // Initialize alert counts to 0 for all levels and statuses to ensure
+    // metrics reset if alerts are cleared.
+    // Pre-size for 4 levels × 2 states = 8 entries to reduce allocations
+    let mut alert_counts: HashMap<(String, bool), f64> = HashMap::with_capacity(8);
+    let _updates = metrics.lock_updates();
 
-        // Reset detailed alert info metric
-        ctx.metrics.alert_info.reset();
+    // Reset detailed alert info metric
+    metrics.alert_info.reset();
 
-        let levels = ["CRITICAL", "ERROR", "WARNING", "INFO"];
-        let states = [true, false]; // Active, Dismissed
+    let levels = ["CRITICAL", "ERROR", "WARNING", "INFO"];
+    let states = [true, false]; // Active, Dismissed
 
-        for level in levels {
-            for state in states {
-                alert_counts.insert((level.to_string(), state), 0.0);
-            }
+    for level in levels {
+        for state in states {
+            alert_counts.insert((level.to_string(), state), 0.0);
         }
+    }
 
-        for alert in alerts {
-            let active = !alert.dismissed;
-            let key = (alert.level.clone(), active);
-            *alert_counts.entry(key).or_insert(0.0) += 1.0;
+    for alert in alerts {
+        let active = !alert.dismissed;
+        let key = (alert.level.clone(), active);
+        *alert_counts.entry(key).or_insert(0.0) += 1.0;
 
-            // Populate detailed alert info
-            ctx.metrics
-                .alert_info
-                .with_label_values(&[
-                    &alert.level,
-                    &alert.formatted,
-                    &alert.uuid,
-                    &(if active { "true" } else { "false" }).to_string(),
-                ])
-                .set(1.0);
-        }
+        // Populate detailed alert info
+        metrics
+            .alert_info
+            .with_label_values(&[
+                &alert.level,
+                &alert.formatted,
+                &alert.uuid,
+                &(if active { "true" } else { "false" }).to_string(),
+            ])
+            .set(1.0);
+    }
 
-        for ((level, active), count) in alert_counts {
-            let active_str = if active { "true" } else { "false" };
-            ctx.metrics
-                .alert_count
-                .with_label_values(&[level.as_str(), active_str])
-                .set(count);
-        }
-    })
-    .await
+    for ((level, active), count) in alert_counts {
+        let active_str = if active { "true" } else { "false" };
+        metrics
+            .alert_count
+            .with_label_values(&[level.as_str(), active_str])
+            .set(count);
+    }
 }
@@ -9,6 +9,8 @@
 //!   - Labels: app
 
 use super::{collect_with_handler, CollectionContext, CollectionResult};
+use crate::metrics::MetricsCollector;
+use crate::truenas::types::AppInfo;
 
 /// Collects application (app) metrics from TrueNAS
 ///
@@ -26,25 +28,39 @@ use super::{collect_with_handler, CollectionContext, CollectionResult};
 /// * `Err(_)` - Fatal error that should propagate
 pub async fn collect_app_metrics(ctx: &CollectionContext<'_>) -> CollectionResult {
     collect_with_handler("applications", ctx.client.query_apps(), |apps| {
-        for app in apps {
-            // 0 = stopped, 1 = running
-            let status_value = if app.state.to_uppercase() == "RUNNING" {
-                1
-            } else {
-                0
-            };
-            ctx.metrics
-                .app_status
-                .with_label_values(&[&app.name])
-                .set(status_value);
-
-            // Update available
-            let update_value = if app.update_available { 1 } else { 0 };
-            ctx.metrics
-                .app_update_available
-                .with_label_values(&[&app.name])
-                .set(update_value);
-        }
+        update_app_metrics(ctx.metrics, apps)
     })
     .await
 }
+
+/// Rebuilds application metrics from the complete list of apps
+///
+/// Shared by the polling collector and the `app.query` event subscription.
+/// Per-app series are reset first so that removed apps disappear.
+pub fn update_app_metrics(metrics: &MetricsCollector, apps: Vec<AppInfo>) {
+    let _updates = metrics.lock_updates();
+    metrics.app_status.reset();
+    metrics.app_cpu_percent.reset();
+    metrics.app_memory_bytes.reset();
+    metrics.app_update_available.reset();
+
+    for app in apps {
+        // 0 = stopped, 1 = running
+        let status_value = if app.state.to_uppercase() == "RUNNING" {
+            1
+        } else {
+            0
+        };
+        metrics
+            .app_status
+            .with_label_values(&[&app.name])
+            .set(status_value);
+
+        // Update available
+        let update_value = if app.update_available { 1 } else { 0 };
+        metrics
+            .app_update_available
+            .with_label_values(&[&app.name])
+            .set(update_value);
+    }
+}
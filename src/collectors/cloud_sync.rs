@@ -29,6 +29,7 @@ pub async fn collect_cloud_sync_metrics(ctx: &CollectionContext<'_>) -> Collecti
     match ctx.client.query_cloud_sync_tasks().await {
         Ok(tasks) => {
             // Reset metrics to clear stale state labels
+            let _updates = ctx.metrics.lock_updates();
             ctx.metrics.cloud_sync_status.reset();
             ctx.metrics.cloud_sync_progress.reset();
 
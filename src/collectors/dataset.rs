@@ -30,6 +30,13 @@ use super::{collect_with_handler, CollectionContext, CollectionResult};
 /// * `Err(_)` - Fatal error that should propagate
 pub async fn collect_dataset_metrics(ctx: &CollectionContext<'_>) -> CollectionResult {
     collect_with_handler("datasets", ctx.client.query_datasets(), |datasets| {
+        // Reset metrics so that deleted datasets disappear
+        let _updates = ctx.metrics.lock_updates();
+        ctx.metrics.dataset_used_bytes.reset();
+        ctx.metrics.dataset_available_bytes.reset();
+        ctx.metrics.dataset_compression_ratio.reset();
+        ctx.metrics.dataset_encrypted.reset();
+
         for dataset in datasets {
             let pool_name = dataset.name.split('/').next().unwrap_or(&dataset.name);
 
@@ -24,6 +24,9 @@ use super::{collect_with_handler, CollectionContext, CollectionResult};
 /// * `Err(_)` - Fatal error that should propagate
 pub async fn collect_disk_metrics(ctx: &CollectionContext<'_>) -> CollectionResult {
     collect_with_handler("disks", ctx.client.query_disks(), |disks| {
+        // Reset metrics so that removed or renamed disks disappear
+        let _updates = ctx.metrics.lock_updates();
+        ctx.metrics.disk_info.reset();
         for disk in disks {
             // Set disk info metric
             let size_str = disk.size.to_string();
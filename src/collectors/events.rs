@@ -0,0 +1,258 @@
+//! Event-Driven Collection
+//!
+//! Keeps alert, pool, service and app metrics current from TrueNAS push events instead of
+//! waiting for the next polling cycle.
+//!
+//! # How It Works
+//!
+//! 1. Each event collection is seeded with a full query and cached by primary key
+//! 2. `added` / `changed` / `removed` notifications are applied to the cache
+//! 3. The affected metrics are rebuilt from the cache after every change
+//!
+//! The caches are re-seeded whenever subscriptions are re-established after a reconnect,
+//! when the event channel overflows, and every `event_resync_interval_seconds` as a safety
+//! net against missed events.
+//!
+//! Like the polling collectors, collections the connected release does not offer (apps
+//! before 24.10) are neither subscribed to nor queried; the connection skips their
+//! subscriptions until a release offers them.
+
+use super::{alert, app, pool, registry, service};
+use crate::config::MetricsConfig;
+use crate::metrics::MetricsCollector;
+use crate::truenas::types::{CollectionUpdate, CollectionUpdateKind};
+use crate::truenas::{SubscriptionEvent, TrueNasClient};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+/// Collections that can be kept current through events, with their primary key field
+const EVENT_COLLECTIONS: &[(&str, &str)] = &[
+    ("alert.list", "uuid"),
+    ("pool.query", "id"),
+    ("service.query", "id"),
+    ("app.query", "id"),
+];
+
+/// Name of the collector whose metrics an event collection keeps current
+fn collector_name(collection: &str) -> &str {
+    match collection {
+        "alert.list" => "alert",
+        "pool.query" => "pool",
+        "service.query" => "service",
+        "app.query" => "app",
+        other => other,
+    }
+}
+
+/// Whether the connected release offers the API behind an event collection
+fn supported(client: &TrueNasClient, collection: &str) -> bool {
+    registry::capability(collector_name(collection))
+        .is_none_or(|capability| client.supports(capability))
+}
+
+/// Returns the event collections whose collectors are enabled by the metrics configuration
+pub fn event_collections(config: &MetricsConfig) -> Vec<&'static str> {
+    EVENT_COLLECTIONS
+        .iter()
+        .map(|(collection, _)| *collection)
+        .filter(|collection| registry::is_enabled(config, collector_name(collection)))
+        .collect()
+}
+
+/// Current contents of a subscribed collection, keyed by primary key
+#[derive(Debug, Clone)]
+pub struct EventCache {
+    key_field: &'static str,
+    items: BTreeMap<String, Value>,
+}
+
+impl EventCache {
+    /// Creates an empty cache for a collection whose objects are keyed by `key_field`
+    pub fn new(key_field: &'static str) -> Self {
+        Self {
+            key_field,
+            items: BTreeMap::new(),
+        }
+    }
+
+    /// Replaces the cache contents with the result of a full query
+    pub fn seed(&mut self, items: Vec<Value>) {
+        self.items = items
+            .into_iter()
+            .filter_map(|item| Some((key_string(item.get(self.key_field)?), item)))
+            .collect();
+    }
+
+    /// Applies a single change notification
+    pub fn apply(&mut self, update: &CollectionUpdate) {
+        let Some(key) = update.id.as_ref().map(key_string) else {
+            debug!("Ignoring {} event without an id", update.collection);
+            return;
+        };
+
+        match update.msg {
+            CollectionUpdateKind::Added => {
+                if let Some(fields) = &update.fields {
+                    self.items.insert(key, fields.clone());
+                }
+            }
+            CollectionUpdateKind::Changed => {
+                let Some(Value::Object(fields)) = &update.fields else {
+                    return;
+                };
+                match self.items.get_mut(&key) {
+                    Some(Value::Object(existing)) => {
+                        for (name, value) in fields {
+                            existing.insert(name.clone(), value.clone());
+                        }
+                    }
+                    _ => {
+                        self.items.insert(key, Value::Object(fields.clone()));
+                    }
+                }
+            }
+            CollectionUpdateKind::Removed => {
+                self.items.remove(&key);
+            }
+        }
+    }
+
+    /// Number of cached objects
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns true if the cache holds no objects
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Deserializes the cached objects, skipping any that do not parse
+    pub fn items<T: DeserializeOwned>(&self) -> Vec<T> {
+        self.items
+            .iter()
+            .filter_map(|(key, item)| match T::deserialize(item) {
+                Ok(item) => Some(item),
+                Err(e) => {
+                    warn!("Skipping unparseable event object {}: {}", key, e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+fn key_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Rebuilds the metrics owned by an event collection from its cache
+///
+/// Per-object series are reset first so that removed objects disappear.
+pub fn refresh_metrics(collection: &str, cache: &EventCache, metrics: &MetricsCollector) {
+    match collection {
+        "alert.list" => alert::update_alert_metrics(metrics, cache.items()),
+        "pool.query" => pool::update_pool_metrics(metrics, cache.items()),
+        "service.query" => service::update_service_metrics(metrics, cache.items()),
+        "app.query" => app::update_app_metrics(metrics, cache.items()),
+        other => debug!("No metrics registered for event collection {}", other),
+    }
+}
+
+/// Subscribes to the given collections and keeps their metrics current until the
+/// event channel closes
+pub async fn run(
+    client: Arc<TrueNasClient>,
+    metrics: MetricsCollector,
+    collections: Vec<&'static str>,
+    resync_interval: Duration,
+    stale_grace: Duration,
+) {
+    let mut caches: HashMap<&'static str, EventCache> = EVENT_COLLECTIONS
+        .iter()
+        .filter(|(collection, _)| collections.contains(collection))
+        .map(|(collection, key_field)| (*collection, EventCache::new(key_field)))
+        .collect();
+
+    // Listen before subscribing so the initial `Subscribed` event is not missed
+    let mut events = client.events();
+    for collection in &collections {
+        if let Err(e) = client.subscribe(collection).await {
+            warn!("Failed to subscribe to {}: {}", collection, e);
+        }
+    }
+    info!("Event subscriptions registered for {:?}", collections);
+
+    let mut resync = interval(resync_interval);
+    resync.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = resync.tick() => resync_all(&client, &metrics, &mut caches, stale_grace).await,
+            event = events.recv() => match event {
+                Ok(SubscriptionEvent::Update(update)) => {
+                    if let Some(cache) = caches.get_mut(update.collection.as_str()) {
+                        cache.apply(&update);
+                        refresh_metrics(&update.collection, cache, &metrics);
+                        metrics.publish(stale_grace);
+                    }
+                }
+                Ok(SubscriptionEvent::Subscribed) => {
+                    resync_all(&client, &metrics, &mut caches, stale_grace).await;
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Missed {} TrueNAS events, resyncing", missed);
+                    resync_all(&client, &metrics, &mut caches, stale_grace).await;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+/// Re-seeds every cache from a full query and publishes the result; failed queries keep
+/// the previous contents
+async fn resync_all(
+    client: &TrueNasClient,
+    metrics: &MetricsCollector,
+    caches: &mut HashMap<&'static str, EventCache>,
+    stale_grace: Duration,
+) {
+    // Connect first so the release, and with it the collections it offers, is known
+    if let Err(e) = client.connect().await {
+        debug!("Connecting before the resync failed: {}", e);
+    }
+    for (collection, cache) in caches.iter_mut() {
+        if !supported(client, collection) {
+            debug!(
+                "Skipping resync of {}: not offered by this TrueNAS release",
+                collection
+            );
+            continue;
+        }
+        let started = Instant::now();
+        let result = client.query_collection(collection).await;
+        metrics.record_collector_run(
+            collector_name(collection),
+            result.is_ok(),
+            started.elapsed(),
+        );
+        match result {
+            Ok(items) => {
+                cache.seed(items);
+                refresh_metrics(collection, cache, metrics);
+                debug!("Resynced {} ({} objects)", collection, cache.len());
+            }
+            Err(e) => warn!("Failed to resync {}: {}", collection, e),
+        }
+    }
+    metrics.publish(stale_grace);
+}
@@ -6,7 +6,8 @@
 //!
 //! # Architecture
 //!
-//! Collectors follow a consistent pattern:
+//! Each collector is registered in [`registry::Registry`] under a name that can be
+//! enabled or disabled in `[metrics.collectors]`. Collectors follow a consistent pattern:
 //! - Accept a `CollectionContext` containing shared state
 //! - Query the TrueNAS API
 //! - Update Prometheus metrics using helper methods
@@ -16,10 +17,21 @@
 //!
 //! Individual collector failures are non-fatal - they log warnings and return Ok(false).
 //! This ensures partial metrics are still exposed even if some APIs are unavailable.
+//!
+//! # Concurrency
+//!
+//! A cycle's collectors are run together by [`run_collectors`], at most
+//! `max_concurrent_collectors` at a time. Each one is bounded by
+//! `collector_timeout_seconds`; a collector that runs over is marked failed and
+//! does not hold up the others. Each run's outcome and duration are returned so they
+//! can be exported as `truenas_exporter_collector_*` self-metrics.
 
 use crate::config::MetricsConfig;
 use crate::metrics::MetricsCollector;
 use crate::truenas::TrueNasClient;
+use futures_util::future::{BoxFuture, FutureExt};
+use futures_util::stream::{self, StreamExt};
+use std::time::{Duration, Instant};
 use tracing::{info, warn};
 
 /// Shared context passed to all collectors
@@ -103,6 +115,47 @@ where
     }
 }
 
+/// A collector queued for a cycle, with the name used in logs
+pub type NamedCollector<'a> = (&'static str, BoxFuture<'a, CollectionResult>);
+
+/// Outcome of a collector run: name, result and how long it took
+pub type CollectorRun = (&'static str, CollectionResult, Duration);
+
+/// Run collectors concurrently, each bounded by `timeout`
+///
+/// At most `limit` collectors run at the same time. Results are returned in the order
+/// collectors finish; a collector that times out is reported as
+/// `CollectionStatus::Failed`.
+pub fn run_collectors<'a>(
+    collectors: Vec<NamedCollector<'a>>,
+    limit: usize,
+    timeout: Duration,
+) -> BoxFuture<'a, Vec<CollectorRun>> {
+    // Boxed so the stream's closure types don't leak into the caller's `Send` check
+    stream::iter(collectors)
+        .map(move |collector| run_collector(collector, timeout))
+        .buffer_unordered(limit.max(1))
+        .collect()
+        .boxed()
+}
+
+/// Run one collector, turning a timeout into `CollectionStatus::Failed`
+async fn run_collector((name, collector): NamedCollector<'_>, timeout: Duration) -> CollectorRun {
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, collector).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!(
+                "{} collector did not finish within {}s",
+                name,
+                timeout.as_secs()
+            );
+            Ok(CollectionStatus::Failed)
+        }
+    };
+    (name, result, started.elapsed())
+}
+
 // Collector modules
 pub mod alert;
 pub mod app;
@@ -110,10 +163,13 @@ pub mod boot_pool;
 pub mod cloud_sync;
 pub mod dataset;
 pub mod disk;
+pub mod events;
 pub mod iscsi;
 pub mod network_interface;
 pub mod nfs;
 pub mod pool;
+pub mod registry;
+pub mod schedule;
 pub mod service;
 pub mod share;
 pub mod smart;
@@ -132,6 +188,7 @@ pub use iscsi::collect_iscsi_client_count;
 pub use network_interface::collect_network_interface_metrics;
 pub use nfs::collect_nfs_metrics;
 pub use pool::collect_pool_metrics;
+pub use registry::{Collector, Registry};
 pub use service::collect_service_metrics;
 pub use share::collect_share_metrics;
 pub use smart::collect_smart_metrics;
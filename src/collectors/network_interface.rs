@@ -27,6 +27,8 @@ pub async fn collect_network_interface_metrics(ctx: &CollectionContext<'_>) -> C
         "network interfaces",
         ctx.client.query_network_interfaces(),
         |interfaces| {
+            let _updates = ctx.metrics.lock_updates();
+            ctx.metrics.network_interface_info.reset();
             for iface in interfaces {
                 let link_state = &iface.state.link_state;
                 ctx.metrics
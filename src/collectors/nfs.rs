@@ -12,6 +12,7 @@ pub async fn collect_nfs_metrics(ctx: &CollectionContext<'_>) -> CollectionResul
     let v4_ok = v4_res.is_ok();
     let v3_ok = v3_res.is_ok();
 
+    let _updates = ctx.metrics.lock_updates();
     ctx.metrics.nfs_client_info.reset();
     ctx.metrics.nfs_client_seconds_since_renew.reset();
 
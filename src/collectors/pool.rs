@@ -20,7 +20,7 @@
 
 use super::{CollectionContext, CollectionResult, CollectionStatus};
 use crate::metrics::MetricsCollector;
-use crate::truenas::types::VDev;
+use crate::truenas::types::{Pool, VDev};
 use serde_json;
 use tracing::{info, warn};
 
@@ -78,70 +78,83 @@ fn collect_vdev_stats(pool_name: &str, vdev: &VDev, metrics: &MetricsCollector)
 pub async fn collect_pool_metrics(ctx: &CollectionContext<'_>) -> CollectionResult {
     match ctx.client.query_pools().await {
         Ok(pools) => {
-            for pool in pools {
-                let health_value = if pool.healthy { 1.0 } else { 0.0 };
+            update_pool_metrics(ctx.metrics, pools);
+            Ok(CollectionStatus::Success)
+        }
+        Err(e) => {
+            warn!("Failed to query pools: {}", e);
+            Ok(CollectionStatus::Failed)
+        }
+    }
+}
 
-                ctx.metrics
-                    .pool_health
-                    .with_label_values(&[&pool.name, &pool.status])
-                    .set(health_value);
+/// Rebuilds pool metrics from the complete list of pools
+///
+/// Shared by the polling collector and the `pool.query` event subscription.
+/// Per-pool series are reset first so that removed pools and vdevs disappear.
+pub fn update_pool_metrics(metrics: &MetricsCollector, pools: Vec<Pool>) {
+    let _updates = metrics.lock_updates();
+    metrics.pool_health.reset();
+    metrics.pool_capacity_bytes.reset();
+    metrics.pool_allocated_bytes.reset();
+    metrics.pool_free_bytes.reset();
+    metrics.pool_last_scrub_seconds.reset();
+    metrics.pool_scrub_errors.reset();
+    metrics.pool_vdev_error_count.reset();
 
-                ctx.metrics.set_gauge(
-                    &ctx.metrics.pool_capacity_bytes,
-                    &[&pool.name],
-                    pool.size as f64,
-                );
+    for pool in pools {
+        let health_value = if pool.healthy { 1.0 } else { 0.0 };
 
-                ctx.metrics.set_gauge(
-                    &ctx.metrics.pool_allocated_bytes,
-                    &[&pool.name],
-                    pool.allocated as f64,
-                );
+        metrics
+            .pool_health
+            .with_label_values(&[&pool.name, &pool.status])
+            .set(health_value);
 
-                ctx.metrics.set_gauge(
-                    &ctx.metrics.pool_free_bytes,
-                    &[&pool.name],
-                    pool.free as f64,
-                );
+        metrics.set_gauge(
+            &metrics.pool_capacity_bytes,
+            &[&pool.name],
+            pool.size as f64,
+        );
 
-                // Collect Scan Stats (Errors & Last Scrub)
-                if let Some(scan) = &pool.scan {
-                    ctx.metrics.set_gauge(
-                        &ctx.metrics.pool_scrub_errors,
-                        &[&pool.name],
-                        scan.errors.unwrap_or_default() as f64,
-                    );
+        metrics.set_gauge(
+            &metrics.pool_allocated_bytes,
+            &[&pool.name],
+            pool.allocated as f64,
+        );
 
-                    if let Some(serde_json::Value::Object(map)) = &scan.end_time {
-                        if let Some(serde_json::Value::Number(num)) = map.get("$date") {
-                            if let Some(millis) = num.as_u64() {
-                                ctx.metrics.set_gauge(
-                                    &ctx.metrics.pool_last_scrub_seconds,
-                                    &[&pool.name],
-                                    (millis / 1000) as f64,
-                                );
-                            }
-                        }
-                    }
-                }
+        metrics.set_gauge(&metrics.pool_free_bytes, &[&pool.name], pool.free as f64);
+
+        // Collect Scan Stats (Errors & Last Scrub)
+        if let Some(scan) = &pool.scan {
+            metrics.set_gauge(
+                &metrics.pool_scrub_errors,
+                &[&pool.name],
+                scan.errors.unwrap_or_default() as f64,
+            );
 
-                // Collect VDev Errors (Recursive)
-                if let Some(topology) = &pool.topology {
-                    for vdev in &topology.data {
-                        collect_vdev_stats(&pool.name, vdev, ctx.metrics);
+            if let Some(serde_json::Value::Object(map)) = &scan.end_time {
+                if let Some(serde_json::Value::Number(num)) = map.get("$date") {
+                    if let Some(millis) = num.as_u64() {
+                        metrics.set_gauge(
+                            &metrics.pool_last_scrub_seconds,
+                            &[&pool.name],
+                            (millis / 1000) as f64,
+                        );
                     }
                 }
-
-                info!(
-                    "Updated metrics for pool: {} (status: {}, healthy: {})",
-                    pool.name, pool.status, pool.healthy
-                );
             }
-            Ok(CollectionStatus::Success)
         }
-        Err(e) => {
-            warn!("Failed to query pools: {}", e);
-            Ok(CollectionStatus::Failed)
+
+        // Collect VDev Errors (Recursive)
+        if let Some(topology) = &pool.topology {
+            for vdev in &topology.data {
+                collect_vdev_stats(&pool.name, vdev, metrics);
+            }
         }
+
+        info!(
+            "Updated metrics for pool: {} (status: {}, healthy: {})",
+            pool.name, pool.status, pool.healthy
+        );
     }
 }
@@ -0,0 +1,305 @@
+//! Collector Trait and Registry
+//!
+//! Every collector implements [`Collector`] and is listed in a [`Registry`] built from
+//! the metrics configuration. The collection loop only asks the registry which
+//! collectors are enabled and when they are due; it no longer knows about individual
+//! collectors.
+//!
+//! # Enabling and Disabling
+//!
+//! Collectors are switched on or off by name in `[metrics.collectors]`:
+//!
+//! ```toml
+//! [metrics.collectors]
+//! smart = false
+//! iscsi = false
+//! ```
+//!
+//! Collectors not listed there are enabled, except that `collect_pool_metrics = false`
+//! still disables `pool` and `dataset`, and `collect_system_metrics = false` disables
+//! `system_info` and `system_reporting`. An explicit entry always wins.
+
+use super::{CollectionContext, CollectionResult};
+use crate::config::MetricsConfig;
+use crate::truenas::Capability;
+use futures_util::future::{BoxFuture, FutureExt};
+use std::time::Duration;
+
+/// A source of metrics that can be scheduled by the collection loop
+pub trait Collector: Send + Sync {
+    /// Name used in `[metrics.collectors]`, `[metrics.intervals]` and logs
+    fn name(&self) -> &'static str;
+
+    /// Whether the collector should run at all
+    fn enabled(&self) -> bool;
+
+    /// How often the collector should run
+    fn interval(&self) -> Duration;
+
+    /// API the connected release must offer for the collector to run
+    fn capability(&self) -> Option<Capability> {
+        None
+    }
+
+    /// True if push events keep these metrics current when `subscribe_events` is on
+    fn event_driven(&self) -> bool {
+        false
+    }
+
+    /// Query TrueNAS and update the collector's metrics
+    fn collect<'a>(&'a self, ctx: &'a CollectionContext<'a>) -> BoxFuture<'a, CollectionResult>;
+}
+
+/// Signature of the built-in collector functions, boxed
+type CollectFn = for<'a> fn(&'a CollectionContext<'a>) -> BoxFuture<'a, CollectionResult>;
+
+/// Static description of a built-in collector
+struct Builtin {
+    name: &'static str,
+    description: &'static str,
+    capability: Option<Capability>,
+    event_driven: bool,
+    collect: CollectFn,
+}
+
+const BUILTINS: [Builtin; 16] = [
+    Builtin {
+        name: "pool",
+        description: "Pool health, capacity, scrubs and vdev errors",
+        capability: None,
+        event_driven: true,
+        collect: |ctx| super::collect_pool_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "dataset",
+        description: "Dataset usage, compression and encryption",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_dataset_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "share",
+        description: "SMB and NFS share status",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_share_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "cloud_sync",
+        description: "Cloud sync task status and progress",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_cloud_sync_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "snapshot",
+        description: "Snapshot task status",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_snapshot_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "alert",
+        description: "Active alerts by level",
+        capability: None,
+        event_driven: true,
+        collect: |ctx| super::collect_alert_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "system_info",
+        description: "Version, uptime and memory",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_system_info_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "system_reporting",
+        description: "CPU, memory, network and disk I/O from the reporting API",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_system_reporting_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "disk",
+        description: "Disk inventory",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_disk_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "smart",
+        description: "SMART test results and power-on hours",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_smart_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "app",
+        description: "App status, resource usage and updates",
+        capability: Some(Capability::Apps),
+        event_driven: true,
+        collect: |ctx| super::collect_app_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "network_interface",
+        description: "Network interface link state",
+        capability: None,
+        event_driven: false,
+        collect: |ctx| super::collect_network_interface_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "service",
+        description: "Service running state",
+        capability: None,
+        event_driven: true,
+        collect: |ctx| super::collect_service_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "boot_pool",
+        description: "Boot pool health, usage and scrubs",
+        capability: Some(Capability::BootPoolState),
+        event_driven: false,
+        collect: |ctx| super::collect_boot_pool_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "nfs",
+        description: "Connected NFS clients",
+        capability: Some(Capability::NfsClients),
+        event_driven: false,
+        collect: |ctx| super::collect_nfs_metrics(ctx).boxed(),
+    },
+    Builtin {
+        name: "iscsi",
+        description: "Connected iSCSI initiators",
+        capability: Some(Capability::IscsiClients),
+        event_driven: false,
+        collect: |ctx| super::collect_iscsi_client_count(ctx).boxed(),
+    },
+];
+
+/// Names of all built-in collectors, in the order they run
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|builtin| builtin.name)
+}
+
+/// API the named built-in collector needs, `None` if it runs on every release
+pub fn capability(name: &str) -> Option<Capability> {
+    BUILTINS
+        .iter()
+        .find(|builtin| builtin.name == name)
+        .and_then(|builtin| builtin.capability)
+}
+
+/// One line per built-in collector, for `--help`
+pub fn help() -> String {
+    let mut help = String::from("Collectors (enable or disable in [metrics.collectors]):\n");
+    for builtin in &BUILTINS {
+        help.push_str(&format!("  {:<18} {}\n", builtin.name, builtin.description));
+    }
+    help
+}
+
+/// Whether the configuration enables the named collector
+pub fn is_enabled(config: &MetricsConfig, name: &str) -> bool {
+    if let Some(enabled) = config.collectors.get(name) {
+        return *enabled;
+    }
+    match name {
+        "pool" | "dataset" => config.collect_pool_metrics,
+        "system_info" | "system_reporting" => config.collect_system_metrics,
+        _ => true,
+    }
+}
+
+/// A built-in collector with its configured settings
+struct BuiltinCollector {
+    builtin: &'static Builtin,
+    enabled: bool,
+    interval: Duration,
+}
+
+impl Collector for BuiltinCollector {
+    fn name(&self) -> &'static str {
+        self.builtin.name
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn capability(&self) -> Option<Capability> {
+        self.builtin.capability
+    }
+
+    fn event_driven(&self) -> bool {
+        self.builtin.event_driven
+    }
+
+    fn collect<'a>(&'a self, ctx: &'a CollectionContext<'a>) -> BoxFuture<'a, CollectionResult> {
+        (self.builtin.collect)(ctx)
+    }
+}
+
+/// The collectors known to the exporter
+pub struct Registry {
+    collectors: Vec<Box<dyn Collector>>,
+}
+
+impl Registry {
+    /// Registry of the built-in collectors, configured from `config`
+    pub fn new(config: &MetricsConfig) -> Self {
+        let collectors = BUILTINS
+            .iter()
+            .map(|builtin| {
+                Box::new(BuiltinCollector {
+                    builtin,
+                    enabled: is_enabled(config, builtin.name),
+                    interval: Duration::from_secs(
+                        config
+                            .intervals
+                            .get(builtin.name)
+                            .copied()
+                            .unwrap_or(config.scrape_interval_seconds),
+                    ),
+                }) as Box<dyn Collector>
+            })
+            .collect();
+
+        Self { collectors }
+    }
+
+    /// Add a collector; it runs after the built-in ones
+    pub fn register(&mut self, collector: Box<dyn Collector>) {
+        self.collectors.push(collector);
+    }
+
+    /// All registered collectors, enabled or not
+    pub fn all(&self) -> impl Iterator<Item = &dyn Collector> {
+        self.collectors.iter().map(|collector| collector.as_ref())
+    }
+
+    /// The collectors that are switched on
+    pub fn enabled(&self) -> impl Iterator<Item = &dyn Collector> {
+        self.all().filter(|collector| collector.enabled())
+    }
+
+    /// Look up a collector by name
+    pub fn get(&self, name: &str) -> Option<&dyn Collector> {
+        self.all().find(|collector| collector.name() == name)
+    }
+}
+
+impl std::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(
+                self.all()
+                    .map(|collector| (collector.name(), collector.enabled())),
+            )
+            .finish()
+    }
+}
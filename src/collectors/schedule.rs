@@ -0,0 +1,96 @@
+//! Per-Collector Scheduling
+//!
+//! Each collector runs on its own interval: `[metrics.intervals]` overrides
+//! `scrape_interval_seconds` by collector name, so pool state can refresh every 30s while
+//! SMART results are fetched once a day. The collection loop wakes up whenever the next
+//! collector is due and runs only the collectors that are due at that moment. When no
+//! collector is scheduled at all, e.g. because every one is disabled or kept current by
+//! events, it wakes up once per `scrape_interval_seconds`.
+//!
+//! With `interval_jitter_seconds` set, each run is pushed back by a random delay of up
+//! to that many seconds, so collectors with equal intervals drift apart instead of
+//! always hitting TrueNAS in the same instant.
+//!
+//! Only a successful run schedules a collector a full interval ahead. One that failed,
+//! timed out or was skipped because TrueNAS is down is retried after
+//! `scrape_interval_seconds` (or its own interval, if shorter), so a daily SMART
+//! collector recovers minutes after an outage rather than a day later.
+
+use crate::config::MetricsConfig;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// When each collector is due next
+#[derive(Debug)]
+pub struct Schedule {
+    default_interval: Duration,
+    intervals: HashMap<String, Duration>,
+    jitter: Duration,
+    next_due: HashMap<&'static str, Instant>,
+}
+
+impl Schedule {
+    pub fn new(config: &MetricsConfig) -> Self {
+        Self {
+            default_interval: Duration::from_secs(config.scrape_interval_seconds),
+            intervals: config
+                .intervals
+                .iter()
+                .map(|(name, seconds)| (name.clone(), Duration::from_secs(*seconds)))
+                .collect(),
+            jitter: Duration::from_secs(config.interval_jitter_seconds),
+            next_due: HashMap::new(),
+        }
+    }
+
+    /// Override the interval of a collector, e.g. with [`Collector::interval`]
+    ///
+    /// [`Collector::interval`]: super::Collector::interval
+    pub fn set_interval(&mut self, name: &str, interval: Duration) {
+        self.intervals.insert(name.to_string(), interval);
+    }
+
+    /// Configured interval for a collector
+    pub fn interval(&self, name: &str) -> Duration {
+        self.intervals
+            .get(name)
+            .copied()
+            .unwrap_or(self.default_interval)
+    }
+
+    /// True if the collector has never run or its next run time has come
+    pub fn is_due(&self, name: &str, now: Instant) -> bool {
+        self.next_due.get(name).is_none_or(|due| *due <= now)
+    }
+
+    /// Record that a collector ran successfully and schedule its next run
+    pub fn mark_run(&mut self, name: &'static str, now: Instant) {
+        let jitter = self.jitter.mul_f64(fastrand::f64());
+        self.next_due
+            .insert(name, now + self.interval(name) + jitter);
+    }
+
+    /// Record that a collector failed or did not run, and schedule a retry
+    pub fn mark_failed(&mut self, name: &'static str, now: Instant) {
+        let retry = self.interval(name).min(self.default_interval);
+        self.next_due.insert(name, now + retry);
+    }
+
+    /// Schedule a retry for every due collector without running it
+    pub fn postpone_due(&mut self, now: Instant) {
+        let due: Vec<&'static str> = self
+            .next_due
+            .iter()
+            .filter(|(_, due)| **due <= now)
+            .map(|(name, _)| *name)
+            .collect();
+        for name in due {
+            self.mark_failed(name, now);
+        }
+    }
+
+    /// When the next scheduled collector is due, `None` if no collector has run yet
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        self.next_due.values().min().copied()
+    }
+}
@@ -7,6 +7,8 @@
 //!   - Labels: service
 
 use super::{collect_with_handler, CollectionContext, CollectionResult};
+use crate::metrics::MetricsCollector;
+use crate::truenas::types::ServiceInfo;
 
 /// Collects system service status metrics from TrueNAS
 ///
@@ -24,17 +26,27 @@ use super::{collect_with_handler, CollectionContext, CollectionResult};
 /// * `Err(_)` - Fatal error that should propagate
 pub async fn collect_service_metrics(ctx: &CollectionContext<'_>) -> CollectionResult {
     collect_with_handler("services", ctx.client.query_services(), |services| {
-        for service in services {
-            let status_value = if service.state.to_uppercase() == "RUNNING" {
-                1
-            } else {
-                0
-            };
-            ctx.metrics
-                .service_status
-                .with_label_values(&[&service.service])
-                .set(status_value);
-        }
+        update_service_metrics(ctx.metrics, services)
     })
     .await
 }
+
+/// Rebuilds service status metrics from the complete list of services
+///
+/// Shared by the polling collector and the `service.query` event subscription.
+pub fn update_service_metrics(metrics: &MetricsCollector, services: Vec<ServiceInfo>) {
+    let _updates = metrics.lock_updates();
+    metrics.service_status.reset();
+
+    for service in services {
+        let status_value = if service.state.to_uppercase() == "RUNNING" {
+            1
+        } else {
+            0
+        };
+        metrics
+            .service_status
+            .with_label_values(&[&service.service])
+            .set(status_value);
+    }
+}
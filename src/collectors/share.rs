@@ -32,6 +32,8 @@ pub async fn collect_share_metrics(ctx: &CollectionContext<'_>) -> CollectionRes
     match ctx.client.query_smb_shares().await {
         Ok(shares) => {
             any_success = true;
+            let _updates = ctx.metrics.lock_updates();
+            ctx.metrics.share_smb_enabled.reset();
             for share in shares {
                 ctx.metrics.set_bool_metric(
                     &ctx.metrics.share_smb_enabled,
@@ -47,6 +49,8 @@ pub async fn collect_share_metrics(ctx: &CollectionContext<'_>) -> CollectionRes
     match ctx.client.query_nfs_shares().await {
         Ok(shares) => {
             any_success = true;
+            let _updates = ctx.metrics.lock_updates();
+            ctx.metrics.share_nfs_enabled.reset();
             for share in shares {
                 ctx.metrics.set_bool_metric(
                     &ctx.metrics.share_nfs_enabled,
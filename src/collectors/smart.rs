@@ -41,6 +41,13 @@ use tracing::{info, warn};
 pub async fn collect_smart_metrics(ctx: &CollectionContext<'_>) -> CollectionResult {
     match ctx.client.query_smart_tests().await {
         Ok(disks) => {
+            // Reset metrics so that removed disks and old test types disappear
+            let _updates = ctx.metrics.lock_updates();
+            ctx.metrics.smart_test_status.reset();
+            ctx.metrics.smart_test_lifetime_hours.reset();
+            ctx.metrics.smart_test_timestamp_seconds.reset();
+            ctx.metrics.disk_power_on_hours.reset();
+
             for disk in disks {
                 let disk_name = disk.name.clone();
 
@@ -28,6 +28,7 @@ pub async fn collect_snapshot_metrics(ctx: &CollectionContext<'_>) -> Collection
     match ctx.client.query_snapshot_tasks().await {
         Ok(tasks) => {
             // Reset metric to clear stale state labels (e.g., RUNNING -> FINISHED transitions)
+            let _updates = ctx.metrics.lock_updates();
             ctx.metrics.snapshot_task_status.reset();
 
             for task in tasks {
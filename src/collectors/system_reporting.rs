@@ -123,6 +123,17 @@ pub async fn collect_system_reporting_metrics(ctx: &CollectionContext<'_>) -> Co
             if !queries.is_empty() {
                 match ctx.client.query_reporting_data(queries, None).await {
                     Ok(results) => {
+                        // Reset metrics so that removed disks and interfaces disappear
+                        let _updates = ctx.metrics.lock_updates();
+                        ctx.metrics.system_cpu_usage_percent.reset();
+                        ctx.metrics.system_cpu_temperature_celsius.reset();
+                        ctx.metrics.system_memory_bytes.reset();
+                        ctx.metrics.disk_temperature_celsius.reset();
+                        ctx.metrics.disk_read_bytes_per_second.reset();
+                        ctx.metrics.disk_write_bytes_per_second.reset();
+                        ctx.metrics.network_receive_bytes_per_second.reset();
+                        ctx.metrics.network_transmit_bytes_per_second.reset();
+
                         for res in results {
                             if let Some(last_point) = res.data.last() {
                                 match res.name.as_str() {
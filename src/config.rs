@@ -1,43 +1,526 @@
 use anyhow::{Context, Result};
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
-    pub truenas: TrueNasConfig,
+    /// The systems served on `/metrics`; without a host only `/probe` collects
+    #[serde(default)]
+    pub truenas: TrueNasHosts,
     pub server: ServerConfig,
     pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub probe: ProbeConfig,
+}
+
+/// The TrueNAS systems served on `/metrics`
+///
+/// Either a single `[truenas]` table, or a `[[truenas]]` list of systems that are
+/// collected in parallel and told apart by a `host` label on every series.
+// Exists once per process, so the size of the single-host variant does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum TrueNasHosts {
+    Single(TrueNasConfig),
+    Multiple(Vec<TrueNasConfig>),
+}
+
+impl TrueNasHosts {
+    /// Settings of every configured system
+    pub fn hosts(&self) -> &[TrueNasConfig] {
+        match self {
+            Self::Single(config) => std::slice::from_ref(config),
+            Self::Multiple(configs) => configs,
+        }
+    }
+
+    /// Whether series are labelled with the `host` they came from
+    pub fn labelled(&self) -> bool {
+        matches!(self, Self::Multiple(_))
+    }
+}
+
+// Not `#[serde(untagged)]`, so that errors inside a table are reported as they are
+// instead of as "did not match any variant"
+impl<'de> Deserialize<'de> for TrueNasHosts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct HostsVisitor;
+
+        impl<'de> Visitor<'de> for HostsVisitor {
+            type Value = TrueNasHosts;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a [truenas] table or a [[truenas]] list")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                TrueNasConfig::deserialize(MapAccessDeserializer::new(map))
+                    .map(TrueNasHosts::Single)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(seq)).map(TrueNasHosts::Multiple)
+            }
+        }
+
+        deserializer.deserialize_any(HostsVisitor)
+    }
+}
+
+impl Default for TrueNasHosts {
+    fn default() -> Self {
+        Self::Single(TrueNasConfig::default())
+    }
+}
+
+impl From<TrueNasConfig> for TrueNasHosts {
+    fn from(config: TrueNasConfig) -> Self {
+        Self::Single(config)
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct TrueNasConfig {
+    #[serde(default)]
     pub host: String,
+    #[serde(default = "default_api_key")]
     pub api_key: SecretString,
     #[serde(default = "default_use_tls")]
     pub use_tls: bool,
     #[serde(default = "default_verify_ssl")]
     #[allow(dead_code)]
     pub verify_ssl: bool,
+    #[serde(default)]
+    pub protocol: ProtocolMode,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    /// Write every API call and response to fixture files in this directory
+    #[serde(default)]
+    pub record_dir: Option<PathBuf>,
+    /// Answer API calls from fixture files in this directory instead of connecting
+    #[serde(default)]
+    pub replay_dir: Option<PathBuf>,
+}
+
+// Secrets have no `PartialEq`; configurations are compared on reload to decide whether
+// the connection has to be rebuilt
+impl PartialEq for TrueNasConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.host == other.host
+            && self.api_key.expose_secret() == other.api_key.expose_secret()
+            && self.use_tls == other.use_tls
+            && self.verify_ssl == other.verify_ssl
+            && self.protocol == other.protocol
+            && self.tls == other.tls
+            && self.auth == other.auth
+            && self.timeouts == other.timeouts
+            && self.reconnect == other.reconnect
+            && self.record_dir == other.record_dir
+            && self.replay_dir == other.replay_dir
+    }
+}
+
+impl Default for TrueNasConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            api_key: default_api_key(),
+            use_tls: default_use_tls(),
+            verify_ssl: default_verify_ssl(),
+            protocol: ProtocolMode::default(),
+            tls: TlsConfig::default(),
+            auth: AuthConfig::default(),
+            timeouts: TimeoutConfig::default(),
+            reconnect: ReconnectConfig::default(),
+            record_dir: None,
+            replay_dir: None,
+        }
+    }
+}
+
+/// TLS settings for the TrueNAS connection (`[truenas.tls]`)
+///
+/// Only used when `use_tls` is enabled.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct TlsConfig {
+    /// PEM bundle of CA certificates to trust in addition to the system roots
+    pub ca_file: Option<PathBuf>,
+    /// Name sent as SNI and checked against the certificate, if it differs from `host`
+    pub server_name: Option<String>,
+    /// PEM client certificate for mutual TLS (requires `client_key`)
+    pub client_cert: Option<PathBuf>,
+    /// PEM (PKCS#8) private key for `client_cert`
+    pub client_key: Option<PathBuf>,
+    /// SHA-256 fingerprint of the server certificate, hex with optional colons
+    ///
+    /// When set, the certificate must match this fingerprint and CA/hostname
+    /// verification is skipped, which allows self-signed certificates to be trusted
+    /// without disabling verification altogether.
+    pub pin_sha256: Option<String>,
+}
+
+/// Timeouts and keepalive for the TrueNAS connection (`[truenas.timeouts]`)
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct TimeoutConfig {
+    /// Opening the socket, including TLS and the DDP handshake
+    #[serde(default = "default_connect_timeout")]
+    pub connect_seconds: u64,
+    /// Waiting for the response to a single API call
+    #[serde(default = "default_call_timeout")]
+    pub call_seconds: u64,
+    /// A whole collection cycle; defaults to the scrape interval
+    #[serde(default)]
+    pub cycle_seconds: Option<u64>,
+    /// How often to ping an idle connection (0 disables keepalive)
+    #[serde(default = "default_keepalive_interval")]
+    pub keepalive_interval_seconds: u64,
+    /// How long to wait for any frame after a ping before dropping the connection
+    #[serde(default = "default_keepalive_timeout")]
+    pub keepalive_timeout_seconds: u64,
 }
 
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect_seconds: default_connect_timeout(),
+            call_seconds: default_call_timeout(),
+            cycle_seconds: None,
+            keepalive_interval_seconds: default_keepalive_interval(),
+            keepalive_timeout_seconds: default_keepalive_timeout(),
+        }
+    }
+}
+
+impl TimeoutConfig {
+    /// A zero timeout would fail every connection or call at once
+    pub fn validate(&self, owner: &str) -> Result<()> {
+        let mut zero = Vec::new();
+        if self.connect_seconds == 0 {
+            zero.push("connect_seconds");
+        }
+        if self.call_seconds == 0 {
+            zero.push("call_seconds");
+        }
+        if self.keepalive_timeout_seconds == 0 {
+            zero.push("keepalive_timeout_seconds");
+        }
+        if !zero.is_empty() {
+            anyhow::bail!(
+                "{}: timeouts.{} must be greater than 0",
+                owner,
+                zero.join(", timeouts.")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Reconnect backoff and circuit breaker (`[truenas.reconnect]`)
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ReconnectConfig {
+    /// Delay after the first failed connection attempt
+    #[serde(default = "default_initial_backoff")]
+    pub initial_backoff_seconds: u64,
+    /// Upper bound for the doubling delay
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff_seconds: u64,
+    /// Consecutive failures after which whole collection cycles are skipped
+    #[serde(default = "default_circuit_breaker_threshold")]
+    pub circuit_breaker_threshold: u32,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff_seconds: default_initial_backoff(),
+            max_backoff_seconds: default_max_backoff(),
+            circuit_breaker_threshold: default_circuit_breaker_threshold(),
+        }
+    }
+}
+
+/// How the exporter authenticates to TrueNAS (`[truenas.auth]`)
 #[derive(Debug, Deserialize, Clone)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub method: AuthMethod,
+    /// User for `password` auth, and for API keys when `login_ex` is enabled
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<SecretString>,
+    /// Log in through `auth.login_ex` instead of the older per-mechanism methods
+    #[serde(default)]
+    pub login_ex: bool,
+    /// Lifetime requested from `auth.generate_token` for the `token` method
+    #[serde(default = "default_token_ttl")]
+    pub token_ttl_seconds: u64,
+}
+
+impl PartialEq for AuthConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.method == other.method
+            && self.username == other.username
+            && self.password.as_ref().map(ExposeSecret::expose_secret)
+                == other.password.as_ref().map(ExposeSecret::expose_secret)
+            && self.login_ex == other.login_ex
+            && self.token_ttl_seconds == other.token_ttl_seconds
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            method: AuthMethod::default(),
+            username: None,
+            password: None,
+            login_ex: false,
+            token_ttl_seconds: default_token_ttl(),
+        }
+    }
+}
+
+/// Authentication strategy
+///
+/// - `api_key` - Log in with `api_key` on every connection
+/// - `password` - Log in with `username` and `password` on every connection
+/// - `token` - Log in once with username/password (or the API key if no username is
+///   set), then reconnect with a short-lived token from `auth.generate_token` that is
+///   renewed before it expires
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    #[default]
+    ApiKey,
+    Password,
+    Token,
+}
+
+/// Which TrueNAS WebSocket API to speak
+///
+/// - `auto` - Try the versioned JSON-RPC 2.0 endpoint first and fall back to the
+///   legacy DDP endpoint if the server does not offer it
+/// - `legacy` - Always use the DDP endpoint at `/websocket`
+/// - `jsonrpc2` - Always use the JSON-RPC 2.0 endpoint at `/api/current`
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProtocolMode {
+    #[default]
+    Auto,
+    Legacy,
+    #[serde(rename = "jsonrpc2")]
+    JsonRpc2,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ServerConfig {
     #[serde(default = "default_addr")]
     pub addr: String,
     #[serde(default = "default_port")]
     pub port: u16,
+    /// exporter-toolkit web configuration with TLS and auth settings (see [`crate::web`])
+    #[serde(default)]
+    pub web_config_file: Option<PathBuf>,
+    /// How long to wait on SIGTERM/SIGINT for scrapes to finish and connections to close
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout_seconds: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct MetricsConfig {
     #[serde(default = "default_scrape_interval")]
     pub scrape_interval_seconds: u64,
+    /// Legacy switch for the `pool` and `dataset` collectors
     #[serde(default = "default_true")]
     pub collect_pool_metrics: bool,
+    /// Legacy switch for the `system_info` and `system_reporting` collectors
     #[serde(default = "default_true")]
     pub collect_system_metrics: bool,
+    /// Collectors switched on or off by name (`[metrics.collectors]`)
+    ///
+    /// Collectors not listed are enabled unless a legacy switch above turns them off.
+    #[serde(default)]
+    pub collectors: HashMap<String, bool>,
+    /// Keep alert, pool, service and app metrics current via push events
+    #[serde(default)]
+    pub subscribe_events: bool,
+    /// Full re-poll of event-driven collections when `subscribe_events` is on
+    #[serde(default = "default_event_resync_interval")]
+    pub event_resync_interval_seconds: u64,
+    /// Collectors allowed to run at the same time within a cycle
+    #[serde(default = "default_max_concurrent_collectors")]
+    pub max_concurrent_collectors: usize,
+    /// Time a single collector may take before it is marked failed
+    #[serde(default = "default_collector_timeout")]
+    pub collector_timeout_seconds: u64,
+    /// Per-collector intervals in seconds, by collector name (`[metrics.intervals]`)
+    ///
+    /// Collectors not listed run every `scrape_interval_seconds`.
+    #[serde(default)]
+    pub intervals: HashMap<String, u64>,
+    /// Random delay of up to this many seconds added to each collector's next run
+    #[serde(default)]
+    pub interval_jitter_seconds: u64,
+    /// Collect in the background or when `/metrics` is requested
+    #[serde(default)]
+    pub collection_mode: CollectionMode,
+    /// In `on_scrape` mode, serve cached metrics to scrapes arriving sooner than this
+    /// after the last collection
+    #[serde(default = "default_min_refresh_age")]
+    pub min_refresh_age_seconds: u64,
+    /// How long a series that is no longer reported (e.g. a deleted dataset) keeps being
+    /// exported with its last value (0 removes it at once)
+    #[serde(default)]
+    pub stale_series_grace_seconds: u64,
+}
+
+impl MetricsConfig {
+    /// Reject collector names in `[metrics.collectors]` or `[metrics.intervals]` that
+    /// no collector is registered under
+    pub fn validate_collectors(&self) -> Result<()> {
+        let known: Vec<&str> = crate::collectors::registry::names().collect();
+        let mut unknown: Vec<&str> = self
+            .collectors
+            .keys()
+            .chain(self.intervals.keys())
+            .map(String::as_str)
+            .filter(|name| !known.contains(name))
+            .collect();
+        unknown.sort_unstable();
+        unknown.dedup();
+        if !unknown.is_empty() {
+            anyhow::bail!(
+                "unknown collector(s) {} (known: {})",
+                unknown.join(", "),
+                known.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Reject zero intervals and timeouts, which would collect in a busy loop or never
+    /// finish
+    pub fn validate_intervals(&self) -> Result<()> {
+        let mut zero: Vec<String> = Vec::new();
+        if self.scrape_interval_seconds == 0 {
+            zero.push("scrape_interval_seconds".to_string());
+        }
+        if self.collector_timeout_seconds == 0 {
+            zero.push("collector_timeout_seconds".to_string());
+        }
+        if self.subscribe_events && self.event_resync_interval_seconds == 0 {
+            zero.push("event_resync_interval_seconds".to_string());
+        }
+        let mut intervals: Vec<_> = self
+            .intervals
+            .iter()
+            .filter(|(_, seconds)| **seconds == 0)
+            .map(|(name, _)| format!("intervals.{}", name))
+            .collect();
+        intervals.sort_unstable();
+        zero.extend(intervals);
+        if !zero.is_empty() {
+            anyhow::bail!("metrics.{} must be greater than 0", zero.join(", metrics."));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            scrape_interval_seconds: default_scrape_interval(),
+            collect_pool_metrics: default_true(),
+            collect_system_metrics: default_true(),
+            collectors: HashMap::new(),
+            subscribe_events: false,
+            event_resync_interval_seconds: default_event_resync_interval(),
+            max_concurrent_collectors: default_max_concurrent_collectors(),
+            collector_timeout_seconds: default_collector_timeout(),
+            intervals: HashMap::new(),
+            interval_jitter_seconds: 0,
+            stale_series_grace_seconds: 0,
+            collection_mode: CollectionMode::default(),
+            min_refresh_age_seconds: default_min_refresh_age(),
+        }
+    }
+}
+
+/// When metrics are collected
+///
+/// - `background` - A background loop collects on the configured intervals and scrapes
+///   are served from the last completed cycle
+/// - `on_scrape` - A scrape collects first, unless the last collection is younger than
+///   `min_refresh_age_seconds`; concurrent scrapes share one collection
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionMode {
+    #[default]
+    Background,
+    OnScrape,
+}
+
+/// Settings for the multi-target `/probe` endpoint (`[probe]`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProbeConfig {
+    /// Probe modules by name (`[probe.modules.<name>]`)
+    ///
+    /// `/probe` only serves the modules listed here; there is no implicit `default`.
+    #[serde(default)]
+    pub modules: HashMap<String, ProbeModule>,
+    /// Drop a target's connection and metrics after it has not been probed this long
+    #[serde(default = "default_probe_idle_timeout")]
+    pub idle_timeout_seconds: u64,
+    /// Targets kept connected at the same time; probes of further targets are refused
+    #[serde(default = "default_probe_max_targets")]
+    pub max_targets: usize,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            modules: HashMap::new(),
+            idle_timeout_seconds: default_probe_idle_timeout(),
+            max_targets: default_probe_max_targets(),
+        }
+    }
+}
+
+/// Connection settings of a probe module and the systems it may be used for
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ProbeModule {
+    /// Connection and auth settings; `host` is ignored, the `target` parameter takes
+    /// its place
+    #[serde(flatten)]
+    pub truenas: TrueNasConfig,
+    /// `host[:port]` values the `target` parameter may name
+    ///
+    /// The module's credentials are only ever sent to these systems.
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+impl ProbeModule {
+    /// Whether `target` is on the module's allowlist
+    pub fn allows(&self, target: &str) -> bool {
+        self.targets.iter().any(|allowed| allowed == target)
+    }
 }
 
+/// Module used by `/probe` requests without a `module` parameter
+pub const DEFAULT_PROBE_MODULE: &str = "default";
+
 fn default_addr() -> String {
     "0.0.0.0".to_string()
 }
@@ -46,6 +529,47 @@ fn default_port() -> u16 {
     9100
 }
 
+fn default_shutdown_timeout() -> u64 {
+    // Below the 10 second grace period of `docker stop`
+    5
+}
+
+fn default_api_key() -> SecretString {
+    SecretString::from("")
+}
+
+fn default_token_ttl() -> u64 {
+    600
+}
+
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+fn default_call_timeout() -> u64 {
+    30
+}
+
+fn default_keepalive_interval() -> u64 {
+    30
+}
+
+fn default_keepalive_timeout() -> u64 {
+    10
+}
+
+fn default_initial_backoff() -> u64 {
+    1
+}
+
+fn default_max_backoff() -> u64 {
+    60
+}
+
+fn default_circuit_breaker_threshold() -> u32 {
+    3
+}
+
 fn default_use_tls() -> bool {
     false
 }
@@ -54,6 +578,30 @@ fn default_verify_ssl() -> bool {
     true
 }
 
+fn default_event_resync_interval() -> u64 {
+    300
+}
+
+fn default_max_concurrent_collectors() -> usize {
+    4
+}
+
+fn default_collector_timeout() -> u64 {
+    30
+}
+
+fn default_min_refresh_age() -> u64 {
+    10
+}
+
+fn default_probe_max_targets() -> usize {
+    100
+}
+
+fn default_probe_idle_timeout() -> u64 {
+    600
+}
+
 fn default_scrape_interval() -> u64 {
     60
 }
@@ -63,7 +611,72 @@ fn default_true() -> bool {
 }
 
 impl Config {
+    /// Connection settings for probing `target` with the named module
+    ///
+    /// Returns `None` if the module is not configured or does not allow `target`.
+    pub fn probe_module(&self, module: &str, target: &str) -> Option<TrueNasConfig> {
+        let module = self.probe.modules.get(module)?;
+        if !module.allows(target) {
+            return None;
+        }
+        Some(TrueNasConfig {
+            host: target.to_string(),
+            ..module.truenas.clone()
+        })
+    }
+
+    /// Check settings that deserialize fine but cannot work
+    pub fn validate(&self) -> Result<()> {
+        self.validate_targets()?;
+        self.metrics.validate_collectors()?;
+        self.metrics.validate_intervals()
+    }
+
+    /// Reject `[[truenas]]` entries without a unique host, probe modules without
+    /// targets and zero connection timeouts
+    pub fn validate_targets(&self) -> Result<()> {
+        if let TrueNasHosts::Multiple(hosts) = &self.truenas {
+            let mut seen = std::collections::HashSet::new();
+            for config in hosts {
+                if config.host.is_empty() {
+                    anyhow::bail!("Every [[truenas]] entry needs a host");
+                }
+                if !seen.insert(config.host.as_str()) {
+                    anyhow::bail!("TrueNAS host {} is listed more than once", config.host);
+                }
+            }
+        }
+        for config in self.truenas.hosts() {
+            config
+                .timeouts
+                .validate(&format!("TrueNAS host {}", config.host))?;
+        }
+        for (name, module) in &self.probe.modules {
+            if module.targets.is_empty() {
+                anyhow::bail!(
+                    "probe.modules.{}.targets must list the systems the module may probe",
+                    name
+                );
+            }
+            module
+                .truenas
+                .timeouts
+                .validate(&format!("probe.modules.{}", name))?;
+        }
+        Ok(())
+    }
+
+    /// Read and validate the configuration file and environment
     pub fn load(path: &str) -> Result<Self> {
+        let config = Self::read(path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read the configuration file and environment without validating the result
+    ///
+    /// For `check-config`, which reports what [`Config::validate`] would reject.
+    pub fn read(path: &str) -> Result<Self> {
         // Load environment variables from .env if present
         dotenvy::dotenv().ok();
 
@@ -78,3 +691,38 @@ impl Config {
             .context("Failed to deserialize configuration")
     }
 }
+
+/// Where the running configuration comes from, so it can be loaded again
+///
+/// The exporter reloads on SIGHUP, on `POST /-/reload` and when one of the watched
+/// files changes.
+pub struct ConfigSource {
+    files: Vec<PathBuf>,
+    load: Box<dyn Fn() -> Result<Config> + Send + Sync>,
+}
+
+impl ConfigSource {
+    /// Source that loads with `load` and is reloaded when one of `files` changes
+    pub fn new(
+        files: Vec<PathBuf>,
+        load: impl Fn() -> Result<Config> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            files,
+            load: Box::new(load),
+        }
+    }
+
+    /// Load and validate the configuration
+    pub fn load(&self) -> Result<Config> {
+        (self.load)()
+    }
+
+    /// Modification times of the watched files, `None` for files that do not exist
+    pub fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.files
+            .iter()
+            .map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
@@ -15,6 +15,23 @@ pub enum ExporterError {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    #[error("TrueNAS unavailable: {0}")]
+    Unavailable(String),
+
+    /// The TLS handshake failed or the certificate did not match `pin_sha256`
+    #[error("TLS error: {0}")]
+    Tls(String),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    /// The WebSocket was closed, by a Close frame or because the connection was lost
+    ///
+    /// `code` is the WebSocket close code; 1006 (abnormal closure) when no Close frame
+    /// was received.
+    #[error("Connection closed (code {code}): {reason}")]
+    ConnectionClosed { code: u16, reason: String },
+
     #[error("Authentication failed: {0}")]
     Auth(String),
 
@@ -0,0 +1,391 @@
+//! Exposition Formats
+//!
+//! `/metrics` speaks the classic Prometheus text format by default and OpenMetrics 1.0
+//! when the scraper asks for `application/openmetrics-text` in its `Accept` header.
+//!
+//! # OpenMetrics Output
+//!
+//! The OpenMetrics encoder adds what the classic format cannot express:
+//!
+//! - **info**: gauges named `*_info` whose value is always 1 (`truenas_disk_info`,
+//!   `truenas_nfs_client_info`, `truenas_network_interface_info`, ...) are typed `info`
+//! - **stateset**: `truenas_pool_status` and `truenas_cloud_sync_state` list every known
+//!   state, with the current one set to 1, in a label named after the metric. They are
+//!   derived from the `status` label of `truenas_pool_health` and the `state` label of
+//!   `truenas_cloud_sync_status`, which stay gauges as in the classic format
+//! - **unit**: metrics ending in a unit suffix (`_bytes`, `_seconds`, ...) declare it
+//!   with `# UNIT`
+//! - Counters are declared without their `_total` suffix, and the output ends with
+//!   `# EOF`
+//!
+//! # Compression
+//!
+//! Responses are compressed with gzip when the `Accept-Encoding` header allows it, as
+//! Prometheus' does, or with zstd if the exporter is built with the `zstd` feature and
+//! the client prefers it.
+
+use anyhow::{bail, Result};
+use flate2::write::GzEncoder;
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+use prometheus::{Encoder, TextEncoder};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::Write as _;
+
+/// Content type of the classic text format
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Content type of OpenMetrics 1.0
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Name suffixes declared as units in OpenMetrics
+const UNITS: [&str; 5] = ["bytes", "seconds", "hours", "celsius", "percent"];
+
+/// Statesets derived from a gauge's label: gauge, stateset name and help, state label,
+/// and the states TrueNAS reports
+const STATESETS: [(&str, &str, &str, &str, &[&str]); 2] = [
+    (
+        "truenas_pool_health",
+        "truenas_pool_status",
+        "Pool status reported by TrueNAS",
+        "status",
+        &[
+            "ONLINE", "DEGRADED", "FAULTED", "OFFLINE", "UNAVAIL", "REMOVED",
+        ],
+    ),
+    (
+        "truenas_cloud_sync_status",
+        "truenas_cloud_sync_state",
+        "Cloud Sync job state reported by TrueNAS",
+        "state",
+        &[
+            "PENDING", "WAITING", "RUNNING", "SUCCESS", "FAILED", "ABORTED",
+        ],
+    ),
+];
+
+/// Exposition format of a scrape response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Prometheus text format 0.0.4
+    #[default]
+    Text,
+    /// OpenMetrics 1.0 text format
+    OpenMetrics,
+}
+
+impl Format {
+    /// Every format, indexed by its discriminant
+    pub const ALL: [Format; 2] = [Format::Text, Format::OpenMetrics];
+
+    /// Pick the format from an `Accept` header
+    ///
+    /// OpenMetrics is used when the client rates it at least as high as any other type
+    /// it accepts; everything else gets the classic text format.
+    pub fn negotiate(accept: Option<&str>) -> Self {
+        let Some(accept) = accept else {
+            return Self::Text;
+        };
+        let mut openmetrics: f32 = 0.0;
+        let mut other: f32 = 0.0;
+        for range in accept.split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default();
+            let quality = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if media_type.eq_ignore_ascii_case("application/openmetrics-text") {
+                openmetrics = openmetrics.max(quality);
+            } else {
+                other = other.max(quality);
+            }
+        }
+        if openmetrics > 0.0 && openmetrics >= other {
+            Self::OpenMetrics
+        } else {
+            Self::Text
+        }
+    }
+
+    /// `Content-Type` of responses in this format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Text => TEXT_CONTENT_TYPE,
+            Self::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+        }
+    }
+
+    /// Encode metric families, which must be sorted by name, as a complete exposition
+    pub fn encode(self, families: &[MetricFamily]) -> Result<String> {
+        let mut output = self.encode_families(families)?;
+        self.finish(&mut output);
+        Ok(output)
+    }
+
+    /// Encode metric families without the end of the exposition
+    ///
+    /// Outputs of families with distinct names can be concatenated and then completed
+    /// with [`Format::finish`].
+    pub fn encode_families(self, families: &[MetricFamily]) -> Result<String> {
+        match self {
+            Self::Text => {
+                let mut buffer = Vec::new();
+                TextEncoder::new().encode(families, &mut buffer)?;
+                Ok(String::from_utf8(buffer)?)
+            }
+            Self::OpenMetrics => encode_openmetrics(families),
+        }
+    }
+
+    /// Append what ends an exposition: `# EOF` in OpenMetrics, nothing in text format
+    pub fn finish(self, output: &mut String) {
+        if self == Self::OpenMetrics {
+            output.push_str("# EOF\n");
+        }
+    }
+}
+
+/// Content coding of a scrape response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Compression {
+    /// Uncompressed
+    #[default]
+    Identity,
+    /// gzip, which every Prometheus version accepts
+    Gzip,
+    /// zstd, with the `zstd` feature
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Pick the coding from an `Accept-Encoding` header
+    ///
+    /// The coding with the highest quality wins; on a tie zstd is preferred over gzip,
+    /// and gzip over no compression.
+    pub fn negotiate(accept_encoding: Option<&str>) -> Self {
+        let Some(accept_encoding) = accept_encoding else {
+            return Self::Identity;
+        };
+        let mut best = (Self::Identity, 0.0_f32);
+        for coding in accept_encoding.split(',') {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let quality = params
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let candidate = match name.to_ascii_lowercase().as_str() {
+                "gzip" | "x-gzip" => Self::Gzip,
+                #[cfg(feature = "zstd")]
+                "zstd" => Self::Zstd,
+                _ => continue,
+            };
+            if quality > best.1 || (quality == best.1 && quality > 0.0 && candidate > best.0) {
+                best = (candidate, quality);
+            }
+        }
+        best.0
+    }
+
+    /// `Content-Encoding` of responses with this coding, `None` for identity
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            Self::Identity => None,
+            Self::Gzip => Some("gzip"),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Some("zstd"),
+        }
+    }
+
+    /// Compress a response body
+    pub fn compress(self, body: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Identity => Ok(body),
+            Self::Gzip => {
+                // Scrapes are frequent; fast compression still shrinks text tenfold
+                let mut encoder = GzEncoder::new(
+                    Vec::with_capacity(body.len() / 8),
+                    flate2::Compression::fast(),
+                );
+                encoder.write_all(&body)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::encode_all(body.as_slice(), 0),
+        }
+    }
+}
+
+/// Encode metric families in the OpenMetrics 1.0 text format
+fn encode_openmetrics(families: &[MetricFamily]) -> Result<String> {
+    let mut out = String::new();
+    for family in families {
+        if family.get_metric().is_empty() {
+            continue;
+        }
+        let name = family.name();
+        if let Some((_, stateset, help, label, states)) =
+            STATESETS.iter().find(|(n, ..)| *n == name)
+        {
+            write_header(&mut out, stateset, "stateset", None, help);
+            write_stateset(&mut out, stateset, family, label, states);
+        }
+
+        match family.get_field_type() {
+            MetricType::COUNTER => {
+                let base = name.strip_suffix("_total").unwrap_or(name);
+                write_header(&mut out, base, "counter", unit(base), family.help());
+                for metric in family.get_metric() {
+                    let labels = labels(metric.get_label(), None);
+                    let value = metric.get_counter().value();
+                    write_sample(&mut out, &format!("{}_total", base), &labels, value);
+                }
+            }
+            MetricType::GAUGE if is_info(family) => {
+                let base = name.strip_suffix("_info").unwrap_or(name);
+                write_header(&mut out, base, "info", None, family.help());
+                for metric in family.get_metric() {
+                    write_sample(&mut out, name, &labels(metric.get_label(), None), 1.0);
+                }
+            }
+            MetricType::GAUGE => {
+                write_header(&mut out, name, "gauge", unit(name), family.help());
+                for metric in family.get_metric() {
+                    let labels = labels(metric.get_label(), None);
+                    write_sample(&mut out, name, &labels, metric.get_gauge().value());
+                }
+            }
+            MetricType::UNTYPED => {
+                write_header(&mut out, name, "unknown", unit(name), family.help());
+                for metric in family.get_metric() {
+                    let labels = labels(metric.get_label(), None);
+                    write_sample(&mut out, name, &labels, metric.untyped.value());
+                }
+            }
+            other => bail!(
+                "{:?} metrics are not supported in OpenMetrics ({})",
+                other,
+                name
+            ),
+        }
+    }
+    Ok(out)
+}
+
+/// Gauges named `*_info` whose every value is 1
+fn is_info(family: &MetricFamily) -> bool {
+    family.name().ends_with("_info")
+        && family
+            .get_metric()
+            .iter()
+            .all(|metric| metric.get_gauge().value() == 1.0)
+}
+
+/// The unit a metric name ends in, if any
+fn unit(name: &str) -> Option<&'static str> {
+    UNITS
+        .iter()
+        .find(|unit| name.ends_with(&format!("_{}", unit)))
+        .copied()
+}
+
+/// One `name` sample per known state for each set of the other labels of `family`
+///
+/// The current state comes from the state label, whatever the gauge's value; the other
+/// known states are 0.
+fn write_stateset(
+    out: &mut String,
+    name: &str,
+    family: &MetricFamily,
+    label: &str,
+    states: &[&str],
+) {
+    let mut sets: BTreeMap<String, (Vec<&LabelPair>, Vec<String>)> = BTreeMap::new();
+    for metric in family.get_metric() {
+        let others: Vec<&LabelPair> = metric
+            .get_label()
+            .iter()
+            .filter(|pair| pair.name() != label)
+            .collect();
+        let current = metric
+            .get_label()
+            .iter()
+            .find(|pair| pair.name() == label)
+            .map(|pair| pair.value().to_string())
+            .unwrap_or_default();
+        let set = sets
+            .entry(labels(metric.get_label(), Some(label)))
+            .or_insert_with(|| (others, Vec::new()));
+        set.1.push(current);
+    }
+
+    for (others, current) in sets.values() {
+        let mut all: Vec<&str> = states.to_vec();
+        for state in current {
+            if !all.contains(&state.as_str()) {
+                all.push(state);
+            }
+        }
+        for state in all {
+            let mut labels = labels_from(others.iter().copied());
+            if !labels.is_empty() {
+                labels.push(',');
+            }
+            let _ = write!(labels, "{}=\"{}\"", name, escape(state));
+            let value = if current.iter().any(|c| c == state) {
+                1.0
+            } else {
+                0.0
+            };
+            write_sample(out, name, &labels, value);
+        }
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, unit: Option<&str>, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    if let Some(unit) = unit {
+        let _ = writeln!(out, "# UNIT {} {}", name, unit);
+    }
+    let _ = writeln!(out, "# HELP {} {}", name, escape(help));
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &str, value: f64) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, format_value(value));
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, format_value(value));
+    }
+}
+
+/// `name="value"` pairs, without `skip`
+fn labels(pairs: &[LabelPair], skip: Option<&str>) -> String {
+    labels_from(pairs.iter().filter(|pair| Some(pair.name()) != skip))
+}
+
+fn labels_from<'a>(pairs: impl Iterator<Item = &'a LabelPair>) -> String {
+    pairs
+        .map(|pair| format!("{}=\"{}\"", pair.name(), escape(pair.value())))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
@@ -27,9 +27,13 @@
 //!
 //! - [`truenas`] - WebSocket client and API type definitions
 //! - [`metrics`] - Prometheus metric definitions
+//! - [`exposition`] - Prometheus text and OpenMetrics output
 //! - [`server`] - HTTP server and collection loop
+//! - [`web`] - TLS and authentication for the exporter's own endpoints
 //! - [`config`] - Configuration management
+//! - [`check`] - `check-config` and `test-connection` subcommands
 //! - [`error`] - Error types
+//! - `test_support` - Fake TrueNAS server for integration tests (`test-support` feature)
 //!
 //! # Quick Start
 //!
@@ -53,9 +57,14 @@
 //! - ✅ System alerts and resource usage
 //! - ✅ TLS support with optional certificate verification
 
+pub mod check;
 pub mod collectors;
 pub mod config;
 pub mod error;
+pub mod exposition;
 pub mod metrics;
 pub mod server;
+#[cfg(feature = "test-support")]
+pub mod test_support;
 pub mod truenas;
+pub mod web;
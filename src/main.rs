@@ -1,11 +1,23 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 use tracing::{error, info};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
-use truenas_exporter::{config::Config, server};
+use truenas_exporter::{
+    check, collectors,
+    config::{Config, ConfigSource, TrueNasHosts},
+    server,
+};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    after_help = collectors::registry::help()
+)]
 struct Args {
     /// Path to configuration file
     #[arg(short, long, default_value = "config/Default.toml")]
@@ -26,49 +38,155 @@ struct Args {
     /// Address to bind to
     #[arg(short, long, env = "EXPORTER_ADDR", default_value = "0.0.0.0")]
     addr: String,
+
+    /// exporter-toolkit web configuration file enabling TLS and/or authentication
+    #[arg(long = "web.config.file", value_name = "FILE")]
+    web_config_file: Option<PathBuf>,
+
+    /// Write every TrueNAS API call and response to fixture files in DIR
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Serve TrueNAS API responses from fixture files in DIR instead of connecting
+    #[arg(long, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Checks for deployment pipelines; without a subcommand the exporter runs
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Load and validate the configuration, then exit non-zero if a check fails
+    CheckConfig,
+    /// Log in to TrueNAS and run every enabled collector once, then exit non-zero if
+    /// a check fails
+    TestConnection,
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 2)]
 async fn main() -> Result<()> {
-    // Initialize tracing
+    // Parse CLI arguments
+    let args = Args::parse();
+
+    // Initialize tracing; checks only log problems, and to stderr, to keep their table
+    // readable on stdout
+    let (level, writer) = if args.command.is_some() {
+        ("warn", BoxMakeWriter::new(std::io::stderr))
+    } else {
+        ("info", BoxMakeWriter::new(std::io::stdout))
+    };
     tracing_subscriber::registry()
-        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
-        .with(tracing_subscriber::fmt::layer())
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| level.into()))
+        .with(tracing_subscriber::fmt::layer().with_writer(writer))
         .init();
 
+    if let Some(command) = &args.command {
+        return run_checks(command, &args).await;
+    }
+
     info!(
         "Starting TrueNAS Prometheus Exporter v{}",
         env!("CARGO_PKG_VERSION")
     );
 
-    // Parse CLI arguments
-    let args = Args::parse();
-
-    // Load configuration
-    let mut config = Config::load(&args.config)?;
-
-    // Override with CLI arguments if provided
-    if let Some(host) = args.truenas_host {
-        config.truenas.host = host;
-    }
-    if let Some(api_key) = args.truenas_api_key {
-        config.truenas.api_key = secrecy::SecretString::new(api_key.into());
-    }
-    config.server.port = args.port;
-    config.server.addr = args.addr;
+    // Load configuration; reloads apply the same command line overrides
+    let config = load_config(&args)?;
+    let config_file = PathBuf::from(&args.config);
+    let source = ConfigSource::new(vec![config_file], move || load_config(&args));
 
     info!("Configuration loaded successfully");
-    info!("TrueNAS host: {}", config.truenas.host);
+    for truenas in config.truenas.hosts() {
+        match (&truenas.replay_dir, &truenas.record_dir) {
+            (Some(dir), _) => info!("Replaying TrueNAS responses from {}", dir.display()),
+            (None, Some(dir)) => {
+                info!("TrueNAS host: {}", truenas.host);
+                info!("Recording TrueNAS responses to {}", dir.display());
+            }
+            (None, None) => info!("TrueNAS host: {}", truenas.host),
+        }
+    }
     info!(
         "Metrics endpoint: http://{}:{}/metrics",
         config.server.addr, config.server.port
     );
 
     // Start the metrics server
-    if let Err(e) = server::start(config).await {
+    if let Err(e) = server::start_with_reload(config, source).await {
         error!("Server error: {}", e);
         std::process::exit(1);
     }
 
     Ok(())
 }
+
+/// Run a check subcommand, print its table and exit non-zero if a check failed
+async fn run_checks(command: &Command, args: &Args) -> Result<()> {
+    // check-config reports settings `validate` would reject as failed checks instead
+    let load = match command {
+        Command::CheckConfig => read_config,
+        Command::TestConnection => load_config,
+    };
+    let config = match load(args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load configuration {}: {:#}", args.config, e);
+            std::process::exit(1);
+        }
+    };
+    let report = match command {
+        Command::CheckConfig => check::check_config(&config),
+        Command::TestConnection => check::test_connection(&config).await,
+    };
+    print!("{}", report.table());
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Load the configuration file, apply the command line overrides and validate the result
+fn load_config(args: &Args) -> Result<Config> {
+    let config = read_config(args)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Read the configuration file and apply the command line overrides
+fn read_config(args: &Args) -> Result<Config> {
+    let mut config = Config::read(&args.config)?;
+
+    // Override with CLI arguments if provided; they only make sense for a single host
+    let overrides = args.truenas_host.is_some()
+        || args.truenas_api_key.is_some()
+        || args.record.is_some()
+        || args.replay.is_some();
+    match &mut config.truenas {
+        TrueNasHosts::Single(truenas) => {
+            if let Some(host) = &args.truenas_host {
+                truenas.host = host.clone();
+            }
+            if let Some(api_key) = &args.truenas_api_key {
+                truenas.api_key = secrecy::SecretString::new(api_key.clone().into());
+            }
+            if args.record.is_some() {
+                truenas.record_dir = args.record.clone();
+            }
+            if args.replay.is_some() {
+                truenas.replay_dir = args.replay.clone();
+            }
+        }
+        TrueNasHosts::Multiple(_) if overrides => anyhow::bail!(
+            "--truenas-host, --truenas-api-key, --record and --replay need a single \
+             [truenas] table, not a [[truenas]] list"
+        ),
+        TrueNasHosts::Multiple(_) => {}
+    }
+    config.server.port = args.port;
+    config.server.addr = args.addr.clone();
+    if args.web_config_file.is_some() {
+        config.server.web_config_file = args.web_config_file.clone();
+    }
+    Ok(config)
+}
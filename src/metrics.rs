@@ -30,15 +30,68 @@
 //! - **IntGauge**: Integer gauge (e.g., service status 0/1)
 //! - **GaugeVec**: Gauge with labels (e.g., pool metrics labeled by pool name)
 //!
-//! All metrics use the `truenas_` namespace prefix.
+//! ## Exporter Self-Metrics
+//! - Connection state, reconnect and authentication failure counters
+//! - Build info with the exporter and detected TrueNAS versions
+//! - Per-collector success, duration and last-success timestamp
+//! - Age of the served metrics
+//!
+//! All metrics use the `truenas_` namespace prefix; metrics about the exporter itself
+//! use `truenas_exporter_`.
+//!
+//! When several TrueNAS systems are configured, each has its own [`MetricsCollector`] and
+//! [`HostsRendering`] merges them, adding a `host` label to every series.
+
+use crate::exposition::Format;
+use crate::truenas::{ConnectionStats, ServerVersion};
+use prometheus::proto::{LabelPair, Metric, MetricFamily};
+use prometheus::{Gauge, GaugeVec, IntCounter, IntGauge, IntGaugeVec, Opts, Registry};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Identity of a series: metric family name and label pairs
+type SeriesKey = (String, Vec<(String, String)>);
+
+/// Snapshots published so far by every [`MetricsCollector`], numbering each of them
+static PUBLISHED: AtomicU64 = AtomicU64::new(0);
+
+/// TrueNAS series as last published, with when each series was last collected
+#[derive(Default)]
+struct Snapshot {
+    families: Vec<MetricFamily>,
+    last_seen: HashMap<SeriesKey, Instant>,
+    published_at: Option<Instant>,
+    /// Unique across collectors, so a rendering of several hosts knows what it shows
+    number: u64,
+    /// `families` encoded in each format, filled in by the first scrape that needs it
+    rendered: [OnceLock<String>; Format::ALL.len()],
+}
 
-use prometheus::{Encoder, Gauge, GaugeVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
-use std::sync::Arc;
+impl Snapshot {
+    /// The published series encoded in `format`, encoded once per snapshot
+    fn rendered(&self, format: Format) -> anyhow::Result<&str> {
+        let cell = &self.rendered[format as usize];
+        if let Some(rendered) = cell.get() {
+            return Ok(rendered);
+        }
+        let rendered = format.encode_families(&self.families)?;
+        Ok(cell.get_or_init(|| rendered))
+    }
+}
 
 /// Metrics collector for TrueNAS
 #[derive(Clone)]
 pub struct MetricsCollector {
+    /// TrueNAS series, written by the collectors
     registry: Arc<Registry>,
+    /// Exporter self-metrics and `truenas_up`, rendered live
+    exporter_registry: Arc<Registry>,
+    /// TrueNAS series served to scrapes, replaced by [`MetricsCollector::publish`]
+    published: Arc<RwLock<Option<Snapshot>>>,
+    /// Held while a collector rebuilds its series and while publishing
+    updates: Arc<Mutex<()>>,
 
     // Pool metrics
     pub pool_health: Arc<GaugeVec>,
@@ -111,6 +164,18 @@ pub struct MetricsCollector {
 
     // Service status
     pub service_status: Arc<IntGaugeVec>,
+
+    // Exporter self-metrics
+    pub exporter_connection_state: Arc<IntGauge>,
+    pub exporter_reconnects_total: Arc<IntCounter>,
+    pub exporter_auth_failures_total: Arc<IntCounter>,
+    pub exporter_build_info: Arc<IntGaugeVec>,
+    pub exporter_collector_success: Arc<GaugeVec>,
+    pub exporter_collector_duration_seconds: Arc<GaugeVec>,
+    pub exporter_collector_last_success_timestamp_seconds: Arc<GaugeVec>,
+    pub exporter_cache_age_seconds: Arc<Gauge>,
+    pub exporter_config_last_reload_successful: Arc<IntGauge>,
+    pub exporter_config_last_reload_success_timestamp_seconds: Arc<Gauge>,
 }
 
 impl MetricsCollector {
@@ -540,10 +605,87 @@ impl MetricsCollector {
         registry.register(Box::new(network_receive_bytes_per_second.clone()))?;
         registry.register(Box::new(network_transmit_bytes_per_second.clone()))?;
         registry.register(Box::new(service_status.clone()))?;
-        registry.register(Box::new(up.clone()))?;
+
+        // Exporter self-metrics live outside the published snapshot and are always current
+        let exporter_registry = Registry::new();
+        exporter_registry.register(Box::new(up.clone()))?;
+
+        let exporter_connection_state = IntGauge::new(
+            "truenas_exporter_connection_state",
+            "Connection state to TrueNAS (0=disconnected, 1=connected, 2=backing off)",
+        )?;
+        let exporter_reconnects_total = IntCounter::new(
+            "truenas_exporter_reconnects_total",
+            "Connection attempts made after the initial connection",
+        )?;
+        let exporter_auth_failures_total = IntCounter::new(
+            "truenas_exporter_auth_failures_total",
+            "Connection attempts rejected during authentication",
+        )?;
+        exporter_registry.register(Box::new(exporter_connection_state.clone()))?;
+        exporter_registry.register(Box::new(exporter_reconnects_total.clone()))?;
+        exporter_registry.register(Box::new(exporter_auth_failures_total.clone()))?;
+
+        let exporter_build_info = IntGaugeVec::new(
+            Opts::new(
+                "truenas_exporter_build_info",
+                "Exporter version and detected TrueNAS version (always 1)",
+            ),
+            &["version", "truenas_version"],
+        )?;
+        exporter_registry.register(Box::new(exporter_build_info.clone()))?;
+
+        let exporter_collector_success = GaugeVec::new(
+            Opts::new(
+                "truenas_exporter_collector_success",
+                "Whether the collector's last run succeeded (1=success, 0=failure)",
+            ),
+            &["collector"],
+        )?;
+        let exporter_collector_duration_seconds = GaugeVec::new(
+            Opts::new(
+                "truenas_exporter_collector_duration_seconds",
+                "Duration of the collector's last run in seconds",
+            ),
+            &["collector"],
+        )?;
+        let exporter_collector_last_success_timestamp_seconds = GaugeVec::new(
+            Opts::new(
+                "truenas_exporter_collector_last_success_timestamp_seconds",
+                "Unix timestamp of the collector's last successful run",
+            ),
+            &["collector"],
+        )?;
+        exporter_registry.register(Box::new(exporter_collector_success.clone()))?;
+        exporter_registry.register(Box::new(exporter_collector_duration_seconds.clone()))?;
+        exporter_registry.register(Box::new(
+            exporter_collector_last_success_timestamp_seconds.clone(),
+        ))?;
+
+        let exporter_cache_age_seconds = Gauge::new(
+            "truenas_exporter_cache_age_seconds",
+            "Seconds since the served TrueNAS metrics were last updated",
+        )?;
+        exporter_registry.register(Box::new(exporter_cache_age_seconds.clone()))?;
+
+        let exporter_config_last_reload_successful = IntGauge::new(
+            "truenas_exporter_config_last_reload_successful",
+            "Whether the last configuration reload attempt succeeded (1=success, 0=failure)",
+        )?;
+        let exporter_config_last_reload_success_timestamp_seconds = Gauge::new(
+            "truenas_exporter_config_last_reload_success_timestamp_seconds",
+            "Unix timestamp of the last successful configuration load",
+        )?;
+        exporter_registry.register(Box::new(exporter_config_last_reload_successful.clone()))?;
+        exporter_registry.register(Box::new(
+            exporter_config_last_reload_success_timestamp_seconds.clone(),
+        ))?;
 
         Ok(Self {
             registry: Arc::new(registry),
+            exporter_registry: Arc::new(exporter_registry),
+            published: Arc::new(RwLock::new(None)),
+            updates: Arc::new(Mutex::new(())),
             pool_health: Arc::new(pool_health),
             pool_capacity_bytes: Arc::new(pool_capacity_bytes),
             pool_allocated_bytes: Arc::new(pool_allocated_bytes),
@@ -596,16 +738,204 @@ impl MetricsCollector {
             network_transmit_bytes_per_second: Arc::new(network_transmit_bytes_per_second),
             service_status: Arc::new(service_status),
             up: Arc::new(up),
+            exporter_connection_state: Arc::new(exporter_connection_state),
+            exporter_reconnects_total: Arc::new(exporter_reconnects_total),
+            exporter_auth_failures_total: Arc::new(exporter_auth_failures_total),
+            exporter_build_info: Arc::new(exporter_build_info),
+            exporter_collector_success: Arc::new(exporter_collector_success),
+            exporter_collector_duration_seconds: Arc::new(exporter_collector_duration_seconds),
+            exporter_collector_last_success_timestamp_seconds: Arc::new(
+                exporter_collector_last_success_timestamp_seconds,
+            ),
+            exporter_cache_age_seconds: Arc::new(exporter_cache_age_seconds),
+            exporter_config_last_reload_successful: Arc::new(
+                exporter_config_last_reload_successful,
+            ),
+            exporter_config_last_reload_success_timestamp_seconds: Arc::new(
+                exporter_config_last_reload_success_timestamp_seconds,
+            ),
         })
     }
 
     /// Render metrics in Prometheus text format
+    ///
+    /// TrueNAS series come from the last published snapshot, or straight from the
+    /// collectors if nothing has been published yet; self-metrics are always current.
     pub fn render(&self) -> anyhow::Result<String> {
-        let encoder = TextEncoder::new();
-        let metric_families = self.registry.gather();
-        let mut buffer = Vec::new();
-        encoder.encode(&metric_families, &mut buffer)?;
-        Ok(String::from_utf8(buffer)?)
+        self.render_as(Format::Text)
+    }
+
+    /// Render metrics in the given exposition format
+    ///
+    /// The published TrueNAS series are encoded once per snapshot and reused by every
+    /// scrape until the next [`MetricsCollector::publish`]; only the self-metrics are
+    /// encoded each time, after them.
+    pub fn render_as(&self, format: Format) -> anyhow::Result<String> {
+        let mut output = match &*self.published.read().expect("snapshot lock poisoned") {
+            Some(snapshot) => snapshot.rendered(format)?.to_string(),
+            None => {
+                let mut metric_families = self.registry.gather();
+                metric_families.sort_by(|a, b| a.name().cmp(b.name()));
+                format.encode_families(&metric_families)?
+            }
+        };
+        let mut exporter_families = self.exporter_registry.gather();
+        exporter_families.sort_by(|a, b| a.name().cmp(b.name()));
+        output.push_str(&format.encode_families(&exporter_families)?);
+        format.finish(&mut output);
+        Ok(output)
+    }
+
+    /// The TrueNAS series served to a scrape
+    fn truenas_families(&self) -> Vec<MetricFamily> {
+        match &*self.published.read().expect("snapshot lock poisoned") {
+            Some(snapshot) => snapshot.families.clone(),
+            None => self.registry.gather(),
+        }
+    }
+
+    /// Number of the published snapshot, `None` before the first publish
+    fn snapshot_number(&self) -> Option<u64> {
+        self.published
+            .read()
+            .expect("snapshot lock poisoned")
+            .as_ref()
+            .map(|snapshot| snapshot.number)
+    }
+
+    /// Replace the published TrueNAS series with the collectors' current state
+    ///
+    /// Called once a collection cycle has finished, so scrapes never see a cycle half
+    /// applied. A series that the collectors no longer report, such as a deleted dataset,
+    /// keeps its last value for `grace` after it was last collected and is dropped after
+    /// that; with a zero `grace` it disappears at once.
+    pub fn publish(&self, grace: Duration) {
+        let now = Instant::now();
+        let mut families = {
+            let _updates = self.lock_updates();
+            self.registry.gather()
+        };
+        let mut last_seen = HashMap::new();
+        for family in &families {
+            for metric in family.get_metric() {
+                last_seen.insert(series_key(family, metric), now);
+            }
+        }
+
+        let mut published = self.published.write().expect("snapshot lock poisoned");
+        let previous = published.take().unwrap_or_default();
+        for mut family in previous.families {
+            for metric in family.take_metric() {
+                let key = series_key(&family, &metric);
+                if last_seen.contains_key(&key) {
+                    continue;
+                }
+                let Some(seen) = previous.last_seen.get(&key).copied() else {
+                    continue;
+                };
+                if now.duration_since(seen) >= grace {
+                    continue;
+                }
+                last_seen.insert(key, seen);
+                match families
+                    .iter_mut()
+                    .find(|current| current.name() == family.name())
+                {
+                    Some(current) => current.mut_metric().push(metric),
+                    None => {
+                        let mut stale = family.clone();
+                        stale.set_metric(vec![metric]);
+                        families.push(stale);
+                    }
+                }
+            }
+        }
+        families.sort_by(|a, b| a.name().cmp(b.name()));
+
+        *published = Some(Snapshot {
+            families,
+            last_seen,
+            published_at: Some(now),
+            number: PUBLISHED.fetch_add(1, Ordering::Relaxed) + 1,
+            rendered: Default::default(),
+        });
+    }
+
+    /// Time since [`MetricsCollector::publish`] last ran, `None` before the first time
+    pub fn snapshot_age(&self) -> Option<Duration> {
+        self.published
+            .read()
+            .expect("snapshot lock poisoned")
+            .as_ref()
+            .and_then(|snapshot| snapshot.published_at)
+            .map(|published_at| published_at.elapsed())
+    }
+
+    /// Mirror the connection manager's state and counters into the self-metrics
+    pub fn update_connection_stats(&self, stats: &ConnectionStats) {
+        self.exporter_connection_state.set(stats.state as i64);
+        // Counters only move forward, so add whatever has happened since the last sync
+        let reconnects = stats
+            .reconnects
+            .saturating_sub(self.exporter_reconnects_total.get());
+        self.exporter_reconnects_total.inc_by(reconnects);
+        let auth_failures = stats
+            .auth_failures
+            .saturating_sub(self.exporter_auth_failures_total.get());
+        self.exporter_auth_failures_total.inc_by(auth_failures);
+    }
+
+    /// Set build info, with `unknown` until the TrueNAS version has been detected
+    pub fn update_build_info(&self, truenas_version: Option<&ServerVersion>) {
+        let truenas_version =
+            truenas_version.map_or_else(|| "unknown".to_string(), |v| v.to_string());
+        self.exporter_build_info.reset();
+        self.exporter_build_info
+            .with_label_values(&[env!("CARGO_PKG_VERSION"), truenas_version.as_str()])
+            .set(1);
+    }
+
+    /// Record the outcome of the last configuration reload and when loading last succeeded
+    pub fn update_config_reload(&self, successful: bool, last_success: SystemTime) {
+        self.exporter_config_last_reload_successful
+            .set(i64::from(successful));
+        self.exporter_config_last_reload_success_timestamp_seconds
+            .set(
+                last_success
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64(),
+            );
+    }
+
+    /// Hold while resetting and refilling a collector's series
+    ///
+    /// [`MetricsCollector::publish`] waits for the guard, so a rebuild is published
+    /// whole or not at all. Never hold it across an `.await`.
+    pub fn lock_updates(&self) -> MutexGuard<'_, ()> {
+        // A collector that panicked mid-update leaves nothing to repair
+        self.updates
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Record the outcome and duration of one collector run
+    ///
+    /// The last-success timestamp only moves on success, so it shows how long a
+    /// collector has been failing.
+    pub fn record_collector_run(&self, collector: &str, success: bool, duration: Duration) {
+        self.set_bool_metric(&self.exporter_collector_success, &[collector], success);
+        self.exporter_collector_duration_seconds
+            .with_label_values(&[collector])
+            .set(duration.as_secs_f64());
+        if success {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            self.exporter_collector_last_success_timestamp_seconds
+                .with_label_values(&[collector])
+                .set(now.as_secs_f64());
+        }
     }
 
     // Helper methods for setting metrics with common patterns
@@ -634,8 +964,10 @@ impl MetricsCollector {
         metric.with_label_values(labels).set(value);
     }
 
-    /// Reset all metrics (useful before a fresh scrape)
-    #[allow(dead_code)] // MVP: Will be used in future iterations
+    /// Drop every labelled TrueNAS series and zero the scalar gauges
+    ///
+    /// Collectors clear only the series they own when they rebuild them; this clears
+    /// everything at once.
     pub fn reset(&self) {
         self.pool_health.reset();
         self.pool_capacity_bytes.reset();
@@ -660,19 +992,16 @@ impl MetricsCollector {
         self.disk_write_bytes_per_second.reset();
         self.disk_info.reset();
         self.smart_test_status.reset();
+        self.smart_test_lifetime_hours.reset();
+        self.smart_test_timestamp_seconds.reset();
+        self.disk_power_on_hours.reset();
         self.app_status.reset();
         self.app_cpu_percent.reset();
         self.app_memory_bytes.reset();
         self.app_update_available.reset();
-        // IntGauge and Gauge do not have a reset method.
-        // For IntGauge, we can't reset it to a default value like 0 or 1 without knowing its purpose.
-        // For Gauge, we can set it to 0 if that's the desired "reset" state.
-        // self.system_info.reset(); // IntGauge doesn't have reset()
-        self.system_uptime_seconds.set(0.0); // Gauge can't reset, but we can set to 0? Or just leave it? Gauge doesn't have reset?
-                                             // Actually Gauge doesn't have reset() method in rust-prometheus?
-                                             // Wait, IntGauge/Gauge don't have reset(). The GaugeVec does.
-                                             // We should probably just not reset scalar gauges or set them to 0.
-        self.system_cpu_usage_percent.reset();
+        // Scalar gauges have no series to drop, so they are zeroed instead
+        self.system_info.set(0);
+        self.system_uptime_seconds.set(0.0);
         self.system_cpu_usage_percent.reset();
         self.system_cpu_temperature_celsius.reset();
         self.system_memory_bytes.reset();
@@ -692,10 +1021,137 @@ impl MetricsCollector {
         self.network_receive_bytes_per_second.reset();
         self.network_transmit_bytes_per_second.reset();
         self.service_status.reset();
-        // self.up.reset(); // Gauge doesn't have reset()
     }
 }
 
+/// Renders the metrics of several TrueNAS systems, each series labelled with its `host`
+///
+/// Families of the same name are merged into one, so every metric has a single
+/// `HELP`/`TYPE` header. Like [`MetricsCollector::render_as`], the published TrueNAS
+/// series are merged and encoded once per format and reused by every scrape until any
+/// of the hosts publishes again; only the self-metrics are merged each time.
+#[derive(Default)]
+pub struct HostsRendering {
+    cached: Mutex<Option<CombinedSnapshot>>,
+    encoded: AtomicU64,
+}
+
+/// The hosts' published TrueNAS series, encoded in each format
+struct CombinedSnapshot {
+    /// Host and snapshot number of every host that went into it
+    snapshots: Vec<(String, u64)>,
+    rendered: [OnceLock<String>; Format::ALL.len()],
+}
+
+impl HostsRendering {
+    /// Render the metrics of `hosts` in the given exposition format
+    pub fn render(
+        &self,
+        hosts: &[(&str, &MetricsCollector)],
+        format: Format,
+    ) -> anyhow::Result<String> {
+        let mut output = self.render_truenas(hosts, format)?;
+        let exporter_families = merge_hosts(hosts, |metrics| metrics.exporter_registry.gather());
+        output.push_str(&format.encode_families(&exporter_families)?);
+        format.finish(&mut output);
+        Ok(output)
+    }
+
+    /// How often the hosts' TrueNAS series have been merged and encoded
+    ///
+    /// Scrapes served from the cached rendering do not count.
+    pub fn encoded(&self) -> u64 {
+        self.encoded.load(Ordering::Relaxed)
+    }
+
+    /// The hosts' TrueNAS series, from the cache while no host has published since
+    fn render_truenas(
+        &self,
+        hosts: &[(&str, &MetricsCollector)],
+        format: Format,
+    ) -> anyhow::Result<String> {
+        let encode = || {
+            self.encoded.fetch_add(1, Ordering::Relaxed);
+            format.encode_families(&merge_hosts(hosts, MetricsCollector::truenas_families))
+        };
+        // Until every host has published, its series come straight from the collectors
+        let Some(snapshots) = hosts
+            .iter()
+            .map(|(host, metrics)| Some((host.to_string(), metrics.snapshot_number()?)))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return encode();
+        };
+
+        let mut cached = self.cached.lock().expect("rendering lock poisoned");
+        if cached
+            .as_ref()
+            .is_none_or(|cached| cached.snapshots != snapshots)
+        {
+            *cached = Some(CombinedSnapshot {
+                snapshots,
+                rendered: Default::default(),
+            });
+        }
+        let cell = &cached.as_ref().expect("rendering just cached").rendered[format as usize];
+        if let Some(rendered) = cell.get() {
+            return Ok(rendered.clone());
+        }
+        let rendered = encode()?;
+        Ok(cell.get_or_init(|| rendered).clone())
+    }
+}
+
+/// Merge the families `gather` returns for each host, adding the `host` label
+fn merge_hosts(
+    hosts: &[(&str, &MetricsCollector)],
+    gather: impl Fn(&MetricsCollector) -> Vec<MetricFamily>,
+) -> Vec<MetricFamily> {
+    let mut merged: Vec<MetricFamily> = Vec::new();
+    for (host, metrics) in hosts {
+        for mut family in gather(metrics) {
+            let mut series = family.take_metric();
+            for metric in series.iter_mut() {
+                add_label(metric, "host", host);
+            }
+            match merged
+                .iter_mut()
+                .find(|current| current.name() == family.name())
+            {
+                Some(current) => current.mut_metric().extend(series),
+                None => {
+                    family.set_metric(series);
+                    merged.push(family);
+                }
+            }
+        }
+    }
+    merged.sort_by(|a, b| a.name().cmp(b.name()));
+    merged
+}
+
+/// Add a label, keeping the labels sorted by name
+fn add_label(metric: &mut Metric, name: &str, value: &str) {
+    let mut label = LabelPair::default();
+    label.set_name(name.to_string());
+    label.set_value(value.to_string());
+    let labels = &mut metric.label;
+    let position = labels
+        .iter()
+        .position(|existing| existing.name() > name)
+        .unwrap_or(labels.len());
+    labels.insert(position, label);
+}
+
+fn series_key(family: &MetricFamily, metric: &Metric) -> SeriesKey {
+    let labels = metric
+        .get_label()
+        .iter()
+        .map(|label| (label.name().to_string(), label.value().to_string()))
+        .collect();
+    (family.name().to_string(), labels)
+}
+
 impl Default for MetricsCollector {
     fn default() -> Self {
         Self::new().expect("Failed to create metrics collector")
@@ -11,161 +11,893 @@
 //! # Endpoints
 //!
 //! - `GET /` - HTML landing page with links to metrics and health
-//! - `GET /metrics` - Prometheus metrics in text format
+//! - `GET /metrics` - Prometheus metrics in text format, or OpenMetrics if the `Accept`
+//!   header asks for it, gzip compressed if accepted (see [`crate::exposition`])
 //! - `GET /health` - Health check (returns 200 if TrueNAS is reachable, 503 otherwise)
+//! - `GET /probe?target=<host>&module=<name>` - Metrics of any TrueNAS system (see below)
+//! - `POST /-/reload` - Reload the configuration (see below)
+//!
+//! With `web_config_file` set, endpoints can be served over HTTPS and require basic auth
+//! or bearer tokens (see [`crate::web`]).
 //!
 //! # Metrics Collection
 //!
-//! The collection loop runs every N seconds (configured via `scrape_interval_seconds`) and:
-//! 1. Queries all enabled TrueNAS API endpoints
+//! The collection loop wakes up whenever a collector is due (every `scrape_interval_seconds`
+//! unless overridden in `[metrics.intervals]`, see [`collectors::schedule`]) and:
+//! 1. Queries the TrueNAS API endpoints of the enabled collectors that are due (see
+//!    [`collectors::registry`])
 //! 2. Updates Prometheus metrics with the latest values
-//! 3. Sets `truenas_up` to 1 if any query succeeds, 0 if all fail
+//! 3. Sets `truenas_up` to 1 if any query succeeds, 0 if all fail or the cycle times out
+//! 4. Publishes the updated metrics, so a scrape never sees a cycle half applied
+//!
+//! Collectors rebuild the series they own on every successful run, so objects that no
+//! longer exist on TrueNAS disappear, after `stale_series_grace_seconds` if set.
+//!
+//! With `subscribe_events` enabled, alert, pool, service and app metrics are instead kept
+//! current by a separate task driven by TrueNAS push events (see [`collectors::events`]),
+//! and the polling loop skips those collectors.
+//!
+//! With `collection_mode = "on_scrape"` there is no loop: each `/metrics` request runs a
+//! cycle first, unless the last one finished less than `min_refresh_age_seconds` ago.
+//! Concurrent scrapes share one cycle. Either way, the age of the served metrics is
+//! exported as `truenas_exporter_cache_age_seconds` and sent in the `Age` header.
+//!
+//! While the connection circuit breaker is open (TrueNAS failed several consecutive
+//! connection attempts), cycles are skipped and `truenas_up` stays 0.
+//!
+//! # Probing Multiple Systems
+//!
+//! Like the Prometheus blackbox exporter, `/probe` collects from the system named by the
+//! `target` parameter, using the connection and auth settings of a module from
+//! `[probe.modules]` (`default` if no `module` is given). Only modules configured there
+//! are served, and only for the targets each one lists, so a module's credentials are
+//! never sent to a system named by whoever can reach the exporter. Each target keeps
+//! its own authenticated connection and metrics between probes, and is collected on
+//! scrape as in `on_scrape` mode. Targets not probed for `idle_timeout_seconds` are
+//! dropped, and at most `max_targets` are kept at once.
+//!
+//! Without a `[truenas]` host the exporter only collects for `/probe`.
+//!
+//! # Multiple Hosts
+//!
+//! With a `[[truenas]]` list, every host gets its own connection, metrics and collection
+//! loop (or on-scrape refresh), so hosts are collected in parallel and one unreachable
+//! NAS does not hold up the others. `/metrics` serves all of them, with a `host` label on
+//! every series, including `truenas_up`.
+//!
+//! # Shutdown
+//!
+//! On SIGTERM or SIGINT the collection tasks stop, the listener stops accepting
+//! connections, in-flight scrapes are answered and every TrueNAS WebSocket is closed, so
+//! no authenticated session is left behind on the NAS. The exporter exits once that is
+//! done, or after `shutdown_timeout_seconds` at the latest.
+//!
+//! # Configuration Reload
+//!
+//! Started with a [`ConfigSource`] ([`start_with_reload`]), the exporter loads its
+//! configuration again on SIGHUP, on `POST /-/reload` and when the file changes. A valid
+//! configuration is swapped in as a whole: collection tasks restart with the new
+//! collectors and intervals, while hosts whose connection settings are unchanged keep
+//! their authenticated connection and metrics. An invalid one is rejected and the
+//! running configuration stays in use. Either way the outcome is exported as
+//! `truenas_exporter_config_last_reload_successful`. `[server]` settings only change on
+//! restart.
 //!
 //! # Error Handling
 //!
 //! Individual API failures are logged as warnings but don't stop the collection loop.
 //! This ensures partial metrics are still exposed even if some APIs are unavailable.
 
-use crate::collectors::{self, CollectionContext, CollectionStatus};
-use crate::config::Config;
-use crate::metrics::MetricsCollector;
+use crate::collectors::schedule::Schedule;
+use crate::collectors::{
+    self, CollectionContext, CollectionStatus, Collector, NamedCollector, Registry,
+};
+use crate::config::{
+    CollectionMode, Config, ConfigSource, MetricsConfig, TrueNasConfig, DEFAULT_PROBE_MODULE,
+};
+use crate::exposition::{Compression, Format};
+use crate::metrics::{HostsRendering, MetricsCollector};
 use crate::truenas::TrueNasClient;
+use crate::web::WebConfigFile;
 use axum::{
-    extract::State,
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Router,
 };
-use std::sync::Arc;
-use tokio::time::{interval, Duration};
-use tracing::{error, info};
+use futures_util::future::join_all;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Instant, SystemTime};
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
+
+/// How often the configuration files are checked for changes
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Clone)]
 struct AppState {
+    /// Configuration, collectors and targets in use, replaced as a whole on reload
+    active: Arc<RwLock<Arc<Active>>>,
+    /// Systems collected for `/probe`, by module and target
+    probes: Arc<std::sync::Mutex<HashMap<(String, String), ProbedTarget>>>,
+    /// Collection and event tasks of the active targets
+    tasks: Arc<std::sync::Mutex<Vec<JoinHandle<()>>>>,
+    /// Where the configuration is reloaded from; `None` if it cannot be
+    source: Option<Arc<ConfigSource>>,
+    /// Outcome of the last reload, held while reloading
+    reload: Arc<std::sync::Mutex<ReloadStatus>>,
+}
+
+impl AppState {
+    fn active(&self) -> Arc<Active> {
+        self.active
+            .read()
+            .expect("active config lock poisoned")
+            .clone()
+    }
+
+    fn reload_status(&self) -> ReloadStatus {
+        *self.reload.lock().expect("reload lock poisoned")
+    }
+}
+
+/// Everything built from one configuration
+struct Active {
     config: Config,
+    collectors: Registry,
+    /// The `[truenas]` systems served on `/metrics`
+    targets: Vec<Target>,
+    /// `/metrics` of labelled targets, reused until one of them publishes
+    rendering: HostsRendering,
+}
+
+impl Active {
+    /// Collectors and targets for `config`
+    ///
+    /// Targets of `previous` whose connection settings are unchanged keep their
+    /// connection and metrics; the others get new ones.
+    fn new(config: Config, previous: Option<&Active>) -> anyhow::Result<Self> {
+        let collectors = Registry::new(&config.metrics);
+        let on_scrape = config.metrics.collection_mode == CollectionMode::OnScrape;
+
+        // Series of collectors that were switched off would otherwise stay forever
+        let disabled = previous.is_some_and(|previous| {
+            previous.collectors.enabled().any(|old| {
+                !collectors
+                    .enabled()
+                    .any(|collector| collector.name() == old.name())
+            })
+        });
+
+        let mut targets = Vec::new();
+        for truenas in config.truenas.hosts() {
+            let local = collects(truenas);
+            let events = local && config.metrics.subscribe_events;
+            // Collect when /metrics is requested
+            let schedule =
+                (local && on_scrape).then(|| build_schedule(&config.metrics, &collectors, true));
+            let reused = previous
+                .and_then(|previous| previous.targets.iter().find(|t| *t.truenas == *truenas));
+            let target = match reused {
+                Some(target) => {
+                    if disabled {
+                        target.metrics.reset();
+                    }
+                    target.reconfigured(events, schedule)
+                }
+                None => Target::new(truenas.clone(), events, schedule)?,
+            };
+            targets.push(target);
+        }
+
+        Ok(Self {
+            config,
+            collectors,
+            targets,
+            rendering: HostsRendering::default(),
+        })
+    }
+}
+
+/// Whether there is anything to collect: a host, or recorded responses to replay
+fn collects(truenas: &TrueNasConfig) -> bool {
+    !truenas.host.is_empty() || truenas.replay_dir.is_some()
+}
+
+/// Outcome of the last configuration reload
+#[derive(Debug, Clone, Copy)]
+struct ReloadStatus {
+    successful: bool,
+    /// When the configuration was last loaded successfully, at startup or by a reload
+    last_success: SystemTime,
+}
+
+/// A TrueNAS system with its own connection and metrics
+#[derive(Clone)]
+struct Target {
+    truenas: Arc<TrueNasConfig>,
     metrics: MetricsCollector,
     client: Arc<TrueNasClient>,
+    /// Event-driven collectors are kept current by the event task instead of polling
+    events: bool,
+    /// Set when collecting on scrape; held while a scrape-triggered collection runs
+    refresh: Option<Arc<Mutex<ScrapeRefresh>>>,
+}
+
+impl Target {
+    /// Target with a fresh client and metrics; `schedule` makes it collect on scrape
+    fn new(
+        truenas: TrueNasConfig,
+        events: bool,
+        schedule: Option<Schedule>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            metrics: MetricsCollector::new()?,
+            client: Arc::new(TrueNasClient::new(truenas.clone())),
+            truenas: Arc::new(truenas),
+            events,
+            refresh: schedule.map(ScrapeRefresh::shared),
+        })
+    }
+
+    /// The same connection and metrics with new collection settings
+    fn reconfigured(&self, events: bool, schedule: Option<Schedule>) -> Self {
+        Self {
+            truenas: self.truenas.clone(),
+            metrics: self.metrics.clone(),
+            client: self.client.clone(),
+            events,
+            refresh: schedule.map(ScrapeRefresh::shared),
+        }
+    }
+}
+
+/// A probe target and when it was last probed
+struct ProbedTarget {
+    target: Target,
+    last_probe: Instant,
+}
+
+/// Query parameters of `/probe`
+#[derive(Debug, Deserialize)]
+struct ProbeParams {
+    target: Option<String>,
+    module: Option<String>,
+}
+
+/// Schedule and freshness of on-scrape collections
+struct ScrapeRefresh {
+    schedule: Schedule,
+    last_refresh: Option<Instant>,
 }
 
+impl ScrapeRefresh {
+    fn shared(schedule: Schedule) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            schedule,
+            last_refresh: None,
+        }))
+    }
+}
+
+/// Run the exporter until SIGTERM or SIGINT, then shut down gracefully
 pub async fn start(config: Config) -> anyhow::Result<()> {
-    let metrics = MetricsCollector::new()?;
-    let client = Arc::new(TrueNasClient::new(config.truenas.clone()));
+    start_with_shutdown(config, None, shutdown_signal()).await
+}
 
+/// Run the exporter like [`start`], reloading the configuration from `source`
+///
+/// Reloads happen on SIGHUP, on `POST /-/reload` and when a watched file changes.
+pub async fn start_with_reload(config: Config, source: ConfigSource) -> anyhow::Result<()> {
+    start_with_shutdown(config, Some(source), shutdown_signal()).await
+}
+
+/// Run the exporter until `shutdown` completes, then shut down gracefully
+///
+/// Collection stops at once, the listener stops accepting connections, in-flight
+/// scrapes finish and every TrueNAS WebSocket is closed. Whatever is still running after
+/// `shutdown_timeout_seconds` is abandoned.
+pub async fn start_with_shutdown(
+    config: Config,
+    source: Option<ConfigSource>,
+    shutdown: impl Future<Output = ()> + Send,
+) -> anyhow::Result<()> {
+    config.validate()?;
+    let active = Arc::new(Active::new(config.clone(), None)?);
+    if !active
+        .targets
+        .iter()
+        .any(|target| collects(&target.truenas))
+    {
+        info!("No TrueNAS host configured; only /probe collects metrics");
+    }
+    let reloadable = source.is_some();
     let state = AppState {
-        config: config.clone(),
-        metrics: metrics.clone(),
-        client: client.clone(),
+        active: Arc::new(RwLock::new(active.clone())),
+        probes: Arc::default(),
+        tasks: Arc::new(std::sync::Mutex::new(spawn_tasks(&active))),
+        source: source.map(Arc::new),
+        reload: Arc::new(std::sync::Mutex::new(ReloadStatus {
+            successful: true,
+            last_success: SystemTime::now(),
+        })),
+    };
+    let reload_triggers = if reloadable {
+        spawn_reload_triggers(&state)
+    } else {
+        Vec::new()
     };
-
-    // Start background metrics collection
-    let collection_state = state.clone();
-    tokio::spawn(async move {
-        collect_metrics_loop(collection_state).await;
-    });
 
     // Build the router
     let app = Router::new()
         .route("/", get(root_handler))
         .route("/metrics", get(metrics_handler))
-        .route("/health", get(health_handler))
-        .with_state(state);
+        .route("/probe", get(probe_handler))
+        .route("/health", get(health_handler));
+    let app = if reloadable {
+        app.route("/-/reload", post(reload_handler))
+    } else {
+        app
+    };
+    let app = app.with_state(state.clone());
+
+    // Optional HTTPS and authentication
+    let web = config
+        .server
+        .web_config_file
+        .as_deref()
+        .map(WebConfigFile::open)
+        .transpose()?
+        .map(Arc::new);
+    if let Some(web) = &web {
+        tokio::spawn(WebConfigFile::watch(Arc::downgrade(web)));
+    }
+    let app = match &web {
+        Some(web) => app.layer(middleware::from_fn_with_state(
+            web.clone(),
+            crate::web::require_auth,
+        )),
+        None => app,
+    };
 
     // Start the server
     let addr = format!("{}:{}", config.server.addr, config.server.port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let scheme = match &web {
+        Some(web) if web.tls_enabled() => "https",
+        _ => "http",
+    };
 
     info!("Metrics server listening on {}", addr);
-    info!("Metrics available at http://{}/metrics", addr);
+    info!("Metrics available at {}://{}/metrics", scheme, addr);
 
-    axum::serve(listener, app).await?;
+    let (stop, stopped) = oneshot::channel::<()>();
+    let stopped = async move {
+        let _ = stopped.await;
+    };
+    let server = async move {
+        match web {
+            Some(web) if web.tls_enabled() => {
+                crate::web::serve_tls(listener, app, web, stopped).await
+            }
+            _ => Ok(axum::serve(listener, app)
+                .with_graceful_shutdown(stopped)
+                .await?),
+        }
+    };
+    tokio::pin!(server);
+    tokio::pin!(shutdown);
+    tokio::select! {
+        result = &mut server => return result,
+        _ = &mut shutdown => {}
+    }
 
+    let timeout = Duration::from_secs(state.active().config.server.shutdown_timeout_seconds);
+    info!("Shutting down, waiting up to {}s", timeout.as_secs());
+    for task in &reload_triggers {
+        task.abort();
+    }
+    for task in state.tasks.lock().expect("task lock poisoned").iter() {
+        task.abort();
+    }
+    let _ = stop.send(());
+    let drained = tokio::time::timeout(timeout, async {
+        if let Err(e) = server.await {
+            error!("Server error during shutdown: {}", e);
+        }
+        close_connections(&state).await;
+    })
+    .await;
+    match drained {
+        Ok(()) => info!("Shutdown complete"),
+        Err(_) => warn!("Shutdown did not finish within {}s", timeout.as_secs()),
+    }
     Ok(())
 }
 
-async fn collect_metrics_loop(state: AppState) {
-    let mut ticker = interval(Duration::from_secs(
-        state.config.metrics.scrape_interval_seconds,
-    ));
+/// Start the event and collection tasks of the targets that have something to collect
+fn spawn_tasks(active: &Arc<Active>) -> Vec<JoinHandle<()>> {
+    let metrics = &active.config.metrics;
+    let mut tasks = Vec::new();
+    for target in active.targets.iter().filter(|t| collects(&t.truenas)) {
+        // Keep event-driven collections current between polling cycles
+        if target.events {
+            let collections = collectors::events::event_collections(metrics);
+            let resync = Duration::from_secs(metrics.event_resync_interval_seconds);
+            let grace = Duration::from_secs(metrics.stale_series_grace_seconds);
+            tasks.push(tokio::spawn(collectors::events::run(
+                target.client.clone(),
+                target.metrics.clone(),
+                collections,
+                resync,
+                grace,
+            )));
+        }
 
-    loop {
-        ticker.tick().await;
+        // Start background metrics collection; each host has its own loop
+        if metrics.collection_mode == CollectionMode::Background {
+            tasks.push(tokio::spawn(collect_metrics_loop(
+                active.clone(),
+                target.clone(),
+            )));
+        }
+    }
+    tasks
+}
 
-        if let Err(e) = collect_metrics(&state).await {
-            error!("Failed to collect metrics: {}", e);
-            state.metrics.up.set(0.0);
-        } else {
-            state.metrics.up.set(1.0);
+/// Wait for SIGTERM (as sent by `docker stop`) or SIGINT (Ctrl-C)
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
     }
 }
 
-async fn collect_metrics(state: &AppState) -> anyhow::Result<()> {
-    info!("Collecting metrics from TrueNAS");
+/// Close the WebSocket of every host and probe target, ending their TrueNAS sessions
+async fn close_connections(state: &AppState) {
+    let mut clients: Vec<Arc<TrueNasClient>> = state
+        .active()
+        .targets
+        .iter()
+        .map(|target| target.client.clone())
+        .collect();
+    clients.extend(
+        state
+            .probes
+            .lock()
+            .expect("probe targets lock poisoned")
+            .values()
+            .map(|probed| probed.target.client.clone()),
+    );
+    join_all(clients.iter().map(|client| client.close())).await;
+}
 
-    let ctx = CollectionContext {
-        client: &state.client,
-        metrics: &state.metrics,
-        config: &state.config.metrics,
-    };
+/// Reload on SIGHUP and whenever a watched configuration file changes
+fn spawn_reload_triggers(state: &AppState) -> Vec<JoinHandle<()>> {
+    let mut tasks = Vec::new();
 
-    let mut any_success = false;
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::hangup()) {
+            Ok(mut hangup) => {
+                let state = state.clone();
+                tasks.push(tokio::spawn(async move {
+                    while hangup.recv().await.is_some() {
+                        info!("Received SIGHUP, reloading configuration");
+                        let _ = reload(&state);
+                    }
+                }));
+            }
+            Err(e) => error!("Failed to listen for SIGHUP: {}", e),
+        }
+    }
 
-    // Helper macro to track success
-    macro_rules! collect {
-        ($collector:expr) => {
-            match $collector.await? {
-                CollectionStatus::Success => any_success = true,
-                CollectionStatus::Failed => { /* Already logged */ }
+    if let Some(source) = state.source.clone() {
+        let state = state.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut modified = source.modified();
+            let mut poll = tokio::time::interval(CONFIG_WATCH_INTERVAL);
+            poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                poll.tick().await;
+                let current = source.modified();
+                if current != modified {
+                    modified = current;
+                    info!("Configuration file changed, reloading");
+                    let _ = reload(&state);
+                }
             }
+        }));
+    }
+
+    tasks
+}
+
+/// Load the configuration again and switch to it
+///
+/// An invalid configuration is rejected as a whole and the running one stays in use;
+/// either way the outcome is exported as `truenas_exporter_config_last_reload_successful`.
+fn reload(state: &AppState) -> anyhow::Result<()> {
+    let Some(source) = &state.source else {
+        anyhow::bail!("The configuration cannot be reloaded");
+    };
+    let mut status = state.reload.lock().expect("reload lock poisoned");
+    let result = source.load().and_then(|config| {
+        config.validate()?;
+        activate(state, config)
+    });
+    match &result {
+        Ok(()) => {
+            *status = ReloadStatus {
+                successful: true,
+                last_success: SystemTime::now(),
+            };
+            info!("Configuration reloaded");
+        }
+        Err(e) => {
+            status.successful = false;
+            error!(
+                "Failed to reload configuration, keeping the current one: {:#}",
+                e
+            );
+        }
+    }
+    result
+}
+
+/// Switch to `config`, replacing the collection tasks and retiring unused connections
+fn activate(state: &AppState, config: Config) -> anyhow::Result<()> {
+    let previous = state.active();
+    if config.server != previous.config.server {
+        warn!("Changes to [server] settings take effect after a restart");
+    }
+    let active = Arc::new(Active::new(config, Some(&previous))?);
+
+    // Stop the old tasks before new ones collect from the same targets
+    {
+        let mut tasks = state.tasks.lock().expect("task lock poisoned");
+        for task in tasks.drain(..) {
+            task.abort();
+        }
+        *tasks = spawn_tasks(&active);
+    }
+    *state.active.write().expect("active config lock poisoned") = active.clone();
+
+    // Close connections whose settings changed or whose host was removed
+    let mut retired: Vec<Arc<TrueNasClient>> = previous
+        .targets
+        .iter()
+        .filter(|old| {
+            !active
+                .targets
+                .iter()
+                .any(|target| Arc::ptr_eq(&target.client, &old.client))
+        })
+        .map(|old| old.client.clone())
+        .collect();
+
+    // Keep probe targets whose module is unchanged, with the new collection settings
+    let mut probes = state.probes.lock().expect("probe targets lock poisoned");
+    probes.retain(|(module, address), probed| {
+        if active.config.probe_module(module, address).as_ref() == Some(&*probed.target.truenas) {
+            probed.target = probed.target.reconfigured(
+                false,
+                Some(build_schedule(
+                    &active.config.metrics,
+                    &active.collectors,
+                    true,
+                )),
+            );
+            return true;
+        }
+        info!(
+            "Dropping probe target {} (module {} changed or no longer allows it)",
+            address, module
+        );
+        retired.push(probed.target.client.clone());
+        false
+    });
+
+    for client in retired {
+        tokio::spawn(async move { client.close().await });
+    }
+    Ok(())
+}
+
+/// Schedule for the registered collectors
+///
+/// When collecting on scrape, collectors without their own entry in `[metrics.intervals]`
+/// run on every refresh.
+fn build_schedule(metrics: &MetricsConfig, collectors: &Registry, on_scrape: bool) -> Schedule {
+    let mut schedule = Schedule::new(metrics);
+    for collector in collectors.all() {
+        let interval = if on_scrape && !metrics.intervals.contains_key(collector.name()) {
+            Duration::ZERO
+        } else {
+            collector.interval()
         };
+        schedule.set_interval(collector.name(), interval);
     }
+    schedule
+}
+
+async fn collect_metrics_loop(active: Arc<Active>, target: Target) {
+    let mut schedule = build_schedule(&active.config.metrics, &active.collectors, false);
+    let idle_interval = Duration::from_secs(active.config.metrics.scrape_interval_seconds);
 
-    // Collect pool metrics
-    if state.config.metrics.collect_pool_metrics {
-        collect!(collectors::collect_pool_metrics(&ctx));
-        collect!(collectors::collect_dataset_metrics(&ctx));
+    loop {
+        run_cycle(&active, &target, &mut schedule).await;
+
+        // Sleep until the next collector is due; with none scheduled, still refresh `up`
+        let wakeup = schedule
+            .next_wakeup()
+            .unwrap_or_else(|| Instant::now() + idle_interval);
+        tokio::time::sleep_until(wakeup.into()).await;
     }
+}
 
-    // Collect share metrics
-    collect!(collectors::collect_share_metrics(&ctx));
+/// The probe target for `module` and `address`, connecting on first use
+///
+/// Returns `None` if `max_targets` are already connected. Targets not probed within
+/// `idle_timeout_seconds` are dropped and their connections closed.
+fn probe_target(
+    state: &AppState,
+    active: &Active,
+    module: &str,
+    address: &str,
+) -> anyhow::Result<Option<Target>> {
+    let now = Instant::now();
+    let idle_timeout = Duration::from_secs(active.config.probe.idle_timeout_seconds);
+    let mut probes = state.probes.lock().expect("probe targets lock poisoned");
 
-    // Collect data protection metrics
-    collect!(collectors::collect_cloud_sync_metrics(&ctx));
-    collect!(collectors::collect_snapshot_metrics(&ctx));
+    probes.retain(|(module, address), probed| {
+        if now.duration_since(probed.last_probe) < idle_timeout {
+            return true;
+        }
+        info!("Dropping idle probe target {} (module {})", address, module);
+        let client = probed.target.client.clone();
+        tokio::spawn(async move { client.close().await });
+        false
+    });
 
-    // Collect alerts
-    collect!(collectors::collect_alert_metrics(&ctx));
+    let key = (module.to_string(), address.to_string());
+    if let Some(probed) = probes.get_mut(&key) {
+        probed.last_probe = now;
+        return Ok(Some(probed.target.clone()));
+    }
 
-    // Collect system metrics
-    if state.config.metrics.collect_system_metrics {
-        collect!(collectors::collect_system_info_metrics(&ctx));
-        collect!(collectors::collect_system_reporting_metrics(&ctx));
+    let Some(truenas) = active.config.probe_module(module, address) else {
+        anyhow::bail!("module {} does not allow target {}", module, address);
+    };
+    if probes.len() >= active.config.probe.max_targets {
+        warn!(
+            "Refusing probe target {} (module {}): {} targets already connected",
+            address,
+            module,
+            probes.len()
+        );
+        return Ok(None);
     }
+    info!("Adding probe target {} (module {})", address, module);
+    let target = Target::new(
+        truenas,
+        false,
+        Some(build_schedule(
+            &active.config.metrics,
+            &active.collectors,
+            true,
+        )),
+    )?;
+    probes.insert(
+        key,
+        ProbedTarget {
+            target: target.clone(),
+            last_probe: now,
+        },
+    );
+    Ok(Some(target))
+}
 
-    // Collect disk metrics
-    collect!(collectors::collect_disk_metrics(&ctx));
-    collect!(collectors::collect_smart_metrics(&ctx));
+/// Collect for a scrape unless the last collection is younger than `min_refresh_age_seconds`
+///
+/// Scrapes arriving while a collection runs wait for it instead of starting their own.
+/// The collection runs in its own task, so a scrape that gives up does not abort it.
+async fn refresh_on_scrape(active: &Arc<Active>, target: &Target) {
+    let Some(refresh) = target.refresh.clone() else {
+        return;
+    };
+    let arrived = Instant::now();
+    let task_active = active.clone();
+    let task_target = target.clone();
+    let task = tokio::spawn(async move {
+        let mut refresh = refresh.lock().await;
+        // A collection that finished while this scrape waited is as fresh as a new one
+        let min_age = Duration::from_secs(task_active.config.metrics.min_refresh_age_seconds);
+        if refresh
+            .last_refresh
+            .is_some_and(|last| last > arrived || last.elapsed() < min_age)
+        {
+            return;
+        }
+        run_cycle(&task_active, &task_target, &mut refresh.schedule).await;
+        refresh.last_refresh = Some(Instant::now());
+    });
+    if let Err(e) = task.await {
+        error!("On-scrape collection failed: {}", e);
+    }
+}
 
-    // Collect application metrics
-    collect!(collectors::collect_app_metrics(&ctx));
+/// Run the collectors that are due, update `truenas_up` and publish the result
+async fn run_cycle(active: &Active, target: &Target, schedule: &mut Schedule) {
+    // Don't hammer a NAS that is known to be down; the backoff decides when to retry
+    if target.client.circuit_open() {
+        debug!(
+            "TrueNAS {} unreachable, skipping collection cycle",
+            target.truenas.host
+        );
+        schedule.postpone_due(Instant::now());
+        target.metrics.up.set(0.0);
+        return;
+    }
+
+    let cycle_timeout = Duration::from_secs(
+        target
+            .truenas
+            .timeouts
+            .cycle_seconds
+            .unwrap_or(active.config.metrics.scrape_interval_seconds),
+    );
+    match tokio::time::timeout(cycle_timeout, collect_metrics(active, target, schedule)).await {
+        Ok(Ok(0)) => {}
+        Ok(Ok(_)) => target.metrics.up.set(1.0),
+        Ok(Err(e)) => {
+            error!(
+                "Failed to collect metrics from {}: {}",
+                target.truenas.host, e
+            );
+            target.metrics.up.set(0.0);
+        }
+        Err(_) => {
+            error!(
+                "Metrics collection from {} did not finish within {}s",
+                target.truenas.host,
+                cycle_timeout.as_secs()
+            );
+            target.metrics.up.set(0.0);
+        }
+    }
+
+    // Serve the finished cycle to scrapes as a whole
+    target.metrics.publish(Duration::from_secs(
+        active.config.metrics.stale_series_grace_seconds,
+    ));
+}
 
-    // Collect network interface metrics
-    collect!(collectors::collect_network_interface_metrics(&ctx));
+/// Run the collectors that are due and return how many ran
+async fn collect_metrics(
+    active: &Active,
+    target: &Target,
+    schedule: &mut Schedule,
+) -> anyhow::Result<usize> {
+    let now = Instant::now();
 
-    // Collect service status
-    collect!(collectors::collect_service_metrics(&ctx));
+    let ctx = CollectionContext {
+        client: &target.client,
+        metrics: &target.metrics,
+        config: &active.config.metrics,
+    };
 
-    // Collect boot pool, NFS clients (count + per-client detail), iSCSI sessions
-    collect!(collectors::collect_boot_pool_metrics(&ctx));
-    collect!(collectors::collect_nfs_metrics(&ctx));
-    collect!(collectors::collect_iscsi_client_count(&ctx));
+    // Alerts, pools, services and apps are kept current by the event task when enabled
+    let events = target.events;
+
+    // Collectors whose API the connected release lacks are skipped
+    let supported = |collector: &dyn Collector| {
+        let Some(capability) = collector.capability() else {
+            return true;
+        };
+        let supported = target.client.supports(capability);
+        if !supported {
+            debug!(
+                "Skipping {} collector: {:?} not supported by TrueNAS {}",
+                collector.name(),
+                capability,
+                target
+                    .client
+                    .server_version()
+                    .map_or_else(|| "(unknown version)".to_string(), |v| v.to_string())
+            );
+        }
+        supported
+    };
+
+    // Queue the enabled collectors that are due; unsupported ones are rescheduled unrun.
+    // Queued ones count as failed until they succeed, also if the cycle times out.
+    let mut queue: Vec<NamedCollector<'_>> = Vec::new();
+    for collector in active.collectors.enabled() {
+        let name = collector.name();
+        if (events && collector.event_driven()) || !schedule.is_due(name, now) {
+            continue;
+        }
+        if supported(collector) {
+            schedule.mark_failed(name, now);
+            queue.push((name, collector.collect(&ctx)));
+        } else {
+            schedule.mark_run(name, now);
+        }
+    }
+
+    if queue.is_empty() {
+        return Ok(0);
+    }
+    let names: Vec<&str> = queue.iter().map(|(name, _)| *name).collect();
+    info!(
+        "Collecting metrics from TrueNAS {}: {}",
+        target.truenas.host,
+        names.join(", ")
+    );
+    let count = queue.len();
+
+    let results = collectors::run_collectors(
+        queue,
+        active.config.metrics.max_concurrent_collectors,
+        Duration::from_secs(active.config.metrics.collector_timeout_seconds),
+    )
+    .await;
+
+    // Record every collector's outcome before propagating a fatal error
+    let mut any_success = false;
+    let mut fatal = None;
+    for (name, result, duration) in results {
+        let success = matches!(result, Ok(CollectionStatus::Success));
+        target.metrics.record_collector_run(name, success, duration);
+        if success {
+            schedule.mark_run(name, now);
+        }
+        any_success |= success;
+        if let Err(e) = result {
+            fatal.get_or_insert(e);
+        }
+    }
+    if let Some(e) = fatal {
+        return Err(e);
+    }
 
     // If all queries failed, return error so truenas_up is set to 0
     if !any_success {
         anyhow::bail!("Failed to collect any metrics from TrueNAS - check authentication");
     }
 
-    Ok(())
+    Ok(count)
 }
 
 async fn root_handler() -> impl IntoResponse {
@@ -179,13 +911,168 @@ async fn root_handler() -> impl IntoResponse {
 </html>"#
 }
 
-async fn metrics_handler(State(state): State<AppState>) -> Response {
-    match state.metrics.render() {
-        Ok(metrics) => metrics.into_response(),
+async fn metrics_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let active = state.active();
+    // Hosts are collected in parallel; one slow NAS delays the scrape, not the others
+    join_all(
+        active
+            .targets
+            .iter()
+            .map(|target| refresh_on_scrape(&active, target)),
+    )
+    .await;
+    render_targets(
+        &active.targets,
+        active
+            .config
+            .truenas
+            .labelled()
+            .then_some(&active.rendering),
+        state.reload_status(),
+        &headers,
+    )
+}
+
+async fn probe_handler(
+    State(state): State<AppState>,
+    Query(params): Query<ProbeParams>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(address) = params.target.filter(|target| !target.is_empty()) else {
+        return (StatusCode::BAD_REQUEST, "Target parameter is missing").into_response();
+    };
+    let module = params.module.as_deref().unwrap_or(DEFAULT_PROBE_MODULE);
+
+    let active = state.active();
+    // Refused before anything connects, so the module's credentials stay put
+    let Some(allowed) = active.config.probe.modules.get(module) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown module {:?}", module),
+        )
+            .into_response();
+    };
+    if !allowed.allows(&address) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Target {:?} is not allowed for module {:?}",
+                address, module
+            ),
+        )
+            .into_response();
+    }
+    let target = match probe_target(&state, &active, module, &address) {
+        Ok(Some(target)) => target,
+        Ok(None) => {
+            return (StatusCode::SERVICE_UNAVAILABLE, "Too many probe targets").into_response()
+        }
+        Err(e) => {
+            error!("Failed to set up probe target {}: {}", address, e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error setting up probe target: {}", e),
+            )
+                .into_response();
+        }
+    };
+
+    refresh_on_scrape(&active, &target).await;
+    render_targets(
+        std::slice::from_ref(&target),
+        None,
+        state.reload_status(),
+        &headers,
+    )
+}
+
+async fn reload_handler(State(state): State<AppState>) -> Response {
+    match reload(&state) {
+        Ok(()) => (StatusCode::OK, "Configuration reloaded").into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to reload configuration: {:#}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// The targets' metrics, with an `Age` header
+///
+/// With `labelled`, every series carries the `host` it came from and the rendering is
+/// cached there. The `Age` header is
+/// that of the oldest metrics. The format and compression are negotiated from the
+/// scrape's `Accept` and `Accept-Encoding` headers.
+fn render_targets(
+    targets: &[Target],
+    labelled: Option<&HostsRendering>,
+    reload: ReloadStatus,
+    headers: &HeaderMap,
+) -> Response {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+    };
+    let format = Format::negotiate(header(header::ACCEPT));
+    let compression = Compression::negotiate(header(header::ACCEPT_ENCODING));
+
+    let mut age = None;
+    for target in targets {
+        target
+            .metrics
+            .update_connection_stats(&target.client.connection_stats());
+        target
+            .metrics
+            .update_build_info(target.client.server_version().as_ref());
+        target
+            .metrics
+            .update_config_reload(reload.successful, reload.last_success);
+        if let Some(target_age) = target.metrics.snapshot_age() {
+            target
+                .metrics
+                .exporter_cache_age_seconds
+                .set(target_age.as_secs_f64());
+            age = age.max(Some(target_age));
+        }
+    }
+
+    let rendered = match (targets, labelled) {
+        ([target], None) => target.metrics.render_as(format),
+        (_, rendering) => {
+            let hosts: Vec<(&str, &MetricsCollector)> = targets
+                .iter()
+                .map(|target| (target.truenas.host.as_str(), &target.metrics))
+                .collect();
+            rendering
+                .unwrap_or(&HostsRendering::default())
+                .render(&hosts, format)
+        }
+    };
+
+    let body = rendered.and_then(|metrics| Ok(compression.compress(metrics.into_bytes())?));
+    match body {
+        Ok(body) => {
+            let mut response =
+                ([(header::CONTENT_TYPE, format.content_type())], body).into_response();
+            let headers = response.headers_mut();
+            headers.insert(
+                header::VARY,
+                HeaderValue::from_static("Accept, Accept-Encoding"),
+            );
+            if let Some(encoding) = compression.content_encoding() {
+                headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+            }
+            // Standard HTTP cache age, in whole seconds
+            if let Some(age) = age {
+                headers.insert(header::AGE, HeaderValue::from(age.as_secs()));
+            }
+            response
+        }
         Err(e) => {
             error!("Failed to render metrics: {}", e);
             (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Error rendering metrics: {}", e),
             )
                 .into_response()
@@ -194,14 +1081,18 @@ async fn metrics_handler(State(state): State<AppState>) -> Response {
 }
 
 async fn health_handler(State(state): State<AppState>) -> impl IntoResponse {
-    let up_value = state.metrics.up.get();
+    // Healthy while any configured NAS is reachable, or always when only probing
+    let active = state.active();
+    let mut polled = active
+        .targets
+        .iter()
+        .filter(|t| collects(&t.truenas))
+        .peekable();
+    let up = polled.peek().is_none() || polled.any(|target| target.metrics.up.get() > 0.0);
 
-    if up_value > 0.0 {
-        (axum::http::StatusCode::OK, "OK")
+    if up {
+        (StatusCode::OK, "OK")
     } else {
-        (
-            axum::http::StatusCode::SERVICE_UNAVAILABLE,
-            "TrueNAS API unreachable",
-        )
+        (StatusCode::SERVICE_UNAVAILABLE, "TrueNAS API unreachable")
     }
 }
@@ -0,0 +1,678 @@
+//! Fake TrueNAS Middleware for Tests
+//!
+//! [`FakeTrueNas`] is an in-process WebSocket server that speaks both the legacy DDP
+//! endpoint (`/websocket`) and JSON-RPC 2.0 (`/api/current`) closely enough for the real
+//! [`ConnectionManager`](crate::truenas::ConnectionManager) to connect, authenticate and
+//! query it. Only available with the `test-support` feature.
+//!
+//! # Behaviour
+//!
+//! - Every login succeeds unless [`FakeTrueNas::require_credentials`] or
+//!   [`FakeTrueNas::reject_auth`] is set; `auth.login_with_token` only accepts tokens
+//!   issued by `auth.generate_token`
+//! - Calls made before logging in are answered with `ENOTAUTHENTICATED`, and calls in the
+//!   other endpoint's envelope with `EINVAL`
+//! - `system.version` reports `TrueNAS-25.04.0` unless scripted otherwise
+//! - Any other method must be scripted with [`FakeTrueNas::respond`] or
+//!   [`FakeTrueNas::respond_error`]; unscripted methods are answered with "method not found"
+//! - Subscriptions are acknowledged, followed by the events scripted with
+//!   [`FakeTrueNas::event_on_subscribe`]
+//!
+//! # Faults
+//!
+//! | Fault | Method |
+//! |-------|--------|
+//! | Slow or missing replies | [`FakeTrueNas::delay`] |
+//! | Rejected credentials | [`FakeTrueNas::reject_auth`], [`FakeTrueNas::require_credentials`] |
+//! | One-time password required | [`FakeTrueNas::require_otp`] |
+//! | Session expiry (`ENOTAUTHENTICATED`) | [`FakeTrueNas::expire_sessions`] |
+//! | No JSON-RPC 2.0 endpoint (HTTP 404) | [`FakeTrueNas::legacy_only`] |
+//! | Control and unsolicited frames around replies | [`FakeTrueNas::interleave_frames`] |
+//! | Dropped connections | [`FakeTrueNas::disconnect_all`], [`FakeTrueNas::disconnect_on`] |
+//! | Close frames | [`FakeTrueNas::close_on`] |
+//! | Half-open sockets | [`FakeTrueNas::stall_on`] |
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn example() {
+//! use serde_json::json;
+//! use truenas_exporter::test_support::FakeTrueNas;
+//!
+//! let nas = FakeTrueNas::start().await;
+//! nas.respond("pool.query", json!([{"name": "tank", "status": "ONLINE", "healthy": true}]));
+//! let config = nas.config();
+//! # }
+//! ```
+
+use crate::config::{ProtocolMode, TrueNasConfig};
+use futures_util::{SinkExt, StreamExt};
+use secrecy::SecretString;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Version reported by `system.version` unless scripted otherwise
+pub const DEFAULT_VERSION: &str = "TrueNAS-25.04.0";
+
+/// API key used by [`FakeTrueNas::config`]; any key is accepted unless credentials are required
+pub const API_KEY: &str = "fake-api-key";
+
+/// Payload of the pings sent by [`FakeTrueNas::interleave_frames`]
+const PING: &[u8] = b"are you there";
+
+const LOGIN_METHODS: [&str; 4] = [
+    "auth.login",
+    "auth.login_ex",
+    "auth.login_with_api_key",
+    "auth.login_with_token",
+];
+
+/// A scripted reply
+#[derive(Debug, Clone)]
+enum Reply {
+    Result(Value),
+    Error { errname: String, reason: String },
+}
+
+/// Script and fault settings shared by all connections
+#[derive(Debug, Default)]
+struct State {
+    replies: Mutex<HashMap<String, Reply>>,
+    delays: Mutex<HashMap<String, Duration>>,
+    disconnect_on: Mutex<HashSet<String>>,
+    calls: Mutex<Vec<String>>,
+    reject_auth: AtomicBool,
+    /// Bumped by `expire_sessions`; logins from an older epoch are no longer valid
+    session_epoch: AtomicU64,
+    connections: AtomicUsize,
+    /// Connections the client ended with a Close frame
+    closed: AtomicUsize,
+    /// Request path of every accepted connection
+    endpoints: Mutex<Vec<String>>,
+    legacy_only: AtomicBool,
+    credentials: Mutex<Option<Credentials>>,
+    otp_users: Mutex<HashSet<String>>,
+    tokens: Mutex<Vec<String>>,
+    subscription_events: Mutex<HashMap<String, Vec<Value>>>,
+    close_on: Mutex<HashMap<String, (u16, String)>>,
+    stall_on: Mutex<HashSet<String>>,
+    interleave_frames: AtomicBool,
+    pongs: AtomicUsize,
+}
+
+/// The only credentials accepted once [`FakeTrueNas::require_credentials`] is called
+#[derive(Debug, Clone)]
+struct Credentials {
+    api_key: String,
+    username: String,
+    password: String,
+}
+
+/// An in-process fake TrueNAS middleware server
+///
+/// The server runs until the value is dropped.
+pub struct FakeTrueNas {
+    addr: String,
+    state: Arc<State>,
+    disconnect: watch::Sender<u64>,
+    task: JoinHandle<()>,
+}
+
+impl FakeTrueNas {
+    /// Start a server on a free local port
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind fake TrueNAS server");
+        let addr = listener
+            .local_addr()
+            .expect("fake TrueNAS server has no address")
+            .to_string();
+        let state = Arc::new(State::default());
+        let (disconnect, _) = watch::channel(0);
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            let disconnect = disconnect.clone();
+            async move {
+                while let Ok((tcp, _)) = listener.accept().await {
+                    state.connections.fetch_add(1, Ordering::SeqCst);
+                    tokio::spawn(serve(tcp, state.clone(), disconnect.subscribe()));
+                }
+            }
+        });
+
+        Self {
+            addr,
+            state,
+            disconnect,
+            task,
+        }
+    }
+
+    /// `host:port` to connect to
+    pub fn host(&self) -> String {
+        self.addr.clone()
+    }
+
+    /// Plain-text JSON-RPC 2.0 connection settings for this server
+    pub fn config(&self) -> TrueNasConfig {
+        TrueNasConfig {
+            host: self.host(),
+            api_key: SecretString::from(API_KEY),
+            use_tls: false,
+            protocol: ProtocolMode::JsonRpc2,
+            ..Default::default()
+        }
+    }
+
+    /// Answer `method` with `result`
+    pub fn respond(&self, method: &str, result: Value) {
+        self.state
+            .replies
+            .lock()
+            .expect("script lock poisoned")
+            .insert(method.to_string(), Reply::Result(result));
+    }
+
+    /// Answer `method` with a middleware error
+    pub fn respond_error(&self, method: &str, errname: &str, reason: &str) {
+        self.state
+            .replies
+            .lock()
+            .expect("script lock poisoned")
+            .insert(
+                method.to_string(),
+                Reply::Error {
+                    errname: errname.to_string(),
+                    reason: reason.to_string(),
+                },
+            );
+    }
+
+    /// Hold replies to `method` back for `delay`
+    pub fn delay(&self, method: &str, delay: Duration) {
+        self.state
+            .delays
+            .lock()
+            .expect("script lock poisoned")
+            .insert(method.to_string(), delay);
+    }
+
+    /// Only accept this API key and username/password pair from now on
+    pub fn require_credentials(&self, api_key: &str, username: &str, password: &str) {
+        *self.state.credentials.lock().expect("script lock poisoned") = Some(Credentials {
+            api_key: api_key.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+    }
+
+    /// Ask `username` for a one-time password on `auth.login_ex`
+    pub fn require_otp(&self, username: &str) {
+        self.state
+            .otp_users
+            .lock()
+            .expect("script lock poisoned")
+            .insert(username.to_string());
+    }
+
+    /// Push `update` (a `collection_update` payload) after every subscription to
+    /// its collection
+    pub fn event_on_subscribe(&self, update: Value) {
+        let collection = update["collection"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        self.state
+            .subscription_events
+            .lock()
+            .expect("script lock poisoned")
+            .entry(collection)
+            .or_default()
+            .push(update);
+    }
+
+    /// Reject the JSON-RPC 2.0 endpoint with HTTP 404, like releases before 25.04, or
+    /// offer it again, like after an upgrade
+    pub fn legacy_only(&self, legacy_only: bool) {
+        self.state.legacy_only.store(legacy_only, Ordering::SeqCst);
+    }
+
+    /// Precede every reply with a Ping, a non-UTF-8 Binary frame, an unsolicited Pong
+    /// and an event frame without a request ID
+    pub fn interleave_frames(&self, interleave: bool) {
+        self.state
+            .interleave_frames
+            .store(interleave, Ordering::SeqCst);
+    }
+
+    /// Answer `method` with a Close frame instead of a reply
+    pub fn close_on(&self, method: &str, code: u16, reason: &str) {
+        self.state
+            .close_on
+            .lock()
+            .expect("script lock poisoned")
+            .insert(method.to_string(), (code, reason.to_string()));
+    }
+
+    /// Stop reading the connection once `method` is called, leaving it half-open
+    pub fn stall_on(&self, method: &str) {
+        self.state
+            .stall_on
+            .lock()
+            .expect("script lock poisoned")
+            .insert(method.to_string());
+    }
+
+    /// Reject (or accept again) every login attempt
+    pub fn reject_auth(&self, reject: bool) {
+        self.state.reject_auth.store(reject, Ordering::SeqCst);
+    }
+
+    /// Invalidate all current sessions; their next call gets `ENOTAUTHENTICATED`
+    pub fn expire_sessions(&self) {
+        self.state.session_epoch.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Drop every open connection without a Close frame
+    pub fn disconnect_all(&self) {
+        self.disconnect.send_modify(|generation| *generation += 1);
+    }
+
+    /// Drop the connection, without replying, whenever `method` is called
+    pub fn disconnect_on(&self, method: &str) {
+        self.state
+            .disconnect_on
+            .lock()
+            .expect("script lock poisoned")
+            .insert(method.to_string());
+    }
+
+    /// Methods called so far, in order of arrival
+    pub fn calls(&self) -> Vec<String> {
+        self.state.calls.lock().expect("call lock poisoned").clone()
+    }
+
+    /// Number of calls made to `method`
+    pub fn call_count(&self, method: &str) -> usize {
+        self.calls().iter().filter(|call| *call == method).count()
+    }
+
+    /// Number of WebSocket connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.state.connections.load(Ordering::SeqCst)
+    }
+
+    /// Request path of every connection so far (`/api/current` or `/websocket`)
+    pub fn endpoints(&self) -> Vec<String> {
+        self.state
+            .endpoints
+            .lock()
+            .expect("endpoint lock poisoned")
+            .clone()
+    }
+
+    /// Number of pongs received in answer to [`FakeTrueNas::interleave_frames`] pings
+    pub fn pongs(&self) -> usize {
+        self.state.pongs.load(Ordering::SeqCst)
+    }
+
+    /// Number of connections the client closed cleanly, with a Close frame
+    pub fn closed_connections(&self) -> usize {
+        self.state.closed.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for FakeTrueNas {
+    fn drop(&mut self) {
+        self.task.abort();
+        self.disconnect_all();
+    }
+}
+
+/// Handle one WebSocket connection
+// The handshake callback signature is dictated by tungstenite
+#[allow(clippy::result_large_err)]
+async fn serve(tcp: TcpStream, state: Arc<State>, mut disconnect: watch::Receiver<u64>) {
+    let mut path = String::new();
+    let legacy_only = state.legacy_only.load(Ordering::SeqCst);
+    let callback = |request: &Request, response: Response| {
+        path = request.uri().path().to_string();
+        if path == "/api/current" && legacy_only {
+            let mut rejection = ErrorResponse::new(Some("Not Found".to_string()));
+            *rejection.status_mut() = StatusCode::NOT_FOUND;
+            return Err(rejection);
+        }
+        Ok(response)
+    };
+    let accepted = tokio_tungstenite::accept_hdr_async(tcp, callback).await;
+    state
+        .endpoints
+        .lock()
+        .expect("endpoint lock poisoned")
+        .push(path.clone());
+    let Ok(ws) = accepted else {
+        return;
+    };
+    let legacy = path == "/websocket";
+    let (mut sink, mut source) = ws.split();
+
+    // Frames are written by one task so that delayed replies can overtake each other
+    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = rx.recv().await {
+            let close = matches!(frame, Message::Close(_));
+            if sink.send(frame).await.is_err() || close {
+                break;
+            }
+        }
+    });
+    let send = |tx: &mpsc::UnboundedSender<Message>, value: Value| {
+        let _ = tx.send(Message::Text(value.to_string().into()));
+    };
+
+    // Epoch of the last successful login on this connection
+    let mut session: Option<u64> = None;
+    let mut closing = false;
+
+    loop {
+        let text = tokio::select! {
+            msg = source.next() => match msg {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(_))) => {
+                    state.closed.fetch_add(1, Ordering::SeqCst);
+                    break;
+                }
+                Some(Ok(Message::Pong(data))) if data.as_ref() == PING => {
+                    state.pongs.fetch_add(1, Ordering::SeqCst);
+                    continue;
+                }
+                Some(Ok(_)) => continue,
+                _ => break,
+            },
+            _ = disconnect.changed() => break,
+        };
+        let Ok(request) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+
+        if request["msg"] == "connect" {
+            send(&tx, json!({"msg": "connected", "session": "fake"}));
+            continue;
+        }
+        if request["msg"] == "sub" {
+            send(&tx, json!({"msg": "ready", "subs": [request["id"]]}));
+            for update in subscription_events(&state, &request["name"]) {
+                send(&tx, event_envelope(legacy, update));
+            }
+            continue;
+        }
+
+        let id = request["id"].clone();
+        let method = request["method"].as_str().unwrap_or_default().to_string();
+        state
+            .calls
+            .lock()
+            .expect("call lock poisoned")
+            .push(method.clone());
+
+        if state
+            .disconnect_on
+            .lock()
+            .expect("script lock poisoned")
+            .contains(&method)
+        {
+            break;
+        }
+        if state
+            .stall_on
+            .lock()
+            .expect("script lock poisoned")
+            .contains(&method)
+        {
+            let _ = disconnect.changed().await;
+            break;
+        }
+        let close = state
+            .close_on
+            .lock()
+            .expect("script lock poisoned")
+            .get(&method)
+            .cloned();
+        if let Some((code, reason)) = close {
+            let _ = tx.send(Message::Close(Some(CloseFrame {
+                code: CloseCode::from(code),
+                reason: reason.into(),
+            })));
+            closing = true;
+            break;
+        }
+
+        // Each endpoint only understands its own envelope
+        let framed = if legacy {
+            request["msg"] == "method" && request.get("jsonrpc").is_none()
+        } else {
+            request["jsonrpc"] == "2.0"
+                && request.get("msg").is_none()
+                && request
+                    .get("params")
+                    .is_none_or(|params| params.is_array() || params.is_object())
+        };
+
+        let epoch = state.session_epoch.load(Ordering::SeqCst);
+        let reply = if !framed {
+            Reply::Error {
+                errname: "EINVAL".to_string(),
+                reason: "Invalid request for this endpoint".to_string(),
+            }
+        } else if LOGIN_METHODS.contains(&method.as_str()) {
+            let (result, accepted) = login(&state, &method, &request["params"]);
+            if accepted {
+                session = Some(epoch);
+            }
+            Reply::Result(result)
+        } else if session != Some(epoch) {
+            Reply::Error {
+                errname: "ENOTAUTHENTICATED".to_string(),
+                reason: "Not authenticated".to_string(),
+            }
+        } else {
+            scripted_reply(&state, &method)
+        };
+
+        // Events for a new subscription follow its acknowledgement
+        let events = if method == "core.subscribe" && matches!(reply, Reply::Result(_)) {
+            subscription_events(&state, &request["params"][0])
+        } else {
+            Vec::new()
+        };
+        let interleave = state.interleave_frames.load(Ordering::SeqCst);
+        let delay = state
+            .delays
+            .lock()
+            .expect("script lock poisoned")
+            .get(&method)
+            .copied();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+            if interleave {
+                let _ = tx.send(Message::Ping(PING.to_vec().into()));
+                let _ = tx.send(Message::Binary(vec![0xff, 0xfe].into()));
+                let _ = tx.send(Message::Pong(Vec::new().into()));
+                send(
+                    &tx,
+                    event_envelope(
+                        legacy,
+                        json!({"msg": "added", "collection": "test.noise", "fields": {}}),
+                    ),
+                );
+            }
+            send(&tx, envelope(legacy, &id, reply));
+            for update in events {
+                send(&tx, event_envelope(legacy, update));
+            }
+        });
+    }
+
+    if closing {
+        // Let the Close frame go out before the socket is dropped
+        drop(tx);
+        let _ = writer.await;
+    } else {
+        writer.abort();
+    }
+}
+
+/// Answer a login call: the result to send and whether it opened a session
+fn login(state: &State, method: &str, params: &Value) -> (Value, bool) {
+    let rejected = state.reject_auth.load(Ordering::SeqCst);
+    let credentials = state
+        .credentials
+        .lock()
+        .expect("script lock poisoned")
+        .clone();
+    let key_ok = |key: &Value| {
+        !rejected
+            && credentials
+                .as_ref()
+                .is_none_or(|required| *key == required.api_key)
+    };
+    let password_ok = |username: &Value, password: &Value| {
+        !rejected
+            && credentials.as_ref().is_none_or(|required| {
+                *username == required.username && *password == required.password
+            })
+    };
+
+    let accepted = match method {
+        "auth.login_with_api_key" => key_ok(&params[0]),
+        "auth.login" => password_ok(&params[0], &params[1]),
+        "auth.login_with_token" => {
+            !rejected
+                && state
+                    .tokens
+                    .lock()
+                    .expect("token lock poisoned")
+                    .iter()
+                    .any(|token| params[0] == *token)
+        }
+        _ => {
+            let request = &params[0];
+            let otp = request["username"].as_str().is_some_and(|username| {
+                state
+                    .otp_users
+                    .lock()
+                    .expect("script lock poisoned")
+                    .contains(username)
+            });
+            let response_type = match request["mechanism"].as_str() {
+                Some("PASSWORD_PLAIN") if otp && !rejected => "OTP_REQUIRED",
+                Some("PASSWORD_PLAIN")
+                    if password_ok(&request["username"], &request["password"]) =>
+                {
+                    "SUCCESS"
+                }
+                Some("API_KEY_PLAIN") if key_ok(&request["api_key"]) => "SUCCESS",
+                _ => "AUTH_ERR",
+            };
+            return (
+                json!({"response_type": response_type}),
+                response_type == "SUCCESS",
+            );
+        }
+    };
+    (json!(accepted), accepted)
+}
+
+/// Events scripted for a subscription to `collection`
+fn subscription_events(state: &State, collection: &Value) -> Vec<Value> {
+    collection
+        .as_str()
+        .and_then(|collection| {
+            state
+                .subscription_events
+                .lock()
+                .expect("script lock poisoned")
+                .get(collection)
+                .cloned()
+        })
+        .unwrap_or_default()
+}
+
+/// The reply to an authenticated call
+fn scripted_reply(state: &State, method: &str) -> Reply {
+    let scripted = state
+        .replies
+        .lock()
+        .expect("script lock poisoned")
+        .get(method)
+        .cloned();
+    match (scripted, method) {
+        (Some(reply), _) => reply,
+        (None, "system.version") => Reply::Result(json!(DEFAULT_VERSION)),
+        (None, "core.subscribe") => Reply::Result(json!("fake-subscription")),
+        (None, "auth.generate_token") => {
+            let mut tokens = state.tokens.lock().expect("token lock poisoned");
+            let token = format!("fake-token-{}", tokens.len() + 1);
+            tokens.push(token.clone());
+            Reply::Result(json!(token))
+        }
+        (None, method) => Reply::Error {
+            errname: "ENOMETHOD".to_string(),
+            reason: format!("Method {:?} not found", method),
+        },
+    }
+}
+
+/// Wrap a `collection_update` payload in the endpoint's envelope
+fn event_envelope(legacy: bool, update: Value) -> Value {
+    if legacy {
+        update
+    } else {
+        json!({"jsonrpc": "2.0", "method": "collection_update", "params": update})
+    }
+}
+
+/// Wrap a reply in the endpoint's envelope
+fn envelope(legacy: bool, id: &Value, reply: Reply) -> Value {
+    match (legacy, reply) {
+        (true, Reply::Result(result)) => json!({"msg": "result", "id": id, "result": result}),
+        (true, Reply::Error { errname, reason }) => json!({
+            "msg": "result",
+            "id": id,
+            "error": {"error": 22, "errname": errname, "reason": reason}
+        }),
+        (false, Reply::Result(result)) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        (false, Reply::Error { errname, reason }) => {
+            let code = if errname == "ENOMETHOD" {
+                -32601
+            } else {
+                -32001
+            };
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {
+                    "code": code,
+                    "message": "Method call error",
+                    "data": {"error": 22, "errname": errname, "reason": reason}
+                }
+            })
+        }
+    }
+}
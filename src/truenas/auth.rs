@@ -0,0 +1,206 @@
+//! Authentication Strategies
+//!
+//! Builds the login call for the configured [`AuthMethod`] and interprets the reply.
+//!
+//! # Login Methods
+//!
+//! | Strategy | Plain method | `login_ex` mechanism |
+//! |----------|--------------|----------------------|
+//! | API key | `auth.login_with_api_key` | `API_KEY_PLAIN` |
+//! | Username/password | `auth.login` | `PASSWORD_PLAIN` |
+//! | Token | `auth.login_with_token` | `TOKEN_PLAIN` |
+//!
+//! The token strategy logs in with credentials once, then obtains a token via
+//! `auth.generate_token` and uses it for subsequent reconnects. The token is renewed
+//! on the live connection once three quarters of its lifetime have passed.
+
+use crate::config::{AuthMethod, TrueNasConfig};
+use crate::error::{ExporterError, Result};
+use crate::truenas::types::JsonRpcResponse;
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// A login call and how to describe its credentials in errors
+#[derive(Debug)]
+pub struct LoginRequest {
+    pub method: &'static str,
+    pub params: Value,
+    /// Credential kind for error messages (e.g. "API key")
+    pub credential: &'static str,
+}
+
+impl LoginRequest {
+    /// Turn the login reply into `Ok(())` or an [`ExporterError::Auth`]
+    pub fn check(&self, response: JsonRpcResponse) -> Result<()> {
+        if let Some(error) = response.error {
+            return Err(ExporterError::Auth(format!(
+                "Authentication failed: {}",
+                error.reason()
+            )));
+        }
+
+        if self.method == "auth.login_ex" {
+            let response_type = response
+                .result
+                .as_ref()
+                .and_then(|result| result.get("response_type"))
+                .and_then(Value::as_str)
+                .unwrap_or("no response_type");
+            return match response_type {
+                "SUCCESS" => Ok(()),
+                "OTP_REQUIRED" => Err(ExporterError::Auth(
+                    "Authentication failed: TrueNAS requires a one-time password for this user"
+                        .to_string(),
+                )),
+                "EXPIRED" => Err(ExporterError::Auth(format!(
+                    "Authentication failed: {} has expired",
+                    self.credential
+                ))),
+                other => Err(ExporterError::Auth(format!(
+                    "Authentication failed: {} rejected by TrueNAS ({})",
+                    self.credential, other
+                ))),
+            };
+        }
+
+        if response.result == Some(Value::Bool(false)) {
+            return Err(ExporterError::Auth(format!(
+                "Authentication failed: {} rejected by TrueNAS",
+                self.credential
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Build the credential login for the configured strategy
+///
+/// For the `token` strategy this is the bootstrap login used before a token exists:
+/// username/password if both are set, otherwise the API key.
+pub fn credential_login(config: &TrueNasConfig) -> Result<LoginRequest> {
+    let auth = &config.auth;
+    let use_password = match auth.method {
+        AuthMethod::ApiKey => false,
+        AuthMethod::Password => true,
+        AuthMethod::Token => auth.username.is_some() && auth.password.is_some(),
+    };
+
+    if use_password {
+        let (Some(username), Some(password)) = (&auth.username, &auth.password) else {
+            return Err(ExporterError::Auth(
+                "Authentication failed: password auth requires username and password".to_string(),
+            ));
+        };
+        return Ok(if auth.login_ex {
+            LoginRequest {
+                method: "auth.login_ex",
+                params: json!([{
+                    "mechanism": "PASSWORD_PLAIN",
+                    "username": username,
+                    "password": password.expose_secret(),
+                }]),
+                credential: "username/password",
+            }
+        } else {
+            LoginRequest {
+                method: "auth.login",
+                params: json!([username, password.expose_secret()]),
+                credential: "username/password",
+            }
+        });
+    }
+
+    let api_key = config.api_key.expose_secret().trim();
+    if api_key.is_empty() {
+        return Err(ExporterError::Auth(
+            "Authentication failed: no API key configured".to_string(),
+        ));
+    }
+    if auth.login_ex {
+        let Some(username) = &auth.username else {
+            return Err(ExporterError::Auth(
+                "Authentication failed: auth.login_ex with an API key requires username"
+                    .to_string(),
+            ));
+        };
+        return Ok(LoginRequest {
+            method: "auth.login_ex",
+            params: json!([{
+                "mechanism": "API_KEY_PLAIN",
+                "username": username,
+                "api_key": api_key,
+            }]),
+            credential: "API key",
+        });
+    }
+    Ok(LoginRequest {
+        method: "auth.login_with_api_key",
+        params: json!([api_key]),
+        credential: "API key",
+    })
+}
+
+/// Build a login with a previously generated token
+pub fn token_login(config: &TrueNasConfig, token: &AuthToken) -> LoginRequest {
+    let token = token.token.expose_secret();
+    if config.auth.login_ex {
+        LoginRequest {
+            method: "auth.login_ex",
+            params: json!([{"mechanism": "TOKEN_PLAIN", "token": token}]),
+            credential: "token",
+        }
+    } else {
+        LoginRequest {
+            method: "auth.login_with_token",
+            params: json!([token]),
+            credential: "token",
+        }
+    }
+}
+
+/// A token from `auth.generate_token` and its lifetime
+#[derive(Debug, Clone)]
+pub struct AuthToken {
+    token: SecretString,
+    issued: Instant,
+    ttl: Duration,
+}
+
+impl AuthToken {
+    pub fn new(token: String, ttl: Duration) -> Self {
+        Self {
+            token: SecretString::from(token),
+            issued: Instant::now(),
+            ttl,
+        }
+    }
+
+    /// Parse the `auth.generate_token` reply
+    pub fn from_response(response: JsonRpcResponse, ttl: Duration) -> Result<Self> {
+        if let Some(error) = response.error {
+            return Err(ExporterError::Auth(format!(
+                "Failed to generate auth token: {}",
+                error.reason()
+            )));
+        }
+        match response.result {
+            Some(Value::String(token)) => Ok(Self::new(token, ttl)),
+            other => Err(ExporterError::Auth(format!(
+                "Failed to generate auth token: unexpected response {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// True once the token can no longer be used to log in
+    pub fn is_expired(&self) -> bool {
+        self.issued.elapsed() >= self.ttl
+    }
+
+    /// True once three quarters of the lifetime have passed
+    pub fn needs_renewal(&self) -> bool {
+        self.issued.elapsed() >= self.ttl * 3 / 4
+    }
+}
@@ -5,9 +5,11 @@
 //!
 //! # Architecture
 //!
-//! - **Connection**: WebSocket (ws:// or wss://) to `/websocket` endpoint
+//! - **Connection**: WebSocket (ws:// or wss://) to `/api/current` (JSON-RPC 2.0) or the
+//!   legacy `/websocket` endpoint, selected by [`ProtocolMode`](crate::config::ProtocolMode)
 //! - **Authentication**: API key passed as array in initial auth message
-//! - **Protocol**: JSON-RPC 2.0 with DDP (Distributed Data Protocol) handshake
+//! - **Protocol**: JSON-RPC 2.0, with a DDP (Distributed Data Protocol) handshake on the
+//!   legacy endpoint
 //!
 //! # Example
 //!
@@ -22,6 +24,7 @@
 //!     api_key: SecretString::from("your-api-key"),
 //!     use_tls: true,
 //!     verify_ssl: false,
+//!     ..Default::default()
 //! };
 //!
 //! let client = TrueNasClient::new(config);
@@ -32,9 +35,12 @@
 
 use crate::config::TrueNasConfig;
 use crate::error::Result;
-use crate::truenas::connection::ConnectionManager;
+use crate::truenas::connection::{ConnectionManager, SubscriptionEvent};
+use crate::truenas::reconnect::ConnectionStats;
 use crate::truenas::types::*;
+use crate::truenas::version::{Capability, ServerVersion};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 
 /// Client for TrueNAS Scale WebSocket API
 ///
@@ -103,6 +109,7 @@ impl TrueNasClient {
     /// #     api_key: SecretString::from("key"),
     /// #     use_tls: true,
     /// #     verify_ssl: false,
+    /// #     ..Default::default()
     /// # };
     /// let client = TrueNasClient::new(config);
     /// let pools = client.query_pools().await?;
@@ -120,6 +127,54 @@ impl TrueNasClient {
         self.connection_manager.execute_query(method, params).await
     }
 
+    /// Connect and authenticate without making an API call
+    pub async fn connect(&self) -> Result<()> {
+        self.connection_manager.establish().await
+    }
+
+    /// Current connection state and reconnect counters
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.connection_manager.stats()
+    }
+
+    /// Release reported by TrueNAS, once connected
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        self.connection_manager.server_version()
+    }
+
+    /// Whether the connected release offers the given capability
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.connection_manager.supports(capability)
+    }
+
+    /// True while repeated connection failures say TrueNAS is down
+    pub fn circuit_open(&self) -> bool {
+        self.connection_manager.circuit_open()
+    }
+
+    /// Close the WebSocket connection, if one is open
+    pub async fn close(&self) {
+        self.connection_manager.close().await
+    }
+
+    /// Subscribe to change events for a collection
+    ///
+    /// See [`ConnectionManager::subscribe`].
+    pub async fn subscribe(&self, collection: &str) -> Result<()> {
+        self.connection_manager.subscribe(collection).await
+    }
+
+    /// Receive change notifications for subscribed collections
+    pub fn events(&self) -> broadcast::Receiver<SubscriptionEvent> {
+        self.connection_manager.events()
+    }
+
+    /// Query the raw contents of a collection, used to seed event-driven state
+    pub async fn query_collection(&self, collection: &str) -> Result<Vec<serde_json::Value>> {
+        self.execute_query(collection, Some(serde_json::json!([])))
+            .await
+    }
+
     /// Query disk information
     pub async fn query_disks(&self) -> Result<Vec<DiskInfo>> {
         self.execute_query("disk.query", Some(serde_json::json!([])))
@@ -3,186 +3,1158 @@
 //! This module handles persistent WebSocket connections to TrueNAS.
 //! It maintains a single long-lived connection that is reused across multiple API calls,
 //! which is required for proper authentication in TrueNAS 25.04+.
+//!
+//! # Multiplexing
+//!
+//! Once a connection is authenticated, the socket is split into a write half and a read half.
+//! A background reader task owns the read half and routes every response to the caller that
+//! is waiting for it, matched by the JSON-RPC `id`. Callers only hold the write half for as
+//! long as it takes to send their request, so any number of calls can be in flight at once
+//! on the same authenticated session. Frames that do not carry a known `id` (for example
+//! unsolicited server messages) are ignored instead of being mistaken for a reply.
+//!
+//! # Protocols
+//!
+//! Two wire protocols are supported, selected by [`ProtocolMode`]:
+//!
+//! - **Legacy DDP** at `/websocket`: a DDP `connect` handshake followed by
+//!   `{"msg": "method", ...}` calls
+//! - **JSON-RPC 2.0** at `/api/current`: no handshake, plain `{"jsonrpc": "2.0", ...}` calls
+//!
+//! In `auto` mode the JSON-RPC 2.0 endpoint is probed first. If the server rejects the
+//! upgrade with an HTTP error the legacy endpoint is used instead, and the result of the
+//! probe is remembered for subsequent reconnects.
+//!
+//! # Timeouts and Keepalive
+//!
+//! Connecting and every individual call are bounded by `[truenas.timeouts]`. A call that
+//! times out fails on its own without disturbing the connection. Liveness is judged by a
+//! keepalive task that pings the server periodically: if no frame at all arrives within
+//! the keepalive timeout, the connection is torn down, pending calls are released, and
+//! the next request reconnects.
+//!
+//! # Control Frames
+//!
+//! Pings from the server are answered with pongs as soon as they are read, and Pong and
+//! non-JSON Binary frames are skipped while calls keep waiting for their replies. A Close
+//! frame ends the connection: its code and reason are handed to every pending call as
+//! [`ExporterError::ConnectionClosed`], and the next request reconnects. A connection
+//! that ends without a Close frame is reported the same way with code 1006.
+//!
+//! # Reconnecting
+//!
+//! Failed connection attempts are retried with jittered exponential backoff, and
+//! requests made while backing off fail fast instead of reconnecting. See [`Backoff`]
+//! for the policy and circuit breaker.
+//!
+//! # Authentication
+//!
+//! Every new connection is authenticated before use with the strategy configured in
+//! `[truenas.auth]` (see [`auth`]). Failures surface as [`ExporterError::Auth`].
+//!
+//! # Event Subscriptions
+//!
+//! Collections registered with [`ConnectionManager::subscribe`] are subscribed on every
+//! new connection (`sub` on the legacy endpoint, `core.subscribe` on JSON-RPC 2.0).
+//! Change notifications are published on a broadcast channel as [`SubscriptionEvent`]s.
+//! A [`SubscriptionEvent::Subscribed`] is published whenever the subscriptions have been
+//! (re)established, telling consumers to resync any state they derived from earlier events.
+//!
+//! # Server Version
+//!
+//! After authenticating, the release is read with `system.version` (falling back to
+//! `system.info`) and kept for [`ConnectionManager::supports`]. Methods the server
+//! answers with "method not found" are remembered as unsupported for the lifetime of
+//! the manager. See [`crate::truenas::version`] for the capability matrix.
+//!
+//! # Record and Replay
+//!
+//! With `record_dir` set, every call and its reply are also written to fixture files.
+//! With `replay_dir` set, calls are answered from such files and no connection is ever
+//! opened. See [`crate::truenas::replay`].
 
-use crate::config::TrueNasConfig;
+use crate::config::{AuthMethod, ProtocolMode, TimeoutConfig, TrueNasConfig};
 use crate::error::{ExporterError, Result};
-use crate::truenas::types::{DdpConnect, JsonRpcRequest, JsonRpcResponse};
+use crate::truenas::reconnect::{Backoff, ConnectionState, ConnectionStats};
+use crate::truenas::replay::{Recorder, Replayer};
+use crate::truenas::types::{
+    CollectionUpdate, DdpConnect, DdpSubscribe, JsonRpcRequest, JsonRpcResponse,
+};
+use crate::truenas::version::{self, Capability, ServerVersion};
+use crate::truenas::{auth, tls};
+use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
-use secrecy::ExposeSecret;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
-use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tokio::sync::{broadcast, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::{
+    client_async_with_config, connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream,
+};
 use tracing::{debug, info, warn};
 
 type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type WsSink = SplitSink<WsStream, Message>;
+type WsSource = SplitStream<WsStream>;
+
+/// Responses awaited by in-flight calls, keyed by request ID
+type PendingMap = Arc<std::sync::Mutex<HashMap<String, oneshot::Sender<JsonRpcResponse>>>>;
+
+/// Why a connection ended, recorded once by whichever task noticed first
+type CloseSlot = Arc<std::sync::Mutex<Option<(u16, String)>>>;
+
+/// Close code for connections that ended without a Close frame (RFC 6455 section 7.4.1)
+const ABNORMAL_CLOSURE: u16 = 1006;
+
+/// Capacity of the event channel; slower consumers see `Lagged` and should resync
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Notification published to event subscribers
+#[derive(Debug, Clone)]
+pub enum SubscriptionEvent {
+    /// Subscriptions are active on a new connection; earlier events may have been missed
+    Subscribed,
+    /// A subscribed collection changed
+    Update(CollectionUpdate),
+}
+
+/// Wire protocol spoken on an established connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Legacy DDP envelope on `/websocket`
+    Legacy,
+    /// Versioned JSON-RPC 2.0 on `/api/current`
+    JsonRpc2,
+}
+
+impl Protocol {
+    /// Endpoint path for this protocol
+    pub fn path(self) -> &'static str {
+        match self {
+            Protocol::Legacy => "/websocket",
+            Protocol::JsonRpc2 => "/api/current",
+        }
+    }
+
+    /// Build a method call in this protocol's envelope
+    pub fn request(
+        self,
+        id: String,
+        method: &str,
+        params: Option<serde_json::Value>,
+    ) -> JsonRpcRequest {
+        match self {
+            Protocol::Legacy => JsonRpcRequest::ddp(id, method, params),
+            Protocol::JsonRpc2 => JsonRpcRequest::jsonrpc2(id, method, params),
+        }
+    }
+}
 
 /// Manages a persistent WebSocket connection to TrueNAS
 pub struct ConnectionManager {
     config: Arc<TrueNasConfig>,
-    connection: Arc<Mutex<Option<ActiveConnection>>>,
-    request_id: Arc<std::sync::atomic::AtomicU64>,
+    connection: Arc<Mutex<Option<Arc<ActiveConnection>>>>,
+    request_id: Arc<AtomicU64>,
+    /// Protocol found by the `auto` probe, reused on reconnect
+    detected_protocol: std::sync::Mutex<Option<Protocol>>,
+    /// Collections to subscribe to on every connection
+    subscriptions: std::sync::Mutex<BTreeSet<String>>,
+    /// Token for the `token` auth strategy
+    token: std::sync::Mutex<Option<auth::AuthToken>>,
+    backoff: std::sync::Mutex<Backoff>,
+    /// Liveness flag of the current connection, readable without the connection lock
+    current_alive: std::sync::Mutex<Option<Arc<AtomicBool>>>,
+    attempts: AtomicU64,
+    auth_failures: AtomicU64,
+    events: broadcast::Sender<SubscriptionEvent>,
+    /// Release reported by the server on the latest connection
+    server_version: std::sync::Mutex<Option<ServerVersion>>,
+    /// Methods the server reported as not found
+    unsupported_methods: std::sync::Mutex<BTreeSet<String>>,
+    recorder: Option<Recorder>,
+    replayer: Option<Replayer>,
 }
 
-/// An active WebSocket connection
+/// An active, authenticated WebSocket connection
 struct ActiveConnection {
-    stream: WsStream,
-    authenticated: bool,
+    protocol: Protocol,
+    sink: Arc<Mutex<WsSink>>,
+    pending: PendingMap,
+    alive: Arc<AtomicBool>,
+    closed: CloseSlot,
+    call_timeout: Duration,
+    reader: JoinHandle<()>,
+    keepalive: Option<JoinHandle<()>>,
+}
+
+impl ActiveConnection {
+    /// Split an established stream, start routing its responses and keep it alive
+    fn spawn(
+        stream: WsStream,
+        protocol: Protocol,
+        timeouts: &TimeoutConfig,
+        events: broadcast::Sender<SubscriptionEvent>,
+    ) -> Self {
+        let (sink, source) = stream.split();
+        let sink = Arc::new(Mutex::new(sink));
+        let pending: PendingMap = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let alive = Arc::new(AtomicBool::new(true));
+        let closed: CloseSlot = Arc::new(std::sync::Mutex::new(None));
+        let last_seen = Arc::new(std::sync::Mutex::new(Instant::now()));
+
+        let reader = tokio::spawn(read_loop(
+            source,
+            sink.clone(),
+            pending.clone(),
+            alive.clone(),
+            closed.clone(),
+            last_seen.clone(),
+            events,
+        ));
+
+        let keepalive = (timeouts.keepalive_interval_seconds > 0).then(|| {
+            tokio::spawn(keepalive_loop(
+                sink.clone(),
+                pending.clone(),
+                alive.clone(),
+                closed.clone(),
+                last_seen,
+                Duration::from_secs(timeouts.keepalive_interval_seconds),
+                Duration::from_secs(timeouts.keepalive_timeout_seconds),
+            ))
+        });
+
+        Self {
+            protocol,
+            sink,
+            pending,
+            alive,
+            closed,
+            call_timeout: Duration::from_secs(timeouts.call_seconds),
+            reader,
+            keepalive,
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    /// The error for calls cut off by the end of this connection
+    fn close_error(&self) -> ExporterError {
+        let (code, reason) = self
+            .closed
+            .lock()
+            .expect("close lock poisoned")
+            .clone()
+            .unwrap_or_else(|| (ABNORMAL_CLOSURE, "connection lost".to_string()));
+        ExporterError::ConnectionClosed { code, reason }
+    }
+
+    /// Send a message that does not expect a routed reply
+    async fn send(&self, message: String) -> Result<()> {
+        let result = self
+            .sink
+            .lock()
+            .await
+            .send(Message::Text(message.into()))
+            .await;
+        if let Err(e) = result {
+            self.alive.store(false, Ordering::SeqCst);
+            return Err(ExporterError::WebSocket(e));
+        }
+        Ok(())
+    }
+
+    /// Send a request and wait for the response carrying the same ID
+    ///
+    /// The request leaves the pending map however the call ends, including when the
+    /// caller stops waiting, e.g. on `collector_timeout_seconds`.
+    async fn call(&self, request: &JsonRpcRequest) -> Result<JsonRpcResponse> {
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .expect("pending map poisoned")
+            .insert(request.id.clone(), tx);
+        let _pending = PendingCall {
+            pending: &self.pending,
+            id: &request.id,
+        };
+
+        let request_json = serde_json::to_string(request)?;
+        let send_result = self
+            .sink
+            .lock()
+            .await
+            .send(Message::Text(request_json.into()))
+            .await;
+
+        if let Err(e) = send_result {
+            // The socket is unusable; make sure nobody else tries to reuse it
+            self.alive.store(false, Ordering::SeqCst);
+            return Err(ExporterError::WebSocket(e));
+        }
+
+        match tokio::time::timeout(self.call_timeout, rx).await {
+            Ok(response) => response.map_err(|_| self.close_error()),
+            Err(_) => Err(ExporterError::Timeout(format!(
+                "no response to {} within {}s",
+                request.method,
+                self.call_timeout.as_secs()
+            ))),
+        }
+    }
+}
+
+/// Removes an in-flight call from the pending map when it is dropped
+struct PendingCall<'a> {
+    pending: &'a PendingMap,
+    id: &'a str,
+}
+
+impl Drop for PendingCall<'_> {
+    fn drop(&mut self) {
+        self.pending
+            .lock()
+            .expect("pending map poisoned")
+            .remove(self.id);
+    }
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.reader.abort();
+        if let Some(keepalive) = &self.keepalive {
+            keepalive.abort();
+        }
+    }
+}
+
+/// Mark a connection unusable and release every call waiting on it
+///
+/// The first recorded close code and reason win; they are what released calls report.
+fn mark_dead(
+    alive: &AtomicBool,
+    pending: &PendingMap,
+    closed: &CloseSlot,
+    code: u16,
+    reason: String,
+) {
+    closed
+        .lock()
+        .expect("close lock poisoned")
+        .get_or_insert((code, reason));
+    alive.store(false, Ordering::SeqCst);
+    pending.lock().expect("pending map poisoned").clear();
+}
+
+/// Ping the server periodically and tear the connection down if it stops answering
+///
+/// Any incoming frame counts as a sign of life, so a busy connection is never dropped
+/// just because a pong is queued behind other responses. A half-open TCP connection,
+/// on the other hand, produces no frames at all and is detected within
+/// `interval + timeout`.
+async fn keepalive_loop(
+    sink: Arc<Mutex<WsSink>>,
+    pending: PendingMap,
+    alive: Arc<AtomicBool>,
+    closed: CloseSlot,
+    last_seen: Arc<std::sync::Mutex<Instant>>,
+    interval: Duration,
+    timeout: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        if !alive.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let ping_sent = Instant::now();
+        let sent = tokio::time::timeout(timeout, async {
+            sink.lock()
+                .await
+                .send(Message::Ping(Vec::new().into()))
+                .await
+        })
+        .await;
+        if !matches!(sent, Ok(Ok(()))) {
+            warn!("Failed to send WebSocket ping, dropping connection");
+            mark_dead(
+                &alive,
+                &pending,
+                &closed,
+                ABNORMAL_CLOSURE,
+                "failed to send keepalive ping".to_string(),
+            );
+            return;
+        }
+
+        tokio::time::sleep(timeout).await;
+        if *last_seen.lock().expect("last_seen lock poisoned") < ping_sent {
+            warn!(
+                "TrueNAS did not respond within {}s of a ping, dropping connection",
+                timeout.as_secs()
+            );
+            mark_dead(
+                &alive,
+                &pending,
+                &closed,
+                ABNORMAL_CLOSURE,
+                format!("no response within {}s of a ping", timeout.as_secs()),
+            );
+            return;
+        }
+    }
+}
+
+/// Route incoming frames to the calls waiting for them
+///
+/// Runs until the server closes the socket or a read fails. On exit the connection is
+/// marked dead and every pending call is released with an
+/// [`ExporterError::ConnectionClosed`] carrying the close code and reason.
+async fn read_loop(
+    mut source: WsSource,
+    sink: Arc<Mutex<WsSink>>,
+    pending: PendingMap,
+    alive: Arc<AtomicBool>,
+    closed: CloseSlot,
+    last_seen: Arc<std::sync::Mutex<Instant>>,
+    events: broadcast::Sender<SubscriptionEvent>,
+) {
+    let (code, reason) = loop {
+        let msg = match source.next().await {
+            Some(Ok(msg)) => msg,
+            Some(Err(e)) => {
+                warn!("WebSocket read failed: {}", e);
+                break (ABNORMAL_CLOSURE, e.to_string());
+            }
+            None => break (ABNORMAL_CLOSURE, "connection dropped".to_string()),
+        };
+        *last_seen.lock().expect("last_seen lock poisoned") = Instant::now();
+
+        match msg {
+            Message::Text(text) => dispatch_message(&text, &pending, &events),
+            Message::Binary(data) => match std::str::from_utf8(&data) {
+                Ok(text) => dispatch_message(text, &pending, &events),
+                Err(_) => debug!("Skipping {} byte binary frame", data.len()),
+            },
+            Message::Ping(_) => {
+                // tungstenite queues the pong while reading; flush it out right away
+                if let Err(e) = sink.lock().await.flush().await {
+                    debug!("Failed to send pong: {}", e);
+                }
+            }
+            Message::Close(frame) => {
+                // Flush the queued close reply so the server sees a clean shutdown
+                let _ = sink.lock().await.flush().await;
+                let (code, reason) = close_reason(frame);
+                if code == 1000 || code == 1001 {
+                    info!("TrueNAS closed the connection (code {}): {}", code, reason);
+                } else {
+                    warn!("TrueNAS closed the connection (code {}): {}", code, reason);
+                }
+                break (code, reason);
+            }
+            Message::Pong(_) | Message::Frame(_) => {}
+        }
+    };
+
+    mark_dead(&alive, &pending, &closed, code, reason);
+}
+
+/// The result of a reply, or its error as [`ExporterError::TrueNasApi`]
+fn response_result(response: JsonRpcResponse) -> Result<serde_json::Value> {
+    if let Some(error) = response.error {
+        return Err(ExporterError::TrueNasApi(error.reason()));
+    }
+    response
+        .result
+        .ok_or_else(|| ExporterError::TrueNasApi("No valid JSON-RPC response received".to_string()))
+}
+
+/// Close code and reason from a Close frame
+fn close_reason(frame: Option<CloseFrame>) -> (u16, String) {
+    frame.map_or_else(
+        || (ABNORMAL_CLOSURE, "no close reason given".to_string()),
+        |frame| (u16::from(frame.code), frame.reason.to_string()),
+    )
+}
+
+/// Deliver a text frame to the pending call with a matching ID, or to event subscribers
+fn dispatch_message(
+    text: &str,
+    pending: &PendingMap,
+    events: &broadcast::Sender<SubscriptionEvent>,
+) {
+    let value: serde_json::Value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("Ignoring malformed message from TrueNAS: {}", e);
+            return;
+        }
+    };
+
+    // Legacy DDP events are bare `added`/`changed`/`removed` messages;
+    // JSON-RPC 2.0 wraps them in a `collection_update` notification
+    let update = match (value.get("msg"), value.get("method")) {
+        (Some(msg), _) if matches!(msg.as_str(), Some("added" | "changed" | "removed")) => {
+            Some(&value)
+        }
+        (_, Some(method)) if method == "collection_update" => value.get("params"),
+        _ => None,
+    };
+    if let Some(update) = update {
+        match CollectionUpdate::deserialize(update) {
+            Ok(update) => {
+                // No receivers simply means nobody is interested in events
+                let _ = events.send(SubscriptionEvent::Update(update));
+            }
+            Err(e) => warn!("Failed to parse collection update: {}", e),
+        }
+        return;
+    }
+
+    if value.get("msg").and_then(|msg| msg.as_str()) == Some("nosub") {
+        warn!("Subscription rejected by TrueNAS: {}", text);
+        return;
+    }
+
+    let Some(id) = value
+        .get("id")
+        .and_then(|id| id.as_str())
+        .map(str::to_string)
+    else {
+        debug!("Ignoring unsolicited message: {}", text);
+        return;
+    };
+
+    let Some(tx) = pending.lock().expect("pending map poisoned").remove(&id) else {
+        debug!("Ignoring response for unknown request ID {}", id);
+        return;
+    };
+
+    match serde_json::from_value::<JsonRpcResponse>(value) {
+        Ok(response) => {
+            // The caller may have given up waiting; nothing to do in that case
+            let _ = tx.send(response);
+        }
+        Err(e) => warn!("Failed to parse response for request {}: {}", id, e),
+    }
 }
 
 impl ConnectionManager {
     /// Create a new connection manager
     pub fn new(config: Arc<TrueNasConfig>) -> Self {
+        let recorder = config.record_dir.as_ref().map(Recorder::new);
+        let replayer = config.replay_dir.as_ref().map(Replayer::new);
+        // Replay never connects, so take the version from the recording up front
+        let replayed_version = replayer.as_ref().and_then(|replayer| {
+            version::VERSION_METHODS
+                .iter()
+                .filter_map(|method| replayer.replay(method, None).ok())
+                .find_map(|result| version::reported_version(&result))
+                .and_then(|raw| ServerVersion::parse(&raw))
+        });
+
         Self {
+            backoff: std::sync::Mutex::new(Backoff::new(config.reconnect.clone())),
             config,
             connection: Arc::new(Mutex::new(None)),
-            request_id: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            request_id: Arc::new(AtomicU64::new(0)),
+            detected_protocol: std::sync::Mutex::new(None),
+            subscriptions: std::sync::Mutex::new(BTreeSet::new()),
+            token: std::sync::Mutex::new(None),
+            current_alive: std::sync::Mutex::new(None),
+            attempts: AtomicU64::new(0),
+            auth_failures: AtomicU64::new(0),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            server_version: std::sync::Mutex::new(replayed_version),
+            unsupported_methods: std::sync::Mutex::new(BTreeSet::new()),
+            recorder,
+            replayer,
+        }
+    }
+
+    /// Release reported by the server, once a connection has been made
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        self.server_version
+            .lock()
+            .expect("version lock poisoned")
+            .clone()
+    }
+
+    /// Whether the server offers the given capability
+    ///
+    /// False if the detected release predates it or one of its methods was reported
+    /// as not found. True while the version is unknown.
+    pub fn supports(&self, capability: Capability) -> bool {
+        let released = self
+            .server_version()
+            .is_none_or(|version| version.supports(capability));
+        let unsupported = self
+            .unsupported_methods
+            .lock()
+            .expect("method lock poisoned");
+        released
+            && !capability
+                .methods()
+                .iter()
+                .any(|method| unsupported.contains(*method))
+    }
+
+    /// Whether the server offers `method`, per [`ConnectionManager::supports`]
+    fn offers(&self, method: &str) -> bool {
+        let offered =
+            Capability::of_method(method).is_none_or(|capability| self.supports(capability));
+        if !offered {
+            debug!("{} is not offered by this TrueNAS release", method);
         }
+        offered
+    }
+
+    /// Current connection state and reconnect counters
+    pub fn stats(&self) -> ConnectionStats {
+        let connected = self
+            .current_alive
+            .lock()
+            .expect("state lock poisoned")
+            .as_ref()
+            .is_some_and(|alive| alive.load(Ordering::SeqCst));
+        let backing_off = self
+            .backoff
+            .lock()
+            .expect("backoff lock poisoned")
+            .remaining(Instant::now())
+            .is_some();
+
+        ConnectionStats {
+            state: if connected {
+                ConnectionState::Connected
+            } else if backing_off {
+                ConnectionState::BackingOff
+            } else {
+                ConnectionState::Disconnected
+            },
+            reconnects: self.attempts.load(Ordering::SeqCst).saturating_sub(1),
+            auth_failures: self.auth_failures.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Number of calls waiting for a response on the current connection
+    pub async fn pending_calls(&self) -> usize {
+        match self.connection.lock().await.as_ref() {
+            Some(conn) => conn.pending.lock().expect("pending map poisoned").len(),
+            None => 0,
+        }
+    }
+
+    /// True while repeated connection failures say TrueNAS is down
+    ///
+    /// The collection loop skips cycles while the circuit is open; see [`Backoff`].
+    pub fn circuit_open(&self) -> bool {
+        self.backoff
+            .lock()
+            .expect("backoff lock poisoned")
+            .circuit_open(Instant::now())
+    }
+
+    /// Receive change notifications for subscribed collections
+    pub fn events(&self) -> broadcast::Receiver<SubscriptionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Subscribe to change events for a collection (e.g. `alert.list`)
+    ///
+    /// The subscription is kept for the lifetime of the manager and re-established on
+    /// every reconnect. If a connection is already open it is subscribed immediately;
+    /// otherwise the subscription takes effect when the next connection is made.
+    pub async fn subscribe(&self, collection: &str) -> Result<()> {
+        let added = self
+            .subscriptions
+            .lock()
+            .expect("subscription lock poisoned")
+            .insert(collection.to_string());
+        if !added {
+            return Ok(());
+        }
+
+        let conn = self.connection.lock().await.clone();
+        if let Some(conn) = conn.filter(|conn| conn.is_alive()) {
+            if !self.offers(collection) {
+                return Ok(());
+            }
+            self.send_subscription(&conn, collection).await?;
+            let _ = self.events.send(SubscriptionEvent::Subscribed);
+        }
+        Ok(())
+    }
+
+    /// Subscribe a single collection on the given connection
+    async fn send_subscription(&self, conn: &ActiveConnection, collection: &str) -> Result<()> {
+        debug!("Subscribing to {}", collection);
+        match conn.protocol {
+            Protocol::Legacy => {
+                let sub = DdpSubscribe::new(self.next_id(), collection);
+                conn.send(serde_json::to_string(&sub)?).await
+            }
+            Protocol::JsonRpc2 => {
+                let request = conn.protocol.request(
+                    self.next_id(),
+                    "core.subscribe",
+                    Some(serde_json::json!([collection])),
+                );
+                match conn.call(&request).await?.error {
+                    Some(error) => Err(ExporterError::TrueNasApi(format!(
+                        "Failed to subscribe to {}: {}",
+                        collection,
+                        error.reason()
+                    ))),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Re-establish all registered subscriptions on a new connection
+    async fn restore_subscriptions(&self, conn: &ActiveConnection) {
+        let collections: Vec<String> = self
+            .subscriptions
+            .lock()
+            .expect("subscription lock poisoned")
+            .iter()
+            .cloned()
+            .collect();
+        if collections.is_empty() {
+            return;
+        }
+
+        // Kept registered, so they are subscribed once an upgrade offers them
+        let collections: Vec<&String> = collections
+            .iter()
+            .filter(|collection| self.offers(collection))
+            .collect();
+        for collection in &collections {
+            if let Err(e) = self.send_subscription(conn, collection).await {
+                warn!("{}", e);
+            }
+        }
+        info!("Subscribed to {} event collection(s)", collections.len());
+        let _ = self.events.send(SubscriptionEvent::Subscribed);
     }
 
     /// Get next request ID
     fn next_id(&self) -> String {
-        self.request_id
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
-            .to_string()
+        self.request_id.fetch_add(1, Ordering::SeqCst).to_string()
     }
 
     /// Build WebSocket URL
-    fn websocket_url(&self) -> String {
-        let protocol = if self.config.use_tls { "wss" } else { "ws" };
-        format!("{}://{}/websocket", protocol, self.config.host)
+    fn websocket_url(&self, protocol: Protocol) -> String {
+        let scheme = if self.config.use_tls { "wss" } else { "ws" };
+        format!("{}://{}{}", scheme, self.config.host, protocol.path())
     }
 
-    /// Ensure we have an active, authenticated connection
-    async fn ensure_connected(&self) -> Result<()> {
+    /// Ensure we have an active, authenticated connection and return it
+    ///
+    /// Establishing a connection holds the connection lock, so concurrent callers
+    /// wait for a single handshake instead of each opening their own socket.
+    async fn ensure_connected(&self) -> Result<Arc<ActiveConnection>> {
         let mut conn_guard = self.connection.lock().await;
 
-        // Check if we have a connection
-        if conn_guard.is_none() {
-            info!("Establishing WebSocket connection to TrueNAS...");
-            let stream = self.connect_websocket().await?;
-            *conn_guard = Some(ActiveConnection {
-                stream,
-                authenticated: false,
-            });
-        }
-
-        // Check if we need to authenticate
-        if let Some(conn) = conn_guard.as_mut() {
-            if !conn.authenticated {
-                info!("Authenticating with TrueNAS...");
-                if let Err(e) = self.authenticate_connection(conn).await {
-                    warn!("Authentication failed, dropping connection: {}", e);
-                    *conn_guard = None;
-                    return Err(e);
+        if let Some(conn) = conn_guard.as_ref() {
+            if conn.is_alive() {
+                // Keep a usable token around for the next reconnect
+                if self.token_needs_renewal() {
+                    if let Err(e) = self.renew_token(conn).await {
+                        warn!("Failed to renew auth token: {}", e);
+                    }
                 }
-                conn.authenticated = true;
-                info!("Successfully authenticated to TrueNAS");
+                return Ok(conn.clone());
             }
+            warn!("WebSocket connection lost, reconnecting");
+            *conn_guard = None;
         }
 
-        Ok(())
+        let remaining = self
+            .backoff
+            .lock()
+            .expect("backoff lock poisoned")
+            .remaining(Instant::now());
+        if let Some(remaining) = remaining {
+            return Err(ExporterError::Unavailable(format!(
+                "last connection attempt failed, next attempt in {:.1}s",
+                remaining.as_secs_f64()
+            )));
+        }
+
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+        match self.open_connection().await {
+            Ok(conn) => {
+                let failed = self
+                    .backoff
+                    .lock()
+                    .expect("backoff lock poisoned")
+                    .record_success();
+                if failed > 0 {
+                    info!("Reconnected to TrueNAS after {} failed attempt(s)", failed);
+                }
+                *self.current_alive.lock().expect("state lock poisoned") = Some(conn.alive.clone());
+                *conn_guard = Some(conn.clone());
+                Ok(conn)
+            }
+            Err(e) => {
+                if matches!(e, ExporterError::Auth(_)) {
+                    self.auth_failures.fetch_add(1, Ordering::SeqCst);
+                }
+                let mut backoff = self.backoff.lock().expect("backoff lock poisoned");
+                let delay = backoff.record_failure(Instant::now());
+                let failures = backoff.failures();
+                // Warn on the first failure and when the circuit opens, not on every retry
+                if failures == 1 {
+                    warn!(
+                        "Failed to connect to TrueNAS: {}; retrying in {:.1}s",
+                        e,
+                        delay.as_secs_f64()
+                    );
+                } else if failures == self.config.reconnect.circuit_breaker_threshold {
+                    warn!(
+                        "TrueNAS unreachable after {} attempts, skipping collection cycles \
+                         until it recovers: {}",
+                        failures, e
+                    );
+                } else {
+                    debug!(
+                        "Connection attempt {} failed: {}; retrying in {:.1}s",
+                        failures,
+                        e,
+                        delay.as_secs_f64()
+                    );
+                }
+                Err(e)
+            }
+        }
     }
 
-    /// Connect to WebSocket
-    async fn connect_websocket(&self) -> Result<WsStream> {
-        let url = self.websocket_url();
-        debug!("Connecting to {}", url);
+    /// Open, handshake and authenticate a new connection
+    async fn open_connection(&self) -> Result<Arc<ActiveConnection>> {
+        info!("Establishing WebSocket connection to TrueNAS...");
+        let connect_timeout = Duration::from_secs(self.config.timeouts.connect_seconds);
+        let (stream, protocol) = tokio::time::timeout(connect_timeout, async {
+            let (mut stream, protocol) = self.connect().await?;
+            if protocol == Protocol::Legacy {
+                self.handshake(&mut stream).await?;
+            }
+            Ok::<_, ExporterError>((stream, protocol))
+        })
+        .await
+        .map_err(|_| {
+            ExporterError::Timeout(format!(
+                "connecting to {} took longer than {}s",
+                self.config.host,
+                connect_timeout.as_secs()
+            ))
+        })??;
+        let conn = Arc::new(ActiveConnection::spawn(
+            stream,
+            protocol,
+            &self.config.timeouts,
+            self.events.clone(),
+        ));
 
-        let (ws_stream, _) = if self.config.use_tls && !self.config.verify_ssl {
-            // Custom TLS connector for self-signed certs
-            let connector = native_tls::TlsConnector::builder()
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true)
-                .build()
-                .map_err(|e| ExporterError::Config(e.to_string()))?;
+        info!("Authenticating with TrueNAS...");
+        self.authenticate_connection(&conn).await?;
+        info!("Successfully authenticated to TrueNAS");
 
-            let connector = tokio_tungstenite::Connector::NativeTls(connector);
-            tokio_tungstenite::connect_async_tls_with_config(&url, None, false, Some(connector))
-                .await
-                .map_err(|e| ExporterError::Config(format!("TLS connection failed: {}", e)))?
-        } else {
-            connect_async(&url)
-                .await
-                .map_err(ExporterError::WebSocket)?
+        self.detect_version(&conn).await;
+        self.restore_subscriptions(&conn).await;
+        Ok(conn)
+    }
+
+    /// Read the server release, keeping the previous value if it cannot be determined
+    async fn detect_version(&self, conn: &ActiveConnection) {
+        let mut raw = None;
+        for method in version::VERSION_METHODS {
+            let request = conn.protocol.request(self.next_id(), method, None);
+            let outcome = conn.call(&request).await.and_then(response_result);
+            self.record(method, None, &outcome);
+            match outcome {
+                Ok(result) => {
+                    raw = version::reported_version(&result);
+                    if raw.is_some() {
+                        break;
+                    }
+                }
+                Err(e) => debug!("{} failed: {}", method, e),
+            }
+        }
+
+        match raw.as_deref().and_then(ServerVersion::parse) {
+            Some(detected) => {
+                info!("Connected to TrueNAS {}", detected);
+                // The fallback was probed on an older release; probe again after an upgrade
+                if self.config.protocol == ProtocolMode::Auto
+                    && conn.protocol == Protocol::Legacy
+                    && detected.supports(Capability::JsonRpc2Api)
+                {
+                    info!(
+                        "TrueNAS {} offers the JSON-RPC 2.0 API, moving to {} on the next connection",
+                        detected,
+                        Protocol::JsonRpc2.path()
+                    );
+                    *self
+                        .detected_protocol
+                        .lock()
+                        .expect("protocol lock poisoned") = None;
+                }
+                *self.server_version.lock().expect("version lock poisoned") = Some(detected);
+            }
+            None => warn!(
+                "Could not determine TrueNAS version ({}); assuming all APIs are available",
+                raw.as_deref().unwrap_or("no version reported")
+            ),
+        }
+    }
+
+    /// Drop the given connection if it is still the current one
+    async fn invalidate(&self, conn: &Arc<ActiveConnection>) {
+        let mut conn_guard = self.connection.lock().await;
+        if conn_guard
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(current, conn))
+        {
+            *conn_guard = None;
+        }
+    }
+
+    /// Open a socket using the configured protocol mode
+    async fn connect(&self) -> Result<(WsStream, Protocol)> {
+        let protocol = match self.config.protocol {
+            ProtocolMode::Legacy => Protocol::Legacy,
+            ProtocolMode::JsonRpc2 => Protocol::JsonRpc2,
+            ProtocolMode::Auto => {
+                let detected = *self
+                    .detected_protocol
+                    .lock()
+                    .expect("protocol lock poisoned");
+                match detected {
+                    Some(protocol) => protocol,
+                    None => return self.probe().await,
+                }
+            }
+        };
+
+        let stream = self.connect_websocket(protocol).await?;
+        Ok((stream, protocol))
+    }
+
+    /// Try JSON-RPC 2.0 first, falling back to the legacy endpoint on an HTTP rejection
+    async fn probe(&self) -> Result<(WsStream, Protocol)> {
+        let protocol = match self.connect_websocket(Protocol::JsonRpc2).await {
+            Ok(stream) => {
+                info!("Using JSON-RPC 2.0 API at {}", Protocol::JsonRpc2.path());
+                *self
+                    .detected_protocol
+                    .lock()
+                    .expect("protocol lock poisoned") = Some(Protocol::JsonRpc2);
+                return Ok((stream, Protocol::JsonRpc2));
+            }
+            Err(ExporterError::WebSocket(tungstenite::Error::Http(response))) => {
+                info!(
+                    "JSON-RPC 2.0 API not available (HTTP {}), falling back to legacy {}",
+                    response.status(),
+                    Protocol::Legacy.path()
+                );
+                Protocol::Legacy
+            }
+            Err(e) => return Err(e),
         };
 
+        let stream = self.connect_websocket(protocol).await?;
+        *self
+            .detected_protocol
+            .lock()
+            .expect("protocol lock poisoned") = Some(protocol);
+        Ok((stream, protocol))
+    }
+
+    /// Open the WebSocket, setting up TLS according to `[truenas.tls]`
+    async fn connect_websocket(&self, protocol: Protocol) -> Result<WsStream> {
+        let url = self.websocket_url(protocol);
+        debug!("Connecting to {}", url);
+
+        if !self.config.use_tls {
+            let (ws_stream, _) = connect_async(&url).await?;
+            return Ok(ws_stream);
+        }
+
+        let tls_config = &self.config.tls;
+        let connector = tls::connector(&self.config)?;
+        let pin = tls::pin(tls_config)?;
+        if pin.is_none() && !self.config.verify_ssl {
+            warn!("TLS certificate verification is disabled (verify_ssl = false)");
+        }
+
+        let request = url.as_str().into_client_request()?;
+        let host = request.uri().host().unwrap_or_default().to_string();
+        let port = request.uri().port_u16().unwrap_or(443);
+        let tcp = TcpStream::connect((host.as_str(), port))
+            .await
+            .map_err(tungstenite::Error::Io)?;
+
+        let server_name = tls_config.server_name.as_deref().unwrap_or(&host);
+        let stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(server_name, tcp)
+            .await
+            .map_err(|e| ExporterError::Tls(format!("TLS connection failed: {}", e)))?;
+
+        if let Some(pin) = pin {
+            tls::check_pin(stream.get_ref().peer_certificate().ok().flatten(), &pin)?;
+            debug!("Server certificate matches pin_sha256");
+        }
+
+        let (ws_stream, _) =
+            client_async_with_config(request, MaybeTlsStream::NativeTls(stream), None).await?;
         Ok(ws_stream)
     }
 
-    /// Authenticate an active connection
-    async fn authenticate_connection(&self, conn: &mut ActiveConnection) -> Result<()> {
+    /// Perform the DDP handshake on a freshly opened socket
+    async fn handshake(&self, stream: &mut WsStream) -> Result<()> {
         // Send DDP connect
         let connect_msg = serde_json::to_string(&DdpConnect::default())?;
-        conn.stream
+        stream
             .send(Message::Text(connect_msg.into()))
             .await
             .map_err(ExporterError::WebSocket)?;
 
-        // Read connect response
-        if let Some(msg) = conn.stream.next().await {
-            let msg = msg.map_err(ExporterError::WebSocket)?;
-            debug!("Received raw DDP response: {:?}", msg);
-            if let Message::Text(text) = msg {
-                debug!("DDP connect response: {}", text);
+        // Wait for the server to accept the session, skipping anything sent ahead of it
+        loop {
+            match stream.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    let response: serde_json::Value = serde_json::from_str(&text)?;
+                    match response["msg"].as_str() {
+                        Some("connected") => {
+                            debug!("DDP connect response: {}", text);
+                            return Ok(());
+                        }
+                        Some("failed") => {
+                            return Err(ExporterError::TrueNasApi(format!(
+                                "DDP connect rejected: {}",
+                                text
+                            )))
+                        }
+                        _ => debug!("Skipping message during DDP handshake: {}", text),
+                    }
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    let (code, reason) = close_reason(frame);
+                    return Err(ExporterError::ConnectionClosed { code, reason });
+                }
+                Some(Ok(msg)) => debug!("Skipping frame during DDP handshake: {:?}", msg),
+                Some(Err(e)) => return Err(ExporterError::WebSocket(e)),
+                None => {
+                    return Err(ExporterError::ConnectionClosed {
+                        code: ABNORMAL_CLOSURE,
+                        reason: "connection dropped during DDP handshake".to_string(),
+                    })
+                }
             }
         }
+    }
 
-        // Wait a bit to ensure server is ready (mitigate potential race condition)
-        tokio::time::sleep(std::time::Duration::from_millis(2000)).await;
-
-        // Send auth request
-        let auth_request = JsonRpcRequest {
-            id: self.next_id(),
-            msg: "method".to_string(),
-            method: "auth.login_with_api_key".to_string(),
-            params: Some(serde_json::json!([self
-                .config
-                .api_key
-                .expose_secret()
-                .trim()])),
-        };
-
-        let auth_json = serde_json::to_string(&auth_request)?;
-        debug!("Sending auth request");
-        conn.stream
-            .send(Message::Text(auth_json.into()))
-            .await
-            .map_err(ExporterError::WebSocket)?;
-
-        // Read auth response
-        if let Some(msg) = conn.stream.next().await {
-            let msg = msg.map_err(ExporterError::WebSocket)?;
-            if let Message::Text(text) = msg {
-                debug!("Auth response: {}", text);
-                let response: JsonRpcResponse = serde_json::from_str(&text)?;
-
-                // Check for errors
-                if let Some(error) = response.error {
-                    let error_msg = error.reason.unwrap_or_else(|| "Unknown error".to_string());
-                    return Err(ExporterError::Auth(format!(
-                        "Authentication failed: {}",
-                        error_msg
-                    )));
-                }
+    /// Authenticate a freshly opened connection using the configured strategy
+    async fn authenticate_connection(&self, conn: &ActiveConnection) -> Result<()> {
+        if self.config.auth.method != AuthMethod::Token {
+            return self
+                .login(conn, auth::credential_login(&self.config)?)
+                .await;
+        }
 
-                // Check result
-                if let Some(result) = response.result {
-                    if result == serde_json::Value::Bool(true) {
-                        return Ok(());
-                    } else if result == serde_json::Value::Bool(false) {
-                        return Err(ExporterError::Auth(
-                            "Authentication failed: API key rejected by TrueNAS".to_string(),
-                        ));
+        let cached = self
+            .token
+            .lock()
+            .expect("token lock poisoned")
+            .clone()
+            .filter(|token| !token.is_expired());
+        if let Some(token) = cached {
+            match self
+                .login(conn, auth::token_login(&self.config, &token))
+                .await
+            {
+                Ok(()) => {
+                    debug!("Authenticated with cached token");
+                    if token.needs_renewal() {
+                        self.renew_token(conn).await?;
                     }
+                    return Ok(());
                 }
+                Err(ExporterError::Auth(e)) => {
+                    info!("Cached token not accepted ({}), logging in again", e);
+                }
+                Err(e) => return Err(e),
             }
         }
 
+        self.login(conn, auth::credential_login(&self.config)?)
+            .await?;
+        self.renew_token(conn).await
+    }
+
+    /// Send a login call and check the reply
+    async fn login(&self, conn: &ActiveConnection, login: auth::LoginRequest) -> Result<()> {
+        let request =
+            conn.protocol
+                .request(self.next_id(), login.method, Some(login.params.clone()));
+
+        debug!("Sending {} request", login.method);
+        let response = conn.call(&request).await?;
+        login.check(response)
+    }
+
+    /// Whether the token strategy is due to fetch a fresh token
+    fn token_needs_renewal(&self) -> bool {
+        self.config.auth.method == AuthMethod::Token
+            && self
+                .token
+                .lock()
+                .expect("token lock poisoned")
+                .as_ref()
+                .is_none_or(|token| token.needs_renewal())
+    }
+
+    /// Obtain a fresh token on an authenticated connection
+    async fn renew_token(&self, conn: &ActiveConnection) -> Result<()> {
+        let ttl = self.config.auth.token_ttl_seconds;
+        let request = conn.protocol.request(
+            self.next_id(),
+            "auth.generate_token",
+            Some(serde_json::json!([ttl])),
+        );
+        let response = conn.call(&request).await?;
+        let token = auth::AuthToken::from_response(response, Duration::from_secs(ttl))?;
+        *self.token.lock().expect("token lock poisoned") = Some(token);
+        debug!("Obtained auth token valid for {}s", ttl);
+        Ok(())
+    }
+
+    /// Connect and log in now instead of on the first call
+    ///
+    /// Does nothing when replaying a recording.
+    pub async fn establish(&self) -> Result<()> {
+        if self.replayer.is_none() {
+            self.ensure_connected().await?;
+        }
         Ok(())
     }
 
     /// Execute a query on the persistent connection
+    ///
+    /// Safe to call concurrently: each call is matched to its own response by ID,
+    /// so independent queries share the socket without waiting on each other.
     pub async fn execute_query<T>(
         &self,
         method: &str,
@@ -191,82 +1163,88 @@ impl ConnectionManager {
     where
         T: serde::de::DeserializeOwned,
     {
-        // Ensure we're connected and authenticated
-        self.ensure_connected().await?;
-
-        let mut conn_guard = self.connection.lock().await;
-        // Take ownership of the connection (temporarily remove from mutex)
-        let mut conn = conn_guard
-            .take()
-            .ok_or_else(|| ExporterError::Config("No active connection".to_string()))?;
-
-        // Send request
-        let request = JsonRpcRequest {
-            id: self.next_id(),
-            msg: "method".to_string(),
-            method: method.to_string(),
-            params,
-        };
+        let result = match &self.replayer {
+            Some(replayer) => replayer.replay(method, params.as_ref()),
+            None => {
+                let outcome = self.call_method(method, params.as_ref()).await;
+                self.record(method, params.as_ref(), &outcome);
+                outcome
+            }
+        }?;
 
-        let request_json = serde_json::to_string(&request)?;
-        debug!("Sending request: {}", method);
+        serde_json::from_value(result).map_err(ExporterError::Json)
+    }
 
-        if let Err(e) = conn.stream.send(Message::Text(request_json.into())).await {
-            // Connection failed, do not put it back (it remains None)
-            return Err(ExporterError::WebSocket(e));
+    /// Store a call in the recording, if one is being made
+    fn record(
+        &self,
+        method: &str,
+        params: Option<&serde_json::Value>,
+        outcome: &Result<serde_json::Value>,
+    ) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(method, params, outcome);
         }
+    }
+
+    /// Call a method on the live connection and return its raw result
+    async fn call_method(
+        &self,
+        method: &str,
+        params: Option<&serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        // Ensure we're connected and authenticated
+        let conn = self.ensure_connected().await?;
 
-        // Read response
-        let response_msg = conn.stream.next().await;
+        let request = conn
+            .protocol
+            .request(self.next_id(), method, params.cloned());
 
-        // Put connection back immediately if we got a response (IO is okay)
-        // If response is None, it means stream closed, so we don't put it back
-        let msg = match response_msg {
-            Some(Ok(msg)) => {
-                *conn_guard = Some(conn);
-                msg
+        debug!("Sending request: {}", method);
+        let response = match conn.call(&request).await {
+            Ok(response) => response,
+            Err(ExporterError::Timeout(e)) => {
+                // A slow call alone does not mean the peer is gone; keepalive decides that
+                return Err(ExporterError::Timeout(e));
             }
-            Some(Err(e)) => return Err(ExporterError::WebSocket(e)),
-            None => {
-                return Err(ExporterError::TrueNasApi(
-                    "Connection closed by server".to_string(),
-                ))
+            Err(e) => {
+                // Transport failure: force a fresh connection on the next request
+                self.invalidate(&conn).await;
+                return Err(e);
             }
         };
+        debug!("{} response received", method);
 
-        if let Message::Text(text) = msg {
-            debug!("{} response received", method);
-            let response: JsonRpcResponse = serde_json::from_str(&text)?;
-
-            // Check for errors
-            if let Some(error) = response.error {
-                let error_msg = error.reason.unwrap_or_else(|| "Unknown error".to_string());
-
-                // If not authenticated, clear connection to force re-auth
-                if error_msg.contains("ENOTAUTHENTICATED") {
-                    warn!("Session expired, will re-authenticate on next request");
-                    *conn_guard = None;
-                }
-
-                return Err(ExporterError::TrueNasApi(error_msg));
+        // Check for errors
+        if let Some(error) = &response.error {
+            // If not authenticated, clear connection to force re-auth
+            if error.is_not_authenticated() {
+                warn!("Session expired, will re-authenticate on next request");
+                self.invalidate(&conn).await;
             }
-
-            // Parse result
-            if let Some(result) = response.result {
-                return serde_json::from_value(result).map_err(ExporterError::Json);
+            if error.is_method_not_found() {
+                let newly_unsupported = self
+                    .unsupported_methods
+                    .lock()
+                    .expect("method lock poisoned")
+                    .insert(method.to_string());
+                if newly_unsupported {
+                    warn!(
+                        "TrueNAS does not support {}, skipping it from now on",
+                        method
+                    );
+                }
             }
         }
 
-        Err(ExporterError::TrueNasApi(
-            "No valid JSON-RPC response received".to_string(),
-        ))
+        response_result(response)
     }
 
     /// Close the connection
     pub async fn close(&self) {
         let mut conn_guard = self.connection.lock().await;
-        if let Some(mut conn) = conn_guard.take() {
-            let _ = conn.stream.close(None).await;
+        if let Some(conn) = conn_guard.take() {
+            let _ = conn.sink.lock().await.close().await;
             info!("WebSocket connection closed");
         }
     }
@@ -1,6 +1,13 @@
+pub mod auth;
 pub mod client;
 pub mod connection;
+pub mod reconnect;
+pub mod replay;
+pub mod tls;
 pub mod types;
+pub mod version;
 
 pub use client::TrueNasClient;
-pub use connection::ConnectionManager;
+pub use connection::{ConnectionManager, SubscriptionEvent};
+pub use reconnect::{ConnectionState, ConnectionStats};
+pub use version::{Capability, ServerVersion};
@@ -0,0 +1,95 @@
+//! Reconnect Backoff and Circuit Breaker
+//!
+//! Tracks consecutive connection failures and decides when the next attempt may be made.
+//!
+//! # Policy
+//!
+//! - After the n-th consecutive failure, no new attempt is made for
+//!   `initial_backoff_seconds * 2^(n-1)` (capped at `max_backoff_seconds`), randomized to
+//!   between 50% and 100% of that value so that several exporters do not retry in lockstep
+//! - Requests made while backing off fail immediately instead of reconnecting
+//! - Once `circuit_breaker_threshold` consecutive attempts have failed the circuit is open,
+//!   and the collection loop skips whole cycles until the next attempt is due
+//! - A successful connection resets everything
+
+use crate::config::ReconnectConfig;
+use std::time::{Duration, Instant};
+
+/// Connection state reported by `truenas_exporter_connection_state`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// No connection yet, or the last one was lost and no attempt has failed since
+    Disconnected = 0,
+    /// Authenticated connection in use
+    Connected = 1,
+    /// Waiting out the backoff delay after a failed attempt
+    BackingOff = 2,
+}
+
+/// Counters and state exposed as exporter self-metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub state: ConnectionState,
+    /// Connection attempts made after the first one
+    pub reconnects: u64,
+    /// Attempts rejected during authentication
+    pub auth_failures: u64,
+}
+
+/// Backoff state for one TrueNAS host
+#[derive(Debug)]
+pub struct Backoff {
+    config: ReconnectConfig,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(config: ReconnectConfig) -> Self {
+        Self {
+            config,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    /// Time left before the next attempt is allowed, if still backing off
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.retry_at
+            .filter(|retry_at| *retry_at > now)
+            .map(|retry_at| retry_at - now)
+    }
+
+    /// True while enough attempts have failed that whole cycles should be skipped
+    pub fn circuit_open(&self, now: Instant) -> bool {
+        self.failures >= self.config.circuit_breaker_threshold && self.remaining(now).is_some()
+    }
+
+    /// Number of consecutive failed attempts
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Record a failed attempt and return the delay before the next one
+    pub fn record_failure(&mut self, now: Instant) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        let delay = self.delay_for(self.failures);
+        self.retry_at = Some(now + delay);
+        delay
+    }
+
+    /// Record a successful connection and return how many attempts had failed before it
+    pub fn record_success(&mut self) -> u32 {
+        self.retry_at = None;
+        std::mem::take(&mut self.failures)
+    }
+
+    /// Jittered delay after the given number of consecutive failures
+    pub fn delay_for(&self, failures: u32) -> Duration {
+        let initial = Duration::from_secs(self.config.initial_backoff_seconds);
+        let max = Duration::from_secs(self.config.max_backoff_seconds);
+        let exponent = failures.saturating_sub(1).min(31);
+        let base = initial.saturating_mul(1 << exponent).min(max);
+        base.mul_f64(0.5 + fastrand::f64() * 0.5)
+    }
+}
@@ -0,0 +1,162 @@
+//! Record and Replay of API Exchanges
+//!
+//! With `record_dir` set, every call made by the [`ConnectionManager`] is written to a
+//! fixture file together with its response. With `replay_dir` set, no connection is made
+//! at all: calls are answered from those files, so the whole exporter can run offline
+//! against payloads captured from a real appliance.
+//!
+//! # Fixture Files
+//!
+//! One JSON file per distinct call, named after the method plus a short hash of the
+//! parameters when there are any (`pool.query.json`, `reporting.get_data-1f2e3d4c5b6a7988.json`).
+//! The `start` and `end` of a time window are left out of the hash: they move with the
+//! clock, and a reporting query must find its fixture whenever it is replayed.
+//!
+//! ```json
+//! {
+//!   "method": "system.info",
+//!   "params": null,
+//!   "result": { "version": "TrueNAS-SCALE-24.10.2", "hostname": "nas" }
+//! }
+//! ```
+//!
+//! API errors are stored as `"error": "<reason>"` instead of `result` and replayed as
+//! [`ExporterError::TrueNasApi`]. Repeated calls overwrite the file, so a recording
+//! holds the latest response to each call. Files can be edited by hand, for example to
+//! strip serial numbers before attaching them to a bug report.
+//!
+//! [`ConnectionManager`]: crate::truenas::ConnectionManager
+
+use crate::error::{ExporterError, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// A recorded call and its outcome
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    #[serde(default)]
+    pub params: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Option keys holding a time window, which are not part of a fixture name
+const TIME_WINDOW_KEYS: [&str; 2] = ["start", "end"];
+
+/// File name for a call, stable across runs
+pub fn fixture_name(method: &str, params: Option<&Value>) -> String {
+    match params.filter(|params| !params.is_null()) {
+        None => format!("{}.json", method),
+        Some(params) => {
+            // serde_json sorts object keys, so equal params always serialize the same way
+            let digest = Sha256::digest(without_time_window(params).to_string().as_bytes());
+            let hash: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{}-{}.json", method, hash)
+        }
+    }
+}
+
+/// `params` with the time window removed from the params object or its option objects
+fn without_time_window(params: &Value) -> Value {
+    let strip = |value: &Value| match value {
+        Value::Object(options) => Value::Object(
+            options
+                .iter()
+                .filter(|(key, _)| !TIME_WINDOW_KEYS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        ),
+        other => other.clone(),
+    };
+    match params {
+        Value::Array(items) => Value::Array(items.iter().map(strip).collect()),
+        other => strip(other),
+    }
+}
+
+/// Writes exchanges to a fixture directory
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+}
+
+impl Recorder {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Store the outcome of a call
+    ///
+    /// Only replies from TrueNAS are recorded; transport failures and timeouts say
+    /// nothing about the API and are skipped. Write failures are logged, never returned,
+    /// so recording cannot break collection.
+    pub fn record(&self, method: &str, params: Option<&Value>, outcome: &Result<Value>) {
+        let exchange = match outcome {
+            Ok(result) => Exchange {
+                method: method.to_string(),
+                params: params.cloned(),
+                result: Some(result.clone()),
+                error: None,
+            },
+            Err(ExporterError::TrueNasApi(reason)) => Exchange {
+                method: method.to_string(),
+                params: params.cloned(),
+                result: None,
+                error: Some(reason.clone()),
+            },
+            Err(_) => return,
+        };
+
+        let path = self.dir.join(fixture_name(method, params));
+        let written = std::fs::create_dir_all(&self.dir).and_then(|()| {
+            let json = serde_json::to_string_pretty(&exchange)?;
+            std::fs::write(&path, json + "\n")
+        });
+        match written {
+            Ok(()) => debug!("Recorded {} to {}", method, path.display()),
+            Err(e) => warn!("Failed to record {} to {}: {}", method, path.display(), e),
+        }
+    }
+}
+
+/// Answers calls from a fixture directory
+#[derive(Debug)]
+pub struct Replayer {
+    dir: PathBuf,
+}
+
+impl Replayer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The recorded response to a call
+    pub fn replay(&self, method: &str, params: Option<&Value>) -> Result<Value> {
+        let path = self.dir.join(fixture_name(method, params));
+        let json = std::fs::read_to_string(&path).map_err(|e| {
+            ExporterError::TrueNasApi(format!(
+                "no recorded response for {} ({}): {}",
+                method,
+                path.display(),
+                e
+            ))
+        })?;
+        let exchange: Exchange = serde_json::from_str(&json)?;
+
+        match (exchange.result, exchange.error) {
+            (_, Some(error)) => Err(ExporterError::TrueNasApi(error)),
+            (Some(result), None) => Ok(result),
+            (None, None) => Ok(Value::Null),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! TLS Setup for the TrueNAS Connection
+//!
+//! Builds the `native-tls` connector from [`TlsConfig`] and checks certificate pins.
+//!
+//! # Verification Modes
+//!
+//! - **Verified** (default): the certificate must chain to a system root or to a CA in
+//!   `ca_file`, and match `server_name` (or the host from `host`)
+//! - **Pinned**: with `pin_sha256` set, the server certificate must have that SHA-256
+//!   fingerprint; chain and hostname checks are skipped so self-signed certificates work
+//! - **Insecure**: `verify_ssl = false` without a pin accepts any certificate
+//!
+//! A client certificate and key can be added in any mode for mutual TLS.
+
+use crate::config::{TlsConfig, TrueNasConfig};
+use crate::error::{ExporterError, Result};
+use native_tls::{Certificate, Identity, TlsConnector};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const PEM_CERT_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_CERT_END: &str = "-----END CERTIFICATE-----";
+
+/// Build the TLS connector for the configured verification mode
+pub fn connector(config: &TrueNasConfig) -> Result<TlsConnector> {
+    let tls = &config.tls;
+    let mut builder = TlsConnector::builder();
+
+    if let Some(path) = &tls.ca_file {
+        for cert in load_ca_bundle(path)? {
+            builder.add_root_certificate(cert);
+        }
+    }
+
+    if let Some(identity) = load_identity(tls)? {
+        builder.identity(identity);
+    }
+
+    if tls.pin_sha256.is_some() || !config.verify_ssl {
+        builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+
+    builder
+        .build()
+        .map_err(|e| ExporterError::Config(format!("Failed to set up TLS: {}", e)))
+}
+
+/// Parse the configured pin, if any
+pub fn pin(tls: &TlsConfig) -> Result<Option<[u8; 32]>> {
+    tls.pin_sha256.as_deref().map(parse_fingerprint).transpose()
+}
+
+/// Parse a SHA-256 fingerprint written as hex, with or without `:` separators
+pub fn parse_fingerprint(value: &str) -> Result<[u8; 32]> {
+    let hex: String = value
+        .chars()
+        .filter(|c| *c != ':' && !c.is_whitespace())
+        .collect();
+    let invalid = || {
+        ExporterError::Config(format!(
+            "Invalid pin_sha256 '{}': expected 64 hex digits",
+            value
+        ))
+    };
+
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut fingerprint = [0u8; 32];
+    for (i, byte) in fingerprint.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(fingerprint)
+}
+
+/// Format the SHA-256 fingerprint of a DER certificate as colon-separated hex
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Check the server certificate against a pinned fingerprint
+pub fn check_pin(cert: Option<Certificate>, expected: &[u8; 32]) -> Result<()> {
+    let cert = cert.ok_or_else(|| {
+        ExporterError::Tls("TrueNAS did not present a certificate to pin".to_string())
+    })?;
+    let der = cert
+        .to_der()
+        .map_err(|e| ExporterError::Tls(format!("Failed to read server certificate: {}", e)))?;
+
+    if Sha256::digest(&der).as_slice() != expected {
+        return Err(ExporterError::Tls(format!(
+            "Server certificate fingerprint {} does not match pin_sha256",
+            fingerprint(&der)
+        )));
+    }
+    Ok(())
+}
+
+/// Load every certificate from a PEM bundle
+fn load_ca_bundle(path: &Path) -> Result<Vec<Certificate>> {
+    let pem = read_file(path, "CA bundle")?;
+    let pem = String::from_utf8_lossy(&pem);
+
+    let mut certs = Vec::new();
+    let mut rest = pem.as_ref();
+    while let Some(start) = rest.find(PEM_CERT_BEGIN) {
+        let Some(len) = rest[start..].find(PEM_CERT_END) else {
+            break;
+        };
+        let end = start + len + PEM_CERT_END.len();
+        let cert = Certificate::from_pem(&rest.as_bytes()[start..end]).map_err(|e| {
+            ExporterError::Config(format!("Invalid certificate in {}: {}", path.display(), e))
+        })?;
+        certs.push(cert);
+        rest = &rest[end..];
+    }
+
+    if certs.is_empty() {
+        return Err(ExporterError::Config(format!(
+            "No certificates found in CA bundle {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+/// Load the client identity for mutual TLS, if configured
+fn load_identity(tls: &TlsConfig) -> Result<Option<Identity>> {
+    let (cert_path, key_path) = match (&tls.client_cert, &tls.client_key) {
+        (None, None) => return Ok(None),
+        (Some(cert), Some(key)) => (cert, key),
+        _ => {
+            return Err(ExporterError::Config(
+                "client_cert and client_key must be set together".to_string(),
+            ))
+        }
+    };
+
+    let cert = read_file(cert_path, "client certificate")?;
+    let key = read_file(key_path, "client key")?;
+    Identity::from_pkcs8(&cert, &key)
+        .map(Some)
+        .map_err(|e| ExporterError::Config(format!("Invalid client certificate or key: {}", e)))
+}
+
+fn read_file(path: &Path, what: &str) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| {
+        ExporterError::Config(format!("Failed to read {} {}: {}", what, path.display(), e))
+    })
+}
@@ -28,29 +28,71 @@
 //!
 //! # JSON-RPC Protocol
 //!
-//! - [`JsonRpcRequest`] - Outgoing method calls
+//! - [`JsonRpcRequest`] - Outgoing method calls (DDP or JSON-RPC 2.0 envelope)
 //! - [`JsonRpcResponse`] - Incoming responses
-//! - [`DdpConnect`] - Initial handshake message
+//! - [`JsonRpcError`] - Error details in either envelope
+//! - [`DdpConnect`] - Initial handshake message (legacy endpoint only)
+//! - [`DdpSubscribe`] / [`CollectionUpdate`] - Event subscriptions and change notifications
 
 #![allow(dead_code)] // Allow unused fields in API structs for completeness
 use serde::{Deserialize, Serialize};
 
-/// JSON-RPC 2.0 request
+/// JSON-RPC request
+///
+/// Serializes to the legacy DDP envelope (`"msg": "method"`) or to a plain
+/// JSON-RPC 2.0 envelope (`"jsonrpc": "2.0"`) depending on the constructor used.
 #[derive(Debug, Serialize)]
 pub struct JsonRpcRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jsonrpc: Option<String>,
     pub id: String,
-    pub msg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
     pub method: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub params: Option<serde_json::Value>,
 }
 
-/// JSON-RPC 2.0 response
+impl JsonRpcRequest {
+    /// Build a method call for the legacy DDP endpoint (`/websocket`)
+    pub fn ddp(id: String, method: &str, params: Option<serde_json::Value>) -> Self {
+        Self {
+            jsonrpc: None,
+            id,
+            msg: Some("method".to_string()),
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    /// Build a method call for the versioned JSON-RPC 2.0 endpoint (`/api/current`)
+    ///
+    /// JSON-RPC 2.0 only allows structured params, so a `null` value is dropped
+    /// and a bare value is wrapped in a single-element array.
+    pub fn jsonrpc2(id: String, method: &str, params: Option<serde_json::Value>) -> Self {
+        let params = match params {
+            None | Some(serde_json::Value::Null) => None,
+            Some(value @ serde_json::Value::Array(_)) => Some(value),
+            Some(value @ serde_json::Value::Object(_)) => Some(value),
+            Some(value) => Some(serde_json::Value::Array(vec![value])),
+        };
+        Self {
+            jsonrpc: Some("2.0".to_string()),
+            id,
+            msg: None,
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+/// JSON-RPC response (DDP `result` message or JSON-RPC 2.0 response)
 #[derive(Debug, Deserialize)]
 pub struct JsonRpcResponse {
     #[allow(dead_code)] // Part of JSON-RPC spec
     pub id: String,
-    #[allow(dead_code)] // Part of JSON-RPC spec
+    #[allow(dead_code)] // Only present on the legacy DDP endpoint
+    #[serde(default)]
     pub msg: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<serde_json::Value>,
@@ -58,6 +100,10 @@ pub struct JsonRpcResponse {
     pub error: Option<JsonRpcError>,
 }
 
+/// Error object returned by TrueNAS
+///
+/// The legacy endpoint reports `error`/`errname`/`reason` at the top level.
+/// JSON-RPC 2.0 uses `code`/`message` and nests the middleware details in `data`.
 #[derive(Debug, Deserialize)]
 pub struct JsonRpcError {
     #[serde(default)]
@@ -66,6 +112,41 @@ pub struct JsonRpcError {
     pub errname: Option<String>,
     #[serde(default)]
     pub reason: Option<String>,
+    #[serde(default)]
+    pub code: Option<i64>,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub data: Option<Box<JsonRpcError>>,
+}
+
+impl JsonRpcError {
+    /// Most specific human-readable description available
+    pub fn reason(&self) -> String {
+        self.reason
+            .clone()
+            .or_else(|| self.data.as_ref().and_then(|data| data.reason.clone()))
+            .or_else(|| self.message.clone())
+            .unwrap_or_else(|| "Unknown error".to_string())
+    }
+
+    /// Middleware error name (e.g. `ENOTAUTHENTICATED`), if reported
+    pub fn errname(&self) -> Option<&str> {
+        self.errname
+            .as_deref()
+            .or_else(|| self.data.as_ref().and_then(|data| data.errname.as_deref()))
+    }
+
+    /// Whether the error means the session is no longer authenticated
+    pub fn is_not_authenticated(&self) -> bool {
+        self.errname() == Some("ENOTAUTHENTICATED") || self.reason().contains("ENOTAUTHENTICATED")
+    }
+
+    /// Whether the error means the called method does not exist on this release
+    pub fn is_method_not_found(&self) -> bool {
+        let reason = self.reason();
+        self.code == Some(-32601) || (reason.starts_with("Method") && reason.contains("not found"))
+    }
 }
 
 /// DDP Connect message
@@ -86,6 +167,50 @@ impl Default for DdpConnect {
     }
 }
 
+/// DDP subscription request (legacy endpoint only)
+///
+/// The JSON-RPC 2.0 endpoint subscribes through the `core.subscribe` method instead.
+#[derive(Debug, Serialize)]
+pub struct DdpSubscribe {
+    pub msg: String,
+    pub id: String,
+    pub name: String,
+}
+
+impl DdpSubscribe {
+    pub fn new(id: String, name: &str) -> Self {
+        Self {
+            msg: "sub".to_string(),
+            id,
+            name: name.to_string(),
+        }
+    }
+}
+
+/// Change notification for a subscribed collection
+///
+/// Sent as a bare DDP message on the legacy endpoint, or as the `params` of a
+/// `collection_update` notification on the JSON-RPC 2.0 endpoint.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CollectionUpdate {
+    pub msg: CollectionUpdateKind,
+    pub collection: String,
+    /// Primary key of the affected object (e.g. alert UUID, pool ID, app name)
+    #[serde(default)]
+    pub id: Option<serde_json::Value>,
+    /// New (or changed) fields of the object; absent for removals
+    #[serde(default)]
+    pub fields: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionUpdateKind {
+    Added,
+    Changed,
+    Removed,
+}
+
 /// Pool information from pool.query
 
 #[derive(Debug, Deserialize, Clone)]
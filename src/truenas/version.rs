@@ -0,0 +1,131 @@
+//! Server Version Detection and Capabilities
+//!
+//! TrueNAS releases are numbered `YY.MM` (e.g. 24.10 "Electric Eel", 25.04 "Fangtooth").
+//! The version is read once per connection and compared against the release each API
+//! feature first appeared in, so collectors for unsupported APIs can be skipped instead
+//! of failing every cycle.
+//!
+//! # Capability Matrix
+//!
+//! | Capability | API | Minimum release |
+//! |------------|-----|-----------------|
+//! | [`Capability::BootPoolState`] | `boot.get_state` | 22.12 |
+//! | [`Capability::IscsiClients`] | `iscsi.global.client_count` | 22.12 |
+//! | [`Capability::NfsClients`] | `nfs.get_nfs4_clients`, `nfs.get_nfs3_clients`, `nfs.client_count` | 24.04 |
+//! | [`Capability::Apps`] | `app.query` | 24.10 |
+//! | [`Capability::JsonRpc2Api`] | `/api/current` | 25.04 |
+//!
+//! When the version cannot be determined every capability is assumed to be present.
+
+use serde_json::Value;
+use std::fmt;
+
+/// Methods queried for the version, in order of preference
+pub const VERSION_METHODS: [&str; 2] = ["system.version", "system.info"];
+
+/// Version string from a `system.version` (string) or `system.info` (object) result
+pub fn reported_version(result: &Value) -> Option<String> {
+    match result {
+        Value::String(version) => Some(version.clone()),
+        info => info["version"].as_str().map(str::to_string),
+    }
+}
+
+/// A parsed TrueNAS release
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerVersion {
+    /// Version string as reported by the server (e.g. `TrueNAS-SCALE-24.10.2`)
+    pub raw: String,
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ServerVersion {
+    /// Parse the first `YY.MM` number found in a version string
+    ///
+    /// Accepts `TrueNAS-SCALE-24.10.2`, `TrueNAS-25.04.0`, `25.10-MASTER-20250101` and
+    /// similar. Returns `None` if no release number is present.
+    pub fn parse(raw: &str) -> Option<Self> {
+        raw.split(|c: char| !c.is_ascii_digit() && c != '.')
+            .find_map(|part| {
+                let mut numbers = part.split('.');
+                let major = numbers.next()?.parse().ok()?;
+                let minor = numbers.next()?.parse().ok()?;
+                Some((major, minor))
+            })
+            .map(|(major, minor)| Self {
+                raw: raw.to_string(),
+                major,
+                minor,
+            })
+    }
+
+    /// True if this release is at least `major.minor`
+    pub fn at_least(&self, (major, minor): (u32, u32)) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+
+    /// Whether this release offers the given capability
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.at_least(capability.min_version())
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+/// An API feature that is only present on some TrueNAS releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    BootPoolState,
+    IscsiClients,
+    NfsClients,
+    Apps,
+    JsonRpc2Api,
+}
+
+impl Capability {
+    /// Every capability
+    pub const ALL: [Capability; 5] = [
+        Capability::BootPoolState,
+        Capability::IscsiClients,
+        Capability::NfsClients,
+        Capability::Apps,
+        Capability::JsonRpc2Api,
+    ];
+
+    /// The capability an API method depends on, `None` if every release offers it
+    pub fn of_method(method: &str) -> Option<Capability> {
+        Self::ALL
+            .into_iter()
+            .find(|capability| capability.methods().contains(&method))
+    }
+
+    /// First release that offers this capability
+    pub fn min_version(self) -> (u32, u32) {
+        match self {
+            Capability::BootPoolState | Capability::IscsiClients => (22, 12),
+            Capability::NfsClients => (24, 4),
+            Capability::Apps => (24, 10),
+            Capability::JsonRpc2Api => (25, 4),
+        }
+    }
+
+    /// API methods that depend on this capability
+    pub fn methods(self) -> &'static [&'static str] {
+        match self {
+            Capability::BootPoolState => &["boot.get_state"],
+            Capability::IscsiClients => &["iscsi.global.client_count"],
+            Capability::NfsClients => &[
+                "nfs.get_nfs4_clients",
+                "nfs.get_nfs3_clients",
+                "nfs.client_count",
+            ],
+            Capability::Apps => &["app.query"],
+            Capability::JsonRpc2Api => &[],
+        }
+    }
+}
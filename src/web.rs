@@ -0,0 +1,470 @@
+//! TLS and Authentication for the Exporter's Endpoints
+//!
+//! `/metrics` exposes hostnames, disk serials, NFS client addresses and alert text, so
+//! the exporter can serve it over HTTPS and require credentials. Both are set up in a
+//! web configuration file (`--web.config.file`) in the format of the Prometheus
+//! exporter-toolkit, so the same file and the same Prometheus `scrape_config` work as for
+//! other exporters:
+//!
+//! ```yaml
+//! tls_server_config:
+//!   cert_file: /etc/truenas-exporter/exporter.pem
+//!   key_file: /etc/truenas-exporter/exporter-key.pem
+//! basic_auth_users:
+//!   prometheus: $2y$10$... # bcrypt hash, e.g. from `htpasswd -nBC 10 "" | tr -d ':'`
+//! bearer_tokens:
+//!   - $2y$10$...           # bcrypt hash of a token sent as `Authorization: Bearer`
+//! ```
+//!
+//! `bearer_tokens` is an extension; the other keys follow the exporter-toolkit. With
+//! users or tokens configured, every endpoint except `/health` requires one of them.
+//!
+//! # Reloading
+//!
+//! A background task ([`WebConfigFile::watch`]) checks the file every two seconds, like
+//! the exporter's own configuration, and reads it again when it changed; the certificate
+//! and key likewise when either of them changed. Reading and checking happen on a
+//! blocking thread, and the new settings are swapped in whole, so requests never wait
+//! for them. Renewed certificates and new credentials thus apply within seconds and
+//! without a restart.
+//! If the changed file is invalid, the previous settings stay in use. Whether the
+//! exporter speaks HTTPS at all is decided at startup.
+
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use base64::Engine;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, error, info, warn};
+
+/// Time a client has to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the web configuration file and the certificate are checked for changes
+const CHANGE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// bcrypt checks running at once; each takes tens of milliseconds of CPU
+const MAX_CONCURRENT_VERIFICATIONS: usize = 2;
+
+/// Contents of the web configuration file
+#[derive(Debug, Deserialize, Default)]
+pub struct WebConfig {
+    /// Serve HTTPS with this certificate and key
+    #[serde(default)]
+    pub tls_server_config: Option<TlsServerConfig>,
+    /// bcrypt password hashes by user name
+    #[serde(default)]
+    pub basic_auth_users: HashMap<String, SecretString>,
+    /// bcrypt hashes of accepted bearer tokens
+    #[serde(default)]
+    pub bearer_tokens: Vec<SecretString>,
+}
+
+/// `tls_server_config` of the web configuration file
+#[derive(Debug, Deserialize)]
+pub struct TlsServerConfig {
+    /// PEM certificate, optionally followed by its chain
+    pub cert_file: PathBuf,
+    /// PEM (PKCS#8) private key for `cert_file`
+    pub key_file: PathBuf,
+    /// Only `NoClientCert` is supported
+    #[serde(default)]
+    pub client_auth_type: Option<String>,
+}
+
+impl WebConfig {
+    /// Read and check a web configuration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let config: Self = config::Config::builder()
+            .add_source(config::File::from(path).format(config::FileFormat::Yaml))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .with_context(|| format!("Failed to read web config {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Whether requests must carry credentials
+    pub fn requires_auth(&self) -> bool {
+        !self.basic_auth_users.is_empty() || !self.bearer_tokens.is_empty()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(tls) = &self.tls_server_config {
+            match tls.client_auth_type.as_deref() {
+                None | Some("NoClientCert") => {}
+                Some(other) => bail!(
+                    "client_auth_type {:?} is not supported (only NoClientCert)",
+                    other
+                ),
+            }
+            tls_acceptor(tls)?;
+        }
+        let hashes = self
+            .basic_auth_users
+            .values()
+            .chain(&self.bearer_tokens)
+            .map(|hash| hash.expose_secret());
+        for hash in hashes {
+            // Checking any password parses the hash
+            bcrypt::verify("", hash).context("Invalid bcrypt hash in web config")?;
+        }
+        Ok(())
+    }
+}
+
+/// The web configuration file, re-read when it changes
+pub struct WebConfigFile {
+    path: PathBuf,
+    current: RwLock<Loaded>,
+    verifying: Semaphore,
+}
+
+struct Loaded {
+    modified: Option<SystemTime>,
+    config: Arc<WebConfig>,
+    /// SHA-256 of credentials that passed bcrypt against `config`, so each is only
+    /// checked once; replaced along with `config`
+    verified: Arc<Mutex<HashSet<[u8; 32]>>>,
+    tls: Option<LoadedTls>,
+}
+
+struct LoadedTls {
+    modified: (Option<SystemTime>, Option<SystemTime>),
+    acceptor: tokio_native_tls::TlsAcceptor,
+}
+
+impl WebConfigFile {
+    /// Load the file; errors here stop the exporter from starting
+    pub fn open(path: &Path) -> Result<Self> {
+        let modified = modified(path);
+        let config = WebConfig::load(path)?;
+        let tls = config
+            .tls_server_config
+            .as_ref()
+            .map(load_tls)
+            .transpose()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            current: RwLock::new(Loaded {
+                modified,
+                config: Arc::new(config),
+                verified: Arc::default(),
+                tls,
+            }),
+            verifying: Semaphore::new(MAX_CONCURRENT_VERIFICATIONS),
+        })
+    }
+
+    /// Whether the exporter was started with HTTPS
+    pub fn tls_enabled(&self) -> bool {
+        self.current
+            .read()
+            .expect("web config lock poisoned")
+            .tls
+            .is_some()
+    }
+
+    /// The current settings
+    pub fn config(&self) -> Arc<WebConfig> {
+        self.current
+            .read()
+            .expect("web config lock poisoned")
+            .config
+            .clone()
+    }
+
+    /// TLS acceptor for a new connection
+    fn tls_acceptor(&self) -> Option<tokio_native_tls::TlsAcceptor> {
+        self.current
+            .read()
+            .expect("web config lock poisoned")
+            .tls
+            .as_ref()
+            .map(|tls| tls.acceptor.clone())
+    }
+
+    /// Re-read the file and the certificate every [`CHANGE_CHECK_INTERVAL`] while `web`
+    /// is in use
+    pub async fn watch(web: Weak<Self>) {
+        let mut checks = tokio::time::interval(CHANGE_CHECK_INTERVAL);
+        checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        checks.tick().await;
+        loop {
+            checks.tick().await;
+            let Some(web) = web.upgrade() else {
+                return;
+            };
+            // File IO and checking every bcrypt hash would stall an async worker
+            if let Err(e) = tokio::task::spawn_blocking(move || web.reload_if_changed()).await {
+                error!("Failed to check the web config for changes: {}", e);
+            }
+        }
+    }
+
+    /// Re-read the file and the certificate if they changed since the last check
+    ///
+    /// Everything is read and checked before the lock is taken to swap it in.
+    fn reload_if_changed(&self) {
+        let (known, known_tls, mut config) = {
+            let current = self.current.read().expect("web config lock poisoned");
+            (
+                current.modified,
+                current.tls.as_ref().map(|tls| tls.modified),
+                current.config.clone(),
+            )
+        };
+
+        let file_modified = modified(&self.path);
+        let mut reloaded = None;
+        if file_modified != known {
+            match WebConfig::load(&self.path) {
+                Ok(new) => {
+                    info!("Reloaded web config {}", self.path.display());
+                    config = Arc::new(new);
+                    reloaded = Some(config.clone());
+                }
+                Err(e) => error!("Keeping the previous web config: {:#}", e),
+            }
+        }
+
+        // HTTPS cannot be switched on or off while running
+        let mut tls = None;
+        if let (Some(known_tls), Some(tls_config)) = (known_tls, &config.tls_server_config) {
+            let files = (
+                modified(&tls_config.cert_file),
+                modified(&tls_config.key_file),
+            );
+            // A new web config may name other files
+            if reloaded.is_some() || files != known_tls {
+                tls = Some(match load_tls(tls_config) {
+                    Ok(loaded) => {
+                        info!(
+                            "Reloaded TLS certificate {}",
+                            tls_config.cert_file.display()
+                        );
+                        Ok(loaded)
+                    }
+                    Err(e) => {
+                        // Tried again once either file changes
+                        error!("Keeping the previous TLS certificate: {:#}", e);
+                        Err(files)
+                    }
+                });
+            }
+        }
+
+        let mut current = self.current.write().expect("web config lock poisoned");
+        current.modified = file_modified;
+        if let Some(config) = reloaded {
+            current.config = config;
+            current.verified = Arc::default();
+        }
+        match (current.tls.as_mut(), tls) {
+            (Some(current), Some(Ok(loaded))) => *current = loaded,
+            (Some(current), Some(Err(files))) => current.modified = files,
+            _ => {}
+        }
+    }
+
+    /// Whether the request carries credentials accepted by the current settings
+    async fn authorized(&self, headers: &HeaderMap) -> bool {
+        let (config, verified) = {
+            let current = self.current.read().expect("web config lock poisoned");
+            (current.config.clone(), current.verified.clone())
+        };
+        if !config.requires_auth() {
+            return true;
+        }
+        let Some(authorization) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return false;
+        };
+
+        let key: [u8; 32] = Sha256::digest(authorization.as_bytes()).into();
+        if verified
+            .lock()
+            .expect("credential cache poisoned")
+            .contains(&key)
+        {
+            return true;
+        }
+
+        // bcrypt is slow by design: keep it off the async workers, and bound how much
+        // CPU a stream of wrong credentials can take
+        let Ok(_permit) = self.verifying.acquire().await else {
+            return false;
+        };
+        let authorization = authorization.to_string();
+        let accepted = tokio::task::spawn_blocking(move || verify(&config, &authorization))
+            .await
+            .unwrap_or(false);
+        // Credentials checked against a config replaced meanwhile land in its old cache
+        if accepted {
+            verified
+                .lock()
+                .expect("credential cache poisoned")
+                .insert(key);
+        }
+        accepted
+    }
+}
+
+/// Middleware rejecting requests without valid credentials
+pub async fn require_auth(
+    State(web): State<Arc<WebConfigFile>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Health checks from container runtimes carry no credentials
+    if request.uri().path() == "/health" || web.authorized(request.headers()).await {
+        return next.run(request).await;
+    }
+    let mut response = (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    if !web.config().basic_auth_users.is_empty() {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"truenas-exporter\""),
+        );
+    }
+    response
+}
+
+/// Serve `app` over HTTPS, with a fresh TLS acceptor per connection
+///
+/// Once `shutdown` completes, no more connections are accepted and open ones are closed
+/// after their in-flight requests.
+pub async fn serve_tls(
+    listener: TcpListener,
+    app: Router,
+    web: Arc<WebConfigFile>,
+    shutdown: impl Future<Output = ()> + Send,
+) -> Result<()> {
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let (tcp, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        let Some(acceptor) = web.tls_acceptor() else {
+            bail!("TLS is no longer configured");
+        };
+        let service = TowerToHyperService::new(app.clone());
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let tls = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                Ok(Ok(tls)) => tls,
+                Ok(Err(e)) => {
+                    debug!("TLS handshake with {} failed: {}", remote, e);
+                    return;
+                }
+                Err(_) => {
+                    debug!("TLS handshake with {} timed out", remote);
+                    return;
+                }
+            };
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder
+                .serve_connection(TokioIo::new(tls), service)
+                .into_owned();
+            if let Err(e) = watcher.watch(connection).await {
+                debug!("Connection from {} failed: {}", remote, e);
+            }
+        });
+    }
+
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+fn load_tls(config: &TlsServerConfig) -> Result<LoadedTls> {
+    Ok(LoadedTls {
+        modified: (modified(&config.cert_file), modified(&config.key_file)),
+        acceptor: tls_acceptor(config)?,
+    })
+}
+
+fn tls_acceptor(config: &TlsServerConfig) -> Result<tokio_native_tls::TlsAcceptor> {
+    let read = |path: &Path| {
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+    };
+    let identity =
+        native_tls::Identity::from_pkcs8(&read(&config.cert_file)?, &read(&config.key_file)?)
+            .with_context(|| {
+                format!(
+                    "Invalid certificate or key in {}",
+                    config.cert_file.display()
+                )
+            })?;
+    let acceptor = native_tls::TlsAcceptor::new(identity).context("Failed to set up TLS")?;
+    Ok(acceptor.into())
+}
+
+/// User and password from the credentials of a `Basic` authorization header
+fn basic_credentials(encoded: &str) -> Option<(String, String)> {
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// Whether an `Authorization` header value matches a configured user or token
+fn verify(config: &WebConfig, authorization: &str) -> bool {
+    match authorization.split_once(' ') {
+        Some((scheme, credentials)) if scheme.eq_ignore_ascii_case("basic") => {
+            basic_credentials(credentials).is_some_and(|(user, password)| {
+                config
+                    .basic_auth_users
+                    .get(&user)
+                    .is_some_and(|hash| matches_hash(&password, hash))
+            })
+        }
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => config
+            .bearer_tokens
+            .iter()
+            .any(|hash| matches_hash(token.trim(), hash)),
+        _ => false,
+    }
+}
+
+fn matches_hash(secret: &str, hash: &SecretString) -> bool {
+    bcrypt::verify(secret, hash.expose_secret()).unwrap_or(false)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
@@ -0,0 +1,194 @@
+//! Authentication strategy tests
+//!
+//! Runs the `ConnectionManager` against the fake TrueNAS middleware set up to accept one
+//! API key, one username/password pair, and tokens it has issued itself.
+
+use secrecy::SecretString;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use truenas_exporter::config::{AuthConfig, AuthMethod, TrueNasConfig};
+use truenas_exporter::error::ExporterError;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::ConnectionManager;
+
+/// Start a server accepting `good-key` and `exporter`/`secret`
+///
+/// `test.disconnect` closes the socket; `test.otp` users are asked for a one-time password.
+async fn start_server() -> FakeTrueNas {
+    let nas = FakeTrueNas::start().await;
+    nas.require_credentials("good-key", "exporter", "secret");
+    nas.require_otp("test.otp");
+    nas.disconnect_on("test.disconnect");
+    for method in ["test.echo", "test.first", "test.second"] {
+        nas.respond(method, json!(method));
+    }
+    nas
+}
+
+/// The auth calls the server received, in order
+fn auth_calls(nas: &FakeTrueNas) -> Vec<String> {
+    nas.calls()
+        .into_iter()
+        .filter(|method| method.starts_with("auth."))
+        .collect()
+}
+
+fn config(nas: &FakeTrueNas, api_key: &str, auth: AuthConfig) -> Arc<TrueNasConfig> {
+    Arc::new(TrueNasConfig {
+        api_key: SecretString::from(api_key),
+        auth,
+        ..nas.config()
+    })
+}
+
+fn password_auth(method: AuthMethod, username: &str, login_ex: bool) -> AuthConfig {
+    AuthConfig {
+        method,
+        username: Some(username.to_string()),
+        password: Some(SecretString::from("secret")),
+        login_ex,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_password_auth_with_both_login_flows() {
+    for login_ex in [false, true] {
+        // Given: Username/password auth with the plain or login_ex flow
+        let nas = start_server().await;
+        let manager = ConnectionManager::new(config(
+            &nas,
+            "",
+            password_auth(AuthMethod::Password, "exporter", login_ex),
+        ));
+
+        // When: A query is executed
+        let result: String = manager.execute_query("test.echo", None).await.unwrap();
+
+        // Then: The matching login method was used
+        assert_eq!(result, "test.echo");
+        let expected = if login_ex {
+            "auth.login_ex"
+        } else {
+            "auth.login"
+        };
+        assert_eq!(auth_calls(&nas), vec![expected]);
+    }
+}
+
+#[tokio::test]
+async fn test_rejected_credentials_are_auth_errors() {
+    let cases = [
+        (
+            "bad-key",
+            AuthConfig::default(),
+            "API key rejected by TrueNAS",
+        ),
+        (
+            "",
+            password_auth(AuthMethod::Password, "intruder", false),
+            "username/password rejected by TrueNAS",
+        ),
+        (
+            "",
+            password_auth(AuthMethod::Password, "test.otp", true),
+            "one-time password",
+        ),
+        (
+            "",
+            AuthConfig {
+                method: AuthMethod::Password,
+                ..Default::default()
+            },
+            "requires username and password",
+        ),
+    ];
+
+    for (api_key, auth, expected) in cases {
+        // Given: Credentials the server will not accept
+        let nas = start_server().await;
+        let manager = ConnectionManager::new(config(&nas, api_key, auth));
+
+        // When: A query is executed
+        let err = manager
+            .execute_query::<String>("test.echo", None)
+            .await
+            .unwrap_err();
+
+        // Then: An auth error explains why
+        assert!(matches!(err, ExporterError::Auth(_)), "{:?}", err);
+        assert!(err.to_string().contains(expected), "{}", err);
+    }
+}
+
+#[tokio::test]
+async fn test_api_key_via_login_ex() {
+    // Given: API key auth through auth.login_ex
+    let nas = start_server().await;
+    let auth = AuthConfig {
+        username: Some("exporter".to_string()),
+        login_ex: true,
+        ..Default::default()
+    };
+    let manager = ConnectionManager::new(config(&nas, "good-key", auth));
+
+    // When: A query is executed
+    let result: String = manager.execute_query("test.echo", None).await.unwrap();
+
+    // Then: The API_KEY_PLAIN mechanism was accepted
+    assert_eq!(result, "test.echo");
+    assert_eq!(auth_calls(&nas), vec!["auth.login_ex"]);
+}
+
+#[tokio::test]
+async fn test_token_auth_reconnects_with_token() {
+    // Given: Token auth bootstrapped from username/password
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(config(
+        &nas,
+        "",
+        password_auth(AuthMethod::Token, "exporter", false),
+    ));
+    let _: String = manager.execute_query("test.first", None).await.unwrap();
+
+    // When: The connection drops and the next query reconnects
+    let _ = manager
+        .execute_query::<String>("test.disconnect", None)
+        .await;
+    let result: String = manager.execute_query("test.second", None).await.unwrap();
+
+    // Then: The reconnect logs in with the token instead of the password
+    assert_eq!(result, "test.second");
+    assert_eq!(
+        auth_calls(&nas),
+        vec!["auth.login", "auth.generate_token", "auth.login_with_token"]
+    );
+}
+
+#[tokio::test]
+async fn test_token_is_renewed_before_expiry() {
+    // Given: Token auth from an API key with a one second token lifetime
+    let nas = start_server().await;
+    let auth = AuthConfig {
+        method: AuthMethod::Token,
+        token_ttl_seconds: 1,
+        ..Default::default()
+    };
+    let manager = ConnectionManager::new(config(&nas, "good-key", auth));
+    let _: String = manager.execute_query("test.first", None).await.unwrap();
+
+    // When: Most of the lifetime has passed before the next query
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    let _: String = manager.execute_query("test.second", None).await.unwrap();
+
+    // Then: A new token was generated on the live connection
+    assert_eq!(
+        auth_calls(&nas),
+        vec![
+            "auth.login_with_api_key",
+            "auth.generate_token",
+            "auth.generate_token"
+        ]
+    );
+}
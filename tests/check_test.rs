@@ -0,0 +1,167 @@
+//! `check-config` and `test-connection` tests
+
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+use truenas_exporter::check::{self, Status};
+use truenas_exporter::config::{Config, MetricsConfig, ServerConfig, TrueNasConfig};
+use truenas_exporter::test_support::FakeTrueNas;
+
+fn config(truenas: TrueNasConfig, collectors: &[(&str, bool)]) -> Config {
+    Config {
+        truenas: truenas.into(),
+        server: ServerConfig {
+            addr: "127.0.0.1".to_string(),
+            port: 9100,
+            web_config_file: None,
+            shutdown_timeout_seconds: 5,
+        },
+        metrics: MetricsConfig {
+            collectors: collectors
+                .iter()
+                .map(|(name, enabled)| (name.to_string(), *enabled))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        },
+        probe: Default::default(),
+    }
+}
+
+fn status(report: &check::Report, target: &str, name: &str) -> Status {
+    report
+        .get(target, name)
+        .unwrap_or_else(|| panic!("no {} check for {}\n{}", name, target, report.table()))
+        .status
+}
+
+#[test]
+fn test_check_config_passes_tls_with_api_key() {
+    // Given: An API key sent over TLS to the HTTPS port
+    let truenas = TrueNasConfig {
+        host: "nas.example:443".to_string(),
+        api_key: "key".to_string().into(),
+        use_tls: true,
+        ..Default::default()
+    };
+
+    // When: The configuration is checked
+    let report = check::check_config(&config(truenas, &[]));
+
+    // Then: Every check passes
+    assert!(report.passed(), "{}", report.table());
+    assert_eq!(status(&report, "nas.example:443", "host"), Status::Pass);
+    assert_eq!(status(&report, "nas.example:443", "auth"), Status::Pass);
+    assert_eq!(status(&report, "-", "collectors"), Status::Pass);
+}
+
+#[test]
+fn test_check_config_rejects_plain_api_key_and_mismatched_port() {
+    // Given: An API key over plain WebSocket to the HTTPS port, and a misspelt collector
+    let truenas = TrueNasConfig {
+        host: "nas.example:443".to_string(),
+        api_key: "key".to_string().into(),
+        use_tls: false,
+        ..Default::default()
+    };
+
+    // When: The configuration is checked
+    let report = check::check_config(&config(truenas, &[("smrat", false)]));
+
+    // Then: Each mistake fails its own check
+    assert!(!report.passed());
+    assert_eq!(status(&report, "nas.example:443", "host"), Status::Fail);
+    assert_eq!(status(&report, "nas.example:443", "auth"), Status::Fail);
+    assert_eq!(status(&report, "-", "collectors"), Status::Fail);
+    let table = report.table();
+    assert!(table.contains("use_tls = false"), "{}", table);
+    assert!(table.contains("smrat"), "{}", table);
+    assert!(table.contains("3 failed"), "{}", table);
+}
+
+#[tokio::test]
+async fn test_connection_reports_each_collector() {
+    // Given: A TrueNAS answering pool queries but not alert queries
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    let collectors: Vec<(&str, bool)> = truenas_exporter::collectors::registry::names()
+        .map(|name| (name, matches!(name, "pool" | "alert")))
+        .collect();
+    let config = config(nas.config(), &collectors);
+
+    // When: The connection is tested
+    let report = check::test_connection(&config).await;
+
+    // Then: The login and each collector get a row
+    let host = nas.host();
+    assert_eq!(status(&report, &host, "connect"), Status::Pass);
+    assert_eq!(status(&report, &host, "pool"), Status::Pass);
+    assert_eq!(status(&report, &host, "alert"), Status::Fail);
+    assert_eq!(status(&report, &host, "smart"), Status::Skip);
+    assert!(!report.passed());
+    assert_eq!(nas.call_count("pool.query"), 1);
+
+    // And: The session is closed afterwards
+    for _ in 0..50 {
+        if nas.closed_connections() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(nas.closed_connections(), 1);
+}
+
+#[tokio::test]
+async fn test_connection_fails_on_rejected_credentials() {
+    // Given: A TrueNAS rejecting the API key
+    let nas = FakeTrueNas::start().await;
+    nas.reject_auth(true);
+
+    // When: The connection is tested
+    let report = check::test_connection(&config(nas.config(), &[])).await;
+
+    // Then: The login fails and no collector is run
+    assert_eq!(status(&report, &nas.host(), "connect"), Status::Fail);
+    assert_eq!(report.checks.len(), 1, "{}", report.table());
+    assert!(!report.passed());
+}
+
+#[test]
+fn test_check_config_reports_settings_load_would_reject() {
+    // Given: A file with a duplicate host and a zero scrape interval
+    let path = std::env::temp_dir().join(format!(
+        "truenas-exporter-check-{}.toml",
+        std::process::id()
+    ));
+    let toml = r#"
+        [[truenas]]
+        host = "nas01:443"
+        api_key = "key"
+        use_tls = true
+
+        [[truenas]]
+        host = "nas01:443"
+        api_key = "key"
+        use_tls = true
+
+        [server]
+        [metrics]
+        scrape_interval_seconds = 0
+    "#;
+    std::fs::write(&path, toml).unwrap();
+
+    // When: The file is read without validation and checked
+    let config = Config::read(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert!(config.as_ref().unwrap().validate().is_err());
+    let report = check::check_config(&config.unwrap());
+
+    // Then: Each rejected setting is a failed row instead of a load error
+    assert_eq!(status(&report, "-", "targets"), Status::Fail);
+    assert_eq!(status(&report, "-", "intervals"), Status::Fail);
+    let table = report.table();
+    assert!(table.contains("nas01:443"), "{}", table);
+    assert!(table.contains("scrape_interval_seconds"), "{}", table);
+}
@@ -1,6 +1,10 @@
 //! Simplified collector tests focusing on critical behavior
 
-use truenas_exporter::collectors::{collect_with_handler, CollectionStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use truenas_exporter::collectors::{
+    collect_with_handler, run_collectors, CollectionResult, CollectionStatus, NamedCollector,
+};
 use truenas_exporter::error::ExporterError;
 use truenas_exporter::metrics::MetricsCollector;
 
@@ -242,3 +246,81 @@ fn test_all_metric_types_can_be_set() {
     assert!(rendered.contains("truenas_alert_count"));
     assert!(rendered.contains("truenas_smart_test_status"));
 }
+
+/// A collector that takes `delay` and tracks how many run at once
+async fn tracked_collector(
+    delay: Duration,
+    running: &AtomicUsize,
+    peak: &AtomicUsize,
+) -> CollectionResult {
+    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+    peak.fetch_max(now, Ordering::SeqCst);
+    tokio::time::sleep(delay).await;
+    running.fetch_sub(1, Ordering::SeqCst);
+    Ok(CollectionStatus::Success)
+}
+
+#[tokio::test]
+async fn test_run_collectors_respects_concurrency_limit() {
+    // Given: Six 50ms collectors and a limit of two
+    let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let queue: Vec<NamedCollector<'_>> = (0..6)
+        .map(|_| -> NamedCollector<'_> {
+            (
+                "tracked",
+                Box::pin(tracked_collector(
+                    Duration::from_millis(50),
+                    &running,
+                    &peak,
+                )),
+            )
+        })
+        .collect();
+
+    // When: They are run
+    let started = Instant::now();
+    let results = run_collectors(queue, 2, Duration::from_secs(5)).await;
+
+    // Then: All succeed, never more than two at once, yet faster than one by one
+    assert_eq!(results.len(), 6);
+    assert!(results
+        .iter()
+        .all(|(_, result, _)| matches!(result, Ok(CollectionStatus::Success))));
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+    assert!(started.elapsed() < Duration::from_millis(290));
+}
+
+#[tokio::test]
+async fn test_run_collectors_times_out_slow_collector() {
+    // Given: One collector that hangs and one that is quick
+    let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let queue: Vec<NamedCollector<'_>> = vec![
+        (
+            "slow",
+            Box::pin(tracked_collector(Duration::from_secs(60), &running, &peak)),
+        ),
+        (
+            "fast",
+            Box::pin(tracked_collector(
+                Duration::from_millis(10),
+                &running,
+                &peak,
+            )),
+        ),
+    ];
+
+    // When: They run with a 200ms timeout
+    let started = Instant::now();
+    let results = run_collectors(queue, 4, Duration::from_millis(200)).await;
+
+    // Then: The slow one is marked failed without holding up the fast one
+    assert!(started.elapsed() < Duration::from_secs(2));
+    for (name, result, _) in results {
+        let expected = if name == "slow" {
+            CollectionStatus::Failed
+        } else {
+            CollectionStatus::Success
+        };
+        assert_eq!(result.unwrap(), expected, "{}", name);
+    }
+}
@@ -0,0 +1,127 @@
+//! Helpers shared by the integration tests
+//!
+//! Test files include them with `mod common;`, and each one only uses some of them.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use truenas_exporter::config::{Config, MetricsConfig, ProbeConfig, ServerConfig, TrueNasHosts};
+use truenas_exporter::server;
+
+/// A local port that is free right now
+pub async fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// Exporter configuration listening on `port` on localhost
+pub fn exporter_config(
+    truenas: impl Into<TrueNasHosts>,
+    port: u16,
+    metrics: MetricsConfig,
+) -> Config {
+    Config {
+        truenas: truenas.into(),
+        server: ServerConfig {
+            addr: "127.0.0.1".to_string(),
+            port,
+            web_config_file: None,
+            shutdown_timeout_seconds: 5,
+        },
+        metrics,
+        probe: ProbeConfig::default(),
+    }
+}
+
+/// Wait until the exporter accepts connections on `port`
+pub async fn wait_for_port(port: u16) {
+    for _ in 0..100 {
+        if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Start the exporter with `config` and wait until it accepts connections
+pub async fn start_exporter_with(config: Config) -> u16 {
+    let port = config.server.port;
+    tokio::spawn(server::start(config));
+    wait_for_port(port).await;
+    port
+}
+
+/// Start the exporter on a free port and wait until it accepts connections
+pub async fn start_exporter(truenas: impl Into<TrueNasHosts>, metrics: MetricsConfig) -> u16 {
+    start_exporter_with(exporter_config(truenas, free_port().await, metrics)).await
+}
+
+/// Send a request with extra header lines over `stream`; returns the head and raw body
+pub async fn send_request<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    method: &str,
+    path: &str,
+    headers: &str,
+) -> Option<(String, Vec<u8>)> {
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n",
+        method, path, headers
+    );
+    stream.write_all(request.as_bytes()).await.ok()?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.ok()?;
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&response[..split]).to_string();
+    Some((head, response[split + 4..].to_vec()))
+}
+
+/// Send a request to the exporter and return the response head and body
+pub async fn http_request(
+    port: u16,
+    method: &str,
+    path: &str,
+    headers: &str,
+) -> Option<(String, String)> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.ok()?;
+    let (head, body) = send_request(stream, method, path, headers).await?;
+    Some((head, String::from_utf8_lossy(&body).to_string()))
+}
+
+/// GET a path from the exporter and return the response body
+pub async fn http_get(port: u16, path: &str) -> Option<String> {
+    http_request(port, "GET", path, "")
+        .await
+        .map(|(_, body)| body)
+}
+
+/// Poll `condition` for up to a second; true if it came to hold
+pub async fn eventually(condition: impl Fn() -> bool) -> bool {
+    for _ in 0..50 {
+        if condition() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    condition()
+}
+
+/// A path in the temp directory that no other test uses
+pub fn scratch_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "truenas-exporter-{}-{}-{}",
+        name,
+        std::process::id(),
+        fastrand::u64(..)
+    ))
+}
+
+/// A fresh, empty directory
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = scratch_path(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
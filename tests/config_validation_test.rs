@@ -2,7 +2,7 @@
 //!
 //! Tests that verify configuration defaults and structure.
 
-use truenas_exporter::config::{MetricsConfig, ServerConfig, TrueNasConfig};
+use truenas_exporter::config::{Config, MetricsConfig, ServerConfig, TrueNasConfig};
 
 #[test]
 fn test_default_server_config() {
@@ -11,6 +11,8 @@ fn test_default_server_config() {
     let config = ServerConfig {
         addr: "0.0.0.0".to_string(),
         port: 9100,
+        web_config_file: None,
+        shutdown_timeout_seconds: 5,
     };
 
     // Then: Should have expected default values
@@ -31,6 +33,7 @@ fn test_truenas_config_defaults_via_serde() {
         api_key: SecretString::new(String::new().into()),
         use_tls: false,
         verify_ssl: true,
+        ..Default::default()
     };
 
     // Then: Struct should be correctly defined and constructible
@@ -46,6 +49,7 @@ fn test_metrics_config_defaults_via_serde() {
         scrape_interval_seconds: 60,
         collect_pool_metrics: true,
         collect_system_metrics: true,
+        ..Default::default()
     };
 
     // Then: Should have expected default values
@@ -60,6 +64,8 @@ fn test_config_structs_have_sensible_defaults() {
     let server = ServerConfig {
         addr: "0.0.0.0".to_string(),
         port: 9100,
+        web_config_file: None,
+        shutdown_timeout_seconds: 5,
     };
     use secrecy::SecretString;
     let truenas = TrueNasConfig {
@@ -67,11 +73,13 @@ fn test_config_structs_have_sensible_defaults() {
         api_key: SecretString::new(String::new().into()),
         use_tls: false,
         verify_ssl: true,
+        ..Default::default()
     };
     let metrics = MetricsConfig {
         scrape_interval_seconds: 60,
         collect_pool_metrics: true,
         collect_system_metrics: true,
+        ..Default::default()
     };
 
     // When: Checking values
@@ -94,6 +102,8 @@ fn test_server_config_construction() {
     let config = ServerConfig {
         addr: "127.0.0.1".to_string(),
         port: 8080,
+        web_config_file: None,
+        shutdown_timeout_seconds: 5,
     };
 
     // Then: Values should be set correctly
@@ -109,6 +119,7 @@ fn test_metrics_config_construction() {
         scrape_interval_seconds: 30,
         collect_pool_metrics: true,
         collect_system_metrics: false,
+        ..Default::default()
     };
 
     // Then: Values should be set correctly
@@ -116,3 +127,231 @@ fn test_metrics_config_construction() {
     assert!(config.collect_pool_metrics);
     assert!(!config.collect_system_metrics);
 }
+
+/// Write a configuration file and load it
+fn load_toml(name: &str, toml: &str) -> anyhow::Result<Config> {
+    let path = std::env::temp_dir().join(format!(
+        "truenas-exporter-{}-{}.toml",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, toml).unwrap();
+    let config = Config::load(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    config
+}
+
+#[test]
+fn test_truenas_host_list() {
+    // Given: Two [[truenas]] entries with their own credentials
+    let toml = r#"
+        [[truenas]]
+        host = "nas01:443"
+        api_key = "key-1"
+
+        [[truenas]]
+        host = "nas02:443"
+        api_key = "key-2"
+        use_tls = true
+
+        [server]
+        [metrics]
+    "#;
+
+    // When: The configuration is loaded
+    let config = load_toml("host-list", toml).unwrap();
+
+    // Then: Both hosts are kept, in order, and series are labelled by host
+    let hosts: Vec<&str> = config
+        .truenas
+        .hosts()
+        .iter()
+        .map(|truenas| truenas.host.as_str())
+        .collect();
+    assert_eq!(hosts, ["nas01:443", "nas02:443"]);
+    assert!(config.truenas.hosts()[1].use_tls);
+    assert!(config.truenas.labelled());
+
+    // And: A single [truenas] table keeps unlabelled series
+    let single = load_toml(
+        "single-host",
+        "[truenas]\nhost = \"nas01:443\"\n[server]\n[metrics]\n",
+    )
+    .unwrap();
+    assert_eq!(single.truenas.hosts().len(), 1);
+    assert!(!single.truenas.labelled());
+}
+
+#[test]
+fn test_duplicate_truenas_hosts_are_rejected() {
+    // Given: The same host listed twice
+    let toml = r#"
+        [[truenas]]
+        host = "nas01:443"
+
+        [[truenas]]
+        host = "nas01:443"
+
+        [server]
+        [metrics]
+    "#;
+
+    // When: The configuration is loaded
+    let err = load_toml("duplicate-hosts", toml).unwrap_err();
+
+    // Then: It is rejected with the offending host named
+    assert!(err.to_string().contains("nas01:443"), "{}", err);
+}
+
+#[test]
+fn test_zero_collector_interval_is_rejected() {
+    // Given: A collector interval of zero seconds
+    let toml = r#"
+        [server]
+        [metrics.intervals]
+        pool = 0
+    "#;
+
+    // When: The configuration is loaded
+    let err = load_toml("zero-interval", toml).unwrap_err();
+
+    // Then: It is rejected with the offending collector named
+    assert!(err.to_string().contains("intervals.pool"), "{}", err);
+}
+
+#[test]
+fn test_zero_scrape_interval_and_timeout_are_rejected() {
+    // Given: A scrape interval and a collector timeout of zero seconds
+    let toml = r#"
+        [server]
+        [metrics]
+        scrape_interval_seconds = 0
+        collector_timeout_seconds = 0
+    "#;
+
+    // When: The configuration is loaded
+    let err = load_toml("zero-scrape-interval", toml).unwrap_err();
+
+    // Then: Both settings are named
+    let message = err.to_string();
+    assert!(message.contains("scrape_interval_seconds"), "{}", message);
+    assert!(message.contains("collector_timeout_seconds"), "{}", message);
+}
+
+#[test]
+fn test_unknown_collector_is_rejected() {
+    // Given: A misspelt collector name
+    let toml = r#"
+        [server]
+        [metrics.collectors]
+        smrat = false
+    "#;
+
+    // When: The configuration is loaded
+    let err = load_toml("unknown-collector", toml).unwrap_err();
+
+    // Then: It is rejected with the unknown name and the known ones listed
+    let message = err.to_string();
+    assert!(message.contains("smrat"), "{}", message);
+    assert!(message.contains("smart"), "{}", message);
+}
+
+#[test]
+fn test_probe_modules_need_a_target_allowlist() {
+    // Given: A probe module with its connection settings and allowed targets
+    let toml = r#"
+        [server]
+        [metrics]
+        [probe.modules.lab]
+        api_key = "lab-key"
+        use_tls = true
+        targets = ["nas01.example:443"]
+    "#;
+
+    // When: The configuration is loaded
+    let config = load_toml("probe-module", toml).unwrap();
+
+    // Then: Only the listed target is probed, with the module's settings
+    let truenas = config.probe_module("lab", "nas01.example:443").unwrap();
+    assert_eq!(truenas.host, "nas01.example:443");
+    assert!(truenas.use_tls);
+    assert!(config.probe_module("lab", "attacker.example:80").is_none());
+    assert!(config
+        .probe_module("default", "nas01.example:443")
+        .is_none());
+
+    // And: A module without targets is rejected
+    let err = load_toml("probe-no-targets", &toml.replace("targets", "# targets")).unwrap_err();
+    assert!(
+        err.to_string().contains("probe.modules.lab.targets"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_zero_event_resync_interval_is_rejected() {
+    // Given: Push events with a resync interval of zero seconds
+    let toml = r#"
+        [server]
+        [metrics]
+        subscribe_events = true
+        event_resync_interval_seconds = 0
+    "#;
+
+    // When: The configuration is loaded
+    let err = load_toml("zero-resync-interval", toml).unwrap_err();
+
+    // Then: The interval is named
+    assert!(
+        err.to_string().contains("event_resync_interval_seconds"),
+        "{}",
+        err
+    );
+
+    // And: It does not matter while events are off
+    load_toml(
+        "zero-resync-interval-off",
+        &toml.replace("subscribe_events = true", "subscribe_events = false"),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_zero_connection_timeouts_are_rejected() {
+    // Given: Connect, call and keepalive timeouts of zero seconds
+    let toml = r#"
+        [truenas.timeouts]
+        connect_seconds = 0
+        call_seconds = 0
+        keepalive_timeout_seconds = 0
+
+        [server]
+        [metrics]
+    "#;
+
+    // When: The configuration is loaded
+    let err = load_toml("zero-timeouts", toml).unwrap_err();
+
+    // Then: All three timeouts are named
+    let message = err.to_string();
+    assert!(message.contains("timeouts.connect_seconds"), "{}", message);
+    assert!(message.contains("timeouts.call_seconds"), "{}", message);
+    assert!(
+        message.contains("timeouts.keepalive_timeout_seconds"),
+        "{}",
+        message
+    );
+
+    // And: A probe module's timeouts are checked too
+    let toml = r#"
+        [server]
+        [metrics]
+        [probe.modules.lab]
+        targets = ["nas01.example:443"]
+        [probe.modules.lab.timeouts]
+        call_seconds = 0
+    "#;
+    let err = load_toml("probe-zero-timeout", toml).unwrap_err();
+    assert!(err.to_string().contains("probe.modules.lab"), "{}", err);
+}
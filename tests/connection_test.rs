@@ -0,0 +1,347 @@
+//! Connection multiplexing tests
+//!
+//! Runs the real `ConnectionManager` against the fake TrueNAS middleware, set up to
+//! answer requests out of order, interleave unsolicited frames, and push a collection
+//! event whenever a subscription is made, over either the legacy DDP or the JSON-RPC 2.0
+//! protocol.
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use truenas_exporter::config::{ProtocolMode, TimeoutConfig, TrueNasConfig};
+use truenas_exporter::error::ExporterError;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::types::CollectionUpdateKind;
+use truenas_exporter::truenas::{ConnectionManager, SubscriptionEvent};
+
+/// Methods the server echoes back by name
+const ECHOED: [&str; 9] = [
+    "test.warmup",
+    "test.slow",
+    "test.fast",
+    "test.one",
+    "test.two",
+    "test.three",
+    "test.null_params",
+    "test.connect",
+    "test.after",
+];
+
+/// Start a server
+///
+/// `test.slow` is answered after 500ms, `test.hang` effectively never, `test.freeze`
+/// stops the server from reading the socket at all, and `test.fail` fails; every
+/// method in `ECHOED` returns its own name. Each reply is preceded by an unsolicited
+/// `added` frame that carries no request ID, and subscribing to `pool.query` pushes
+/// an `added` event for pool `tank`.
+async fn start_server() -> FakeTrueNas {
+    let nas = FakeTrueNas::start().await;
+    for method in ECHOED {
+        nas.respond(method, json!(method));
+    }
+    nas.delay("test.slow", Duration::from_millis(500));
+    nas.respond("test.hang", json!(null));
+    nas.delay("test.hang", Duration::from_secs(3600));
+    nas.stall_on("test.freeze");
+    nas.respond_error("test.fail", "ENOENT", "Dataset does not exist");
+    nas.interleave_frames(true);
+    nas.event_on_subscribe(json!({
+        "msg": "added",
+        "collection": "pool.query",
+        "id": 1,
+        "fields": {"id": 1, "name": "tank", "status": "ONLINE", "healthy": true}
+    }));
+    nas
+}
+
+fn test_config(nas: &FakeTrueNas, protocol: ProtocolMode) -> Arc<TrueNasConfig> {
+    Arc::new(TrueNasConfig {
+        protocol,
+        ..nas.config()
+    })
+}
+
+#[tokio::test]
+async fn test_concurrent_queries_are_multiplexed() {
+    // Given: An authenticated connection to a server that answers slow calls late
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(test_config(&nas, ProtocolMode::Legacy));
+    let warmup: String = manager.execute_query("test.warmup", None).await.unwrap();
+    assert_eq!(warmup, "test.warmup");
+
+    // When: A slow and a fast query are issued at the same time
+    let started = Instant::now();
+    let (slow, fast) = tokio::join!(manager.execute_query::<String>("test.slow", None), async {
+        let result = manager.execute_query::<String>("test.fast", None).await;
+        (result, started.elapsed())
+    },);
+
+    // Then: Each caller gets its own response, and the fast one is not held up
+    assert_eq!(slow.unwrap(), "test.slow");
+    let (fast, fast_elapsed) = fast;
+    assert_eq!(fast.unwrap(), "test.fast");
+    assert!(
+        fast_elapsed < Duration::from_millis(400),
+        "fast query waited for the slow one ({:?})",
+        fast_elapsed
+    );
+}
+
+#[tokio::test]
+async fn test_unsolicited_frames_are_not_treated_as_replies() {
+    // Given: A server that sends an unsolicited frame before every reply
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(test_config(&nas, ProtocolMode::Legacy));
+
+    // When: Several queries are executed in sequence
+    for method in ["test.one", "test.two", "test.three"] {
+        let result: String = manager.execute_query(method, None).await.unwrap();
+
+        // Then: Every query receives its own result
+        assert_eq!(result, method);
+    }
+}
+
+#[tokio::test]
+async fn test_auto_mode_prefers_jsonrpc2() {
+    // Given: A server offering the versioned JSON-RPC 2.0 endpoint
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(test_config(&nas, ProtocolMode::Auto));
+
+    // When: A query is executed in auto mode
+    let result: String = manager.execute_query("test.one", None).await.unwrap();
+
+    // Then: The JSON-RPC 2.0 endpoint and envelope are used
+    assert_eq!(result, "test.one");
+    assert_eq!(nas.endpoints(), ["/api/current"]);
+}
+
+#[tokio::test]
+async fn test_auto_mode_falls_back_to_legacy() {
+    // Given: A server that rejects /api/current with HTTP 404
+    let nas = start_server().await;
+    nas.legacy_only(true);
+    let manager = ConnectionManager::new(test_config(&nas, ProtocolMode::Auto));
+
+    // When: A query is executed in auto mode
+    let started = Instant::now();
+    let result: String = manager.execute_query("test.one", None).await.unwrap();
+
+    // Then: The legacy DDP endpoint and envelope are used
+    assert_eq!(result, "test.one");
+    assert_eq!(nas.endpoints(), ["/api/current", "/websocket"]);
+
+    // And: The DDP handshake finishes as soon as the server has answered
+    assert!(
+        started.elapsed() < Duration::from_secs(1),
+        "{:?}",
+        started.elapsed()
+    );
+}
+
+#[tokio::test]
+async fn test_auto_mode_moves_to_jsonrpc2_after_upgrade() {
+    // Given: Auto mode that fell back to the legacy endpoint of a 24.10 server
+    let nas = start_server().await;
+    nas.legacy_only(true);
+    nas.respond("system.version", json!("TrueNAS-SCALE-24.10.2"));
+    let manager = ConnectionManager::new(test_config(&nas, ProtocolMode::Auto));
+    let _: String = manager.execute_query("test.one", None).await.unwrap();
+    assert_eq!(nas.endpoints(), ["/api/current", "/websocket"]);
+
+    // When: The server is upgraded to 25.04 and comes back twice
+    nas.legacy_only(false);
+    nas.respond("system.version", json!("TrueNAS-25.04.0"));
+    for _ in 0..2 {
+        nas.disconnect_all();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let _: String = manager.execute_query("test.one", None).await.unwrap();
+    }
+
+    // Then: The reconnect that found 25.04 still spoke legacy, the next one JSON-RPC 2.0
+    assert_eq!(
+        nas.endpoints(),
+        ["/api/current", "/websocket", "/websocket", "/api/current"]
+    );
+}
+
+#[tokio::test]
+async fn test_jsonrpc2_mode_normalizes_null_params() {
+    // Given: A connection forced to JSON-RPC 2.0
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(test_config(&nas, ProtocolMode::JsonRpc2));
+
+    // When: A query is sent with null params (as pool.query does)
+    let result: String = manager
+        .execute_query("test.null_params", Some(Value::Null))
+        .await
+        .unwrap();
+
+    // Then: The server accepts the request
+    assert_eq!(result, "test.null_params");
+}
+
+#[tokio::test]
+async fn test_errors_are_parsed_for_both_protocols() {
+    for mode in [ProtocolMode::Legacy, ProtocolMode::JsonRpc2] {
+        // Given: A server that fails a method call
+        let nas = start_server().await;
+        let manager = ConnectionManager::new(test_config(&nas, mode));
+
+        // When: The failing method is called
+        let err = manager
+            .execute_query::<Value>("test.fail", None)
+            .await
+            .unwrap_err();
+
+        // Then: The middleware reason is surfaced regardless of envelope
+        assert!(
+            err.to_string().contains("Dataset does not exist"),
+            "{:?}: unexpected error {}",
+            mode,
+            err
+        );
+    }
+}
+
+/// Wait for the next event on `collection`, skipping unrelated notifications
+async fn next_update(
+    events: &mut broadcast::Receiver<SubscriptionEvent>,
+    collection: &str,
+) -> truenas_exporter::truenas::types::CollectionUpdate {
+    loop {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for event")
+            .unwrap();
+        if let SubscriptionEvent::Update(update) = event {
+            if update.collection == collection {
+                return update;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_subscriptions_deliver_events_for_both_protocols() {
+    for mode in [ProtocolMode::Legacy, ProtocolMode::JsonRpc2] {
+        // Given: A manager with a registered subscription and an event listener
+        let nas = start_server().await;
+        let manager = ConnectionManager::new(test_config(&nas, mode));
+        let mut events = manager.events();
+        manager.subscribe("pool.query").await.unwrap();
+
+        // When: A connection is established
+        let _: String = manager.execute_query("test.connect", None).await.unwrap();
+
+        // Then: The subscription is made and the pushed change is delivered
+        let update = next_update(&mut events, "pool.query").await;
+        assert_eq!(update.msg, CollectionUpdateKind::Added, "{:?}", mode);
+        assert_eq!(update.id, Some(json!(1)), "{:?}", mode);
+        assert_eq!(update.fields.unwrap()["name"], "tank", "{:?}", mode);
+    }
+}
+
+#[tokio::test]
+async fn test_subscribe_on_live_connection_is_immediate() {
+    // Given: An already established JSON-RPC 2.0 connection
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(test_config(&nas, ProtocolMode::JsonRpc2));
+    let _: String = manager.execute_query("test.connect", None).await.unwrap();
+    let mut events = manager.events();
+
+    // When: A collection is subscribed afterwards
+    manager.subscribe("pool.query").await.unwrap();
+
+    // Then: Its events arrive without reconnecting
+    let update = next_update(&mut events, "pool.query").await;
+    assert_eq!(update.collection, "pool.query");
+}
+
+fn timeout_config(nas: &FakeTrueNas, timeouts: TimeoutConfig) -> Arc<TrueNasConfig> {
+    Arc::new(TrueNasConfig {
+        timeouts,
+        ..nas.config()
+    })
+}
+
+#[tokio::test]
+async fn test_unanswered_call_times_out_without_dropping_connection() {
+    // Given: A one second call timeout
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(timeout_config(
+        &nas,
+        TimeoutConfig {
+            call_seconds: 1,
+            ..Default::default()
+        },
+    ));
+
+    // When: A call is never answered
+    let err = manager
+        .execute_query::<String>("test.hang", None)
+        .await
+        .unwrap_err();
+
+    // Then: It fails with a timeout and the connection keeps serving other calls
+    assert!(matches!(err, ExporterError::Timeout(_)), "{:?}", err);
+    assert!(err.to_string().contains("test.hang"), "{}", err);
+    let result: String = manager.execute_query("test.after", None).await.unwrap();
+    assert_eq!(result, "test.after");
+    assert_eq!(manager.pending_calls().await, 0);
+}
+
+#[tokio::test]
+async fn test_abandoned_call_leaves_no_pending_entry() {
+    // Given: A live connection
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(test_config(&nas, ProtocolMode::Auto));
+    let _: String = manager.execute_query("test.warmup", None).await.unwrap();
+
+    // When: The caller gives up on a call before its own timeout, as a collector
+    // timeout does
+    let abandoned = tokio::time::timeout(
+        Duration::from_millis(100),
+        manager.execute_query::<String>("test.hang", None),
+    )
+    .await;
+
+    // Then: The call is no longer waited for
+    assert!(abandoned.is_err());
+    assert_eq!(manager.pending_calls().await, 0);
+}
+
+#[tokio::test]
+async fn test_keepalive_drops_unresponsive_connection() {
+    // Given: Aggressive keepalive and a long call timeout
+    let nas = start_server().await;
+    let manager = ConnectionManager::new(timeout_config(
+        &nas,
+        TimeoutConfig {
+            call_seconds: 30,
+            keepalive_interval_seconds: 1,
+            keepalive_timeout_seconds: 1,
+            ..Default::default()
+        },
+    ));
+
+    // When: The server stops reading mid-call
+    let started = Instant::now();
+    let err = manager
+        .execute_query::<String>("test.freeze", None)
+        .await
+        .unwrap_err();
+
+    // Then: Keepalive tears the connection down well before the call timeout...
+    assert!(
+        started.elapsed() < Duration::from_secs(10),
+        "stuck connection detected only after {:?}",
+        started.elapsed()
+    );
+    assert!(err.to_string().contains("Connection closed"), "{}", err);
+
+    // ...and the next query reconnects
+    let result: String = manager.execute_query("test.after", None).await.unwrap();
+    assert_eq!(result, "test.after");
+}
@@ -93,6 +93,7 @@ fn test_error_messages_are_distinguishable() {
     let api_err = format!("{}", ExporterError::TrueNasApi("test".to_string()));
     let config_err = format!("{}", ExporterError::Config("test".to_string()));
     let server_err = format!("{}", ExporterError::Server("test".to_string()));
+    let tls_err = format!("{}", ExporterError::Tls("test".to_string()));
 
     // When: Comparing error messages
     // Then: Each should have a unique prefix
@@ -100,11 +101,13 @@ fn test_error_messages_are_distinguishable() {
     assert!(api_err.starts_with("TrueNAS API error"));
     assert!(config_err.starts_with("Configuration error"));
     assert!(server_err.starts_with("HTTP server error"));
+    assert!(tls_err.starts_with("TLS error"));
 
     // All should be different
     assert_ne!(auth_err, api_err);
     assert_ne!(api_err, config_err);
     assert_ne!(config_err, server_err);
+    assert_ne!(config_err, tls_err);
 }
 
 #[test]
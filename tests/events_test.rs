@@ -0,0 +1,185 @@
+//! Event cache tests
+//!
+//! Verifies that collection change notifications are folded into the cached state and
+//! that the derived metrics follow, including removal of objects that disappear, and
+//! that full resyncs are published to scrapes.
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use truenas_exporter::collectors::events::{self, event_collections, refresh_metrics, EventCache};
+use truenas_exporter::config::MetricsConfig;
+use truenas_exporter::metrics::MetricsCollector;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::types::CollectionUpdate;
+use truenas_exporter::truenas::TrueNasClient;
+
+fn update(value: Value) -> CollectionUpdate {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn test_alert_events_update_counts() {
+    // Given: An alert cache seeded with one active warning
+    let metrics = MetricsCollector::new().unwrap();
+    let mut cache = EventCache::new("uuid");
+    cache.seed(vec![
+        json!({"uuid": "a1", "level": "WARNING", "dismissed": false, "formatted": "Disk hot"}),
+    ]);
+
+    // When: A critical alert is added and the warning is dismissed
+    cache.apply(&update(json!({
+        "msg": "added",
+        "collection": "alert.list",
+        "id": "a2",
+        "fields": {"uuid": "a2", "level": "CRITICAL", "dismissed": false, "formatted": "Pool degraded"}
+    })));
+    cache.apply(&update(json!({
+        "msg": "changed",
+        "collection": "alert.list",
+        "id": "a1",
+        "fields": {"dismissed": true}
+    })));
+    refresh_metrics("alert.list", &cache, &metrics);
+
+    // Then: Counts reflect both changes, and the merged alert keeps its other fields
+    let count = |level: &str, active: &str| {
+        metrics
+            .alert_count
+            .with_label_values(&[level, active])
+            .get()
+    };
+    assert_eq!(count("CRITICAL", "true"), 1.0);
+    assert_eq!(count("WARNING", "true"), 0.0);
+    assert_eq!(count("WARNING", "false"), 1.0);
+    assert!(metrics.render().unwrap().contains("Disk hot"));
+}
+
+#[test]
+fn test_removed_objects_disappear_from_metrics() {
+    // Given: A service cache with two services
+    let metrics = MetricsCollector::new().unwrap();
+    let mut cache = EventCache::new("id");
+    cache.seed(vec![
+        json!({"id": 1, "service": "nfs", "state": "RUNNING", "enable": true}),
+        json!({"id": 2, "service": "smb", "state": "STOPPED", "enable": false}),
+    ]);
+    refresh_metrics("service.query", &cache, &metrics);
+    assert!(metrics.render().unwrap().contains("service=\"smb\""));
+
+    // When: One service is removed
+    cache.apply(&update(
+        json!({"msg": "removed", "collection": "service.query", "id": 2}),
+    ));
+    refresh_metrics("service.query", &cache, &metrics);
+
+    // Then: Its series is gone while the other remains
+    let output = metrics.render().unwrap();
+    assert_eq!(cache.len(), 1);
+    assert!(!output.contains("service=\"smb\""));
+    assert!(output.contains("service=\"nfs\""));
+}
+
+#[test]
+fn test_unparseable_objects_are_skipped() {
+    // Given: A pool cache where one object is missing required fields
+    let metrics = MetricsCollector::new().unwrap();
+    let mut cache = EventCache::new("id");
+    cache.seed(vec![
+        json!({"id": 1, "name": "tank", "status": "ONLINE", "healthy": true}),
+        json!({"id": 2, "name": "broken"}),
+    ]);
+
+    // When: Metrics are rebuilt
+    refresh_metrics("pool.query", &cache, &metrics);
+
+    // Then: The valid pool is exported and the invalid one is ignored
+    let output = metrics.render().unwrap();
+    assert!(output.contains("pool=\"tank\""));
+    assert!(!output.contains("pool=\"broken\""));
+}
+
+#[test]
+fn test_pool_events_follow_pool_collection_setting() {
+    // Given: Pool metrics disabled
+    let config = MetricsConfig {
+        collect_pool_metrics: false,
+        ..Default::default()
+    };
+
+    // When: Event collections are selected
+    let collections = event_collections(&config);
+
+    // Then: pool.query is not subscribed, the others are
+    assert!(!collections.contains(&"pool.query"));
+    assert!(collections.contains(&"alert.list"));
+    assert!(collections.contains(&"service.query"));
+    assert!(collections.contains(&"app.query"));
+}
+
+#[tokio::test]
+async fn test_resync_is_published() {
+    // Given: Metrics that have already published an empty cycle, and a NAS with a pool
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{"id": 1, "name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    let metrics = MetricsCollector::new().unwrap();
+    metrics.publish(Duration::ZERO);
+
+    // When: The event task seeds its cache with a full query
+    let events = tokio::spawn(events::run(
+        Arc::new(TrueNasClient::new(nas.config())),
+        metrics.clone(),
+        vec!["pool.query"],
+        Duration::from_secs(3600),
+        Duration::ZERO,
+    ));
+
+    // Then: Scrapes see the resynced pool without waiting for an event
+    let mut rendered = String::new();
+    for _ in 0..100 {
+        rendered = metrics.render().unwrap();
+        if rendered.contains("truenas_pool_health{") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(
+        rendered.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 1"),
+        "{}",
+        rendered
+    );
+    events.abort();
+}
+
+#[tokio::test]
+async fn test_unsupported_collections_are_not_subscribed() {
+    // Given: A 24.04 release, which has no apps API
+    let nas = FakeTrueNas::start().await;
+    nas.respond("system.version", json!("TrueNAS-SCALE-24.04.2"));
+    nas.respond("pool.query", json!([]));
+    let metrics = MetricsCollector::new().unwrap();
+
+    // When: The event task runs for pools and apps
+    let events = tokio::spawn(events::run(
+        Arc::new(TrueNasClient::new(nas.config())),
+        metrics,
+        vec!["pool.query", "app.query"],
+        Duration::from_secs(3600),
+        Duration::ZERO,
+    ));
+    for _ in 0..100 {
+        if nas.call_count("pool.query") > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Then: Pools are subscribed and resynced, apps are left alone
+    assert_eq!(nas.call_count("core.subscribe"), 1, "{:?}", nas.calls());
+    assert!(nas.call_count("pool.query") > 0, "{:?}", nas.calls());
+    assert_eq!(nas.call_count("app.query"), 0, "{:?}", nas.calls());
+    events.abort();
+}
@@ -0,0 +1,348 @@
+//! Exposition format tests
+//!
+//! Content negotiation, compression and the OpenMetrics encoding of the exporter's
+//! metrics.
+
+mod common;
+
+use common::{http_request, send_request, start_exporter};
+use serde_json::json;
+use std::io::Read;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use truenas_exporter::config::{CollectionMode, MetricsConfig};
+use truenas_exporter::exposition::{
+    Compression, Format, OPENMETRICS_CONTENT_TYPE, TEXT_CONTENT_TYPE,
+};
+use truenas_exporter::metrics::MetricsCollector;
+use truenas_exporter::test_support::FakeTrueNas;
+
+#[test]
+fn test_format_negotiation() {
+    // Given: Accept headers as sent by various scrapers
+    let cases = [
+        (None, Format::Text),
+        (Some("*/*"), Format::Text),
+        (Some("text/plain;version=0.0.4"), Format::Text),
+        (Some("application/openmetrics-text"), Format::OpenMetrics),
+        // Prometheus' default scrape protocols
+        (
+            Some("application/openmetrics-text;version=1.0.0;q=0.5,application/openmetrics-text;version=0.0.1;q=0.4,text/plain;version=0.0.4;q=0.3,*/*;q=0.2"),
+            Format::OpenMetrics,
+        ),
+        (
+            Some("text/plain;version=0.0.4;q=0.9,application/openmetrics-text;q=0.5"),
+            Format::Text,
+        ),
+        (Some("application/openmetrics-text;q=0"), Format::Text),
+    ];
+
+    for (accept, expected) in cases {
+        // When: The format is negotiated
+        let format = Format::negotiate(accept);
+
+        // Then: OpenMetrics is only picked when preferred
+        assert_eq!(format, expected, "Accept: {:?}", accept);
+    }
+    assert_eq!(Format::Text.content_type(), TEXT_CONTENT_TYPE);
+    assert_eq!(Format::OpenMetrics.content_type(), OPENMETRICS_CONTENT_TYPE);
+}
+
+#[test]
+fn test_openmetrics_types_and_units() {
+    // Given: Metrics with info, stateset, counter and unit-suffixed series
+    let metrics = MetricsCollector::new().unwrap();
+    metrics
+        .disk_info
+        .with_label_values(&["sda", "S1", "WD \"Red\"", "4000"])
+        .set(1);
+    metrics
+        .pool_health
+        .with_label_values(&["tank", "DEGRADED"])
+        .set(0.0);
+    metrics
+        .cloud_sync_status
+        .with_label_values(&["offsite", "RUNNING"])
+        .set(1.0);
+    metrics
+        .pool_capacity_bytes
+        .with_label_values(&["tank"])
+        .set(1000.0);
+    metrics.exporter_reconnects_total.inc_by(3);
+
+    // When: They are rendered as OpenMetrics
+    let output = metrics.render_as(Format::OpenMetrics).unwrap();
+
+    // Then: Info metrics are typed info, keeping their labels
+    assert!(output.contains("# TYPE truenas_disk info\n"), "{}", output);
+    assert!(
+        output.contains(
+            "truenas_disk_info{disk=\"sda\",model=\"WD \\\"Red\\\"\",serial=\"S1\",size=\"4000\"} 1\n"
+        ),
+        "{}",
+        output
+    );
+
+    // And: Pool status lists every state with the current one set
+    assert!(
+        output.contains("# TYPE truenas_pool_status stateset\n"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("truenas_pool_status{pool=\"tank\",truenas_pool_status=\"DEGRADED\"} 1\n"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("truenas_pool_status{pool=\"tank\",truenas_pool_status=\"ONLINE\"} 0\n"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains(
+            "truenas_cloud_sync_state{description=\"offsite\",truenas_cloud_sync_state=\"RUNNING\"} 1\n"
+        ),
+        "{}",
+        output
+    );
+
+    // And: The gauges they come from keep their labels and values
+    assert!(
+        output.contains("# TYPE truenas_pool_health gauge\n"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("truenas_pool_health{pool=\"tank\",status=\"DEGRADED\"} 0\n"),
+        "{}",
+        output
+    );
+
+    // And: Counters drop the _total suffix from their name and units are declared
+    assert!(
+        output.contains("# TYPE truenas_exporter_reconnects counter\n"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("truenas_exporter_reconnects_total 3\n"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("# UNIT truenas_pool_capacity_bytes bytes\n"),
+        "{}",
+        output
+    );
+    assert!(output.ends_with("# EOF\n"), "{}", output);
+
+    // And: The classic format is unchanged
+    let text = metrics.render().unwrap();
+    assert!(text.contains("# TYPE truenas_disk_info gauge"), "{}", text);
+    assert!(!text.contains("# EOF"), "{}", text);
+}
+
+#[test]
+fn test_openmetrics_unhealthy_online_pool() {
+    // Given: A pool that TrueNAS reports as ONLINE but not healthy
+    let metrics = MetricsCollector::new().unwrap();
+    metrics
+        .pool_health
+        .with_label_values(&["tank", "ONLINE"])
+        .set(0.0);
+
+    // When: It is rendered as OpenMetrics
+    let output = metrics.render_as(Format::OpenMetrics).unwrap();
+
+    // Then: The health gauge still reports it unhealthy, with its status label
+    assert!(
+        output.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 0\n"),
+        "{}",
+        output
+    );
+    assert!(!output.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 1"));
+
+    // And: The stateset only tells which status it is in
+    assert!(
+        output.contains("truenas_pool_status{pool=\"tank\",truenas_pool_status=\"ONLINE\"} 1\n"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn test_compression_negotiation() {
+    // Given: Accept-Encoding headers as sent by various scrapers
+    let cases = [
+        (None, Compression::Identity),
+        (Some("identity"), Compression::Identity),
+        (Some("gzip"), Compression::Gzip),
+        (Some("br, gzip;q=0.8"), Compression::Gzip),
+        (Some("gzip;q=0"), Compression::Identity),
+        (Some("deflate"), Compression::Identity),
+    ];
+
+    for (accept_encoding, expected) in cases {
+        // When: The coding is negotiated
+        let compression = Compression::negotiate(accept_encoding);
+
+        // Then: gzip is used whenever it is accepted
+        assert_eq!(
+            compression, expected,
+            "Accept-Encoding: {:?}",
+            accept_encoding
+        );
+    }
+
+    // And: zstd is preferred when compiled in
+    #[cfg(feature = "zstd")]
+    assert_eq!(
+        Compression::negotiate(Some("gzip, zstd")),
+        Compression::Zstd
+    );
+}
+
+#[test]
+fn test_gzip_round_trip() {
+    // Given: A rendered exposition
+    let metrics = MetricsCollector::new().unwrap();
+    let rendered = metrics.render().unwrap();
+
+    // When: It is gzip compressed and decompressed
+    let compressed = Compression::Gzip
+        .compress(rendered.clone().into_bytes())
+        .unwrap();
+    let mut decompressed = String::new();
+    flate2::read::GzDecoder::new(compressed.as_slice())
+        .read_to_string(&mut decompressed)
+        .unwrap();
+
+    // Then: The output is smaller and unchanged by the round trip
+    assert!(compressed.len() < rendered.len());
+    assert_eq!(decompressed, rendered);
+    assert_eq!(Compression::Gzip.content_encoding(), Some("gzip"));
+}
+
+#[test]
+fn test_rendered_output_follows_published_snapshot() {
+    // Given: A published pool capacity, rendered once in each format
+    let metrics = MetricsCollector::new().unwrap();
+    let capacity = metrics.pool_capacity_bytes.with_label_values(&["tank"]);
+    capacity.set(1000.0);
+    metrics.publish(Duration::ZERO);
+    assert!(metrics
+        .render()
+        .unwrap()
+        .contains("truenas_pool_capacity_bytes{pool=\"tank\"} 1000"));
+    metrics.render_as(Format::OpenMetrics).unwrap();
+
+    // When: The capacity changes and the self-metrics move on
+    capacity.set(2000.0);
+    metrics.up.set(1.0);
+    let before_publish = metrics.render().unwrap();
+    metrics.publish(Duration::ZERO);
+    let text = metrics.render().unwrap();
+    let openmetrics = metrics.render_as(Format::OpenMetrics).unwrap();
+
+    // Then: Self-metrics are always current, TrueNAS series once published
+    assert!(before_publish.contains("truenas_pool_capacity_bytes{pool=\"tank\"} 1000"));
+    assert!(
+        before_publish.contains("truenas_up 1"),
+        "{}",
+        before_publish
+    );
+    assert!(
+        text.contains("truenas_pool_capacity_bytes{pool=\"tank\"} 2000"),
+        "{}",
+        text
+    );
+    assert!(
+        openmetrics.contains("truenas_pool_capacity_bytes{pool=\"tank\"} 2000"),
+        "{}",
+        openmetrics
+    );
+    assert_eq!(openmetrics.matches("# EOF").count(), 1, "{}", openmetrics);
+    assert!(openmetrics.ends_with("# EOF\n"), "{}", openmetrics);
+}
+
+#[tokio::test]
+async fn test_openmetrics_is_served_when_accepted() {
+    // Given: The exporter collecting on scrape from a NAS with one pool
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    let metrics = MetricsConfig {
+        collection_mode: CollectionMode::OnScrape,
+        ..Default::default()
+    };
+    let port = start_exporter(nas.config(), metrics).await;
+
+    // When: /metrics is scraped asking for OpenMetrics, and without an Accept header
+    let accept = "Accept: application/openmetrics-text;version=1.0.0,text/plain;q=0.5\r\n";
+    let (head, body) = http_request(port, "GET", "/metrics", accept).await.unwrap();
+    let (text_head, text_body) = http_request(port, "GET", "/metrics", "").await.unwrap();
+
+    // Then: OpenMetrics is served only when asked for
+    let head = head.to_lowercase();
+    assert!(
+        head.contains("content-type: application/openmetrics-text; version=1.0.0"),
+        "{}",
+        head
+    );
+    assert!(
+        body.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 1"),
+        "{}",
+        body
+    );
+    assert!(body.ends_with("# EOF\n"), "{}", body);
+    assert!(
+        text_head
+            .to_lowercase()
+            .contains("content-type: text/plain; version=0.0.4"),
+        "{}",
+        text_head
+    );
+    assert!(!text_body.contains("# EOF"), "{}", text_body);
+}
+
+#[tokio::test]
+async fn test_metrics_are_gzipped_when_accepted() {
+    // Given: The exporter collecting on scrape from a NAS with one pool
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    let metrics = MetricsConfig {
+        collection_mode: CollectionMode::OnScrape,
+        ..Default::default()
+    };
+    let port = start_exporter(nas.config(), metrics).await;
+
+    // When: /metrics is scraped the way Prometheus does, accepting gzip
+    let (head, gzipped) = send_request(
+        TcpStream::connect(("127.0.0.1", port)).await.unwrap(),
+        "GET",
+        "/metrics",
+        "Accept-Encoding: gzip\r\n",
+    )
+    .await
+    .unwrap();
+    let head = head.to_lowercase();
+    let mut body = String::new();
+    flate2::read::GzDecoder::new(&gzipped[..])
+        .read_to_string(&mut body)
+        .unwrap();
+
+    // Then: The metrics arrive gzip compressed
+    assert!(head.contains("content-encoding: gzip"), "{}", head);
+    assert!(head.contains("vary: accept, accept-encoding"), "{}", head);
+    assert!(
+        body.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 1"),
+        "{}",
+        body
+    );
+}
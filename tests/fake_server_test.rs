@@ -0,0 +1,206 @@
+//! End-to-end tests against the fake TrueNAS middleware
+//!
+//! Exercises the real connection, authentication and query flow, and the full exporter
+//! behind `/metrics`, using `truenas_exporter::test_support::FakeTrueNas`.
+
+mod common;
+
+use common::{http_get, start_exporter};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use truenas_exporter::config::{MetricsConfig, ProtocolMode, TimeoutConfig, TrueNasConfig};
+use truenas_exporter::error::ExporterError;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::ConnectionManager;
+
+fn manager(config: TrueNasConfig) -> ConnectionManager {
+    ConnectionManager::new(Arc::new(config))
+}
+
+#[tokio::test]
+async fn test_scripted_query_after_login() {
+    // Given: A fake server with a scripted pool list
+    let nas = FakeTrueNas::start().await;
+    nas.respond("pool.query", json!([{"name": "tank"}]));
+    let manager = manager(nas.config());
+
+    // When: The pools are queried
+    let pools: Value = manager.execute_query("pool.query", None).await.unwrap();
+
+    // Then: The scripted reply arrives after login and version detection
+    assert_eq!(pools, json!([{"name": "tank"}]));
+    assert_eq!(
+        nas.calls(),
+        ["auth.login_with_api_key", "system.version", "pool.query"]
+    );
+    assert_eq!(manager.server_version().unwrap().raw, "TrueNAS-25.04.0");
+}
+
+#[tokio::test]
+async fn test_legacy_endpoint_is_supported() {
+    // Given: A client restricted to the legacy DDP endpoint
+    let nas = FakeTrueNas::start().await;
+    nas.respond("system.info", json!({"hostname": "nas01"}));
+    let manager = manager(TrueNasConfig {
+        protocol: ProtocolMode::Legacy,
+        ..nas.config()
+    });
+
+    // When: A query is made
+    let info: Value = manager.execute_query("system.info", None).await.unwrap();
+
+    // Then: The handshake and the call succeed
+    assert_eq!(info["hostname"], "nas01");
+}
+
+#[tokio::test]
+async fn test_rejected_login_is_auth_error() {
+    // Given: A server that rejects every login
+    let nas = FakeTrueNas::start().await;
+    nas.reject_auth(true);
+
+    // When: A query is made
+    let err = manager(nas.config())
+        .execute_query::<Value>("pool.query", None)
+        .await
+        .unwrap_err();
+
+    // Then: Authentication fails and the method is never called
+    assert!(matches!(err, ExporterError::Auth(_)), "{:?}", err);
+    assert_eq!(nas.call_count("pool.query"), 0);
+}
+
+#[tokio::test]
+async fn test_expired_session_reauthenticates() {
+    // Given: An authenticated connection
+    let nas = FakeTrueNas::start().await;
+    nas.respond("pool.query", json!([]));
+    let manager = manager(nas.config());
+    let _: Value = manager.execute_query("pool.query", None).await.unwrap();
+
+    // When: The session expires on the server
+    nas.expire_sessions();
+    let err = manager
+        .execute_query::<Value>("pool.query", None)
+        .await
+        .unwrap_err();
+
+    // Then: The call fails with ENOTAUTHENTICATED and the next one logs in again
+    assert!(err.to_string().contains("Not authenticated"), "{}", err);
+    let _: Value = manager.execute_query("pool.query", None).await.unwrap();
+    assert_eq!(nas.connections(), 2);
+    assert_eq!(nas.call_count("auth.login_with_api_key"), 2);
+}
+
+#[tokio::test]
+async fn test_disconnects_are_recovered() {
+    // Given: A server that drops the socket on one method
+    let nas = FakeTrueNas::start().await;
+    nas.disconnect_on("disk.query");
+    nas.respond("pool.query", json!([]));
+    let manager = manager(nas.config());
+
+    // When: That method is called
+    let err = manager
+        .execute_query::<Value>("disk.query", None)
+        .await
+        .unwrap_err();
+
+    // Then: The call reports the lost connection and the next call reconnects
+    assert!(
+        matches!(err, ExporterError::ConnectionClosed { code: 1006, .. }),
+        "{:?}",
+        err
+    );
+    let _: Value = manager.execute_query("pool.query", None).await.unwrap();
+
+    // And: Dropping every connection is recovered from as well
+    nas.disconnect_all();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let _: Value = manager.execute_query("pool.query", None).await.unwrap();
+    assert_eq!(nas.connections(), 3);
+}
+
+#[tokio::test]
+async fn test_delayed_reply_times_out() {
+    // Given: A reply slower than the call timeout
+    let nas = FakeTrueNas::start().await;
+    nas.respond("pool.query", json!([]));
+    nas.delay("pool.query", Duration::from_secs(3));
+    let manager = manager(TrueNasConfig {
+        timeouts: TimeoutConfig {
+            call_seconds: 1,
+            ..Default::default()
+        },
+        ..nas.config()
+    });
+
+    // When: The call is made
+    let err = manager
+        .execute_query::<Value>("pool.query", None)
+        .await
+        .unwrap_err();
+
+    // Then: It times out
+    assert!(matches!(err, ExporterError::Timeout(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn test_exporter_serves_metrics_end_to_end() {
+    // Given: A fake NAS with a healthy pool and the exporter pointed at it
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{
+            "name": "tank",
+            "status": "ONLINE",
+            "healthy": true,
+            "size": 1000,
+            "allocated": 400,
+            "free": 600
+        }]),
+    );
+    nas.respond(
+        "system.info",
+        json!({"version": "TrueNAS-25.04.0", "hostname": "nas01", "uptime_seconds": 3600.0}),
+    );
+    let port = start_exporter(nas.config(), MetricsConfig::default()).await;
+
+    // When: /metrics is scraped once the first cycle has run
+    let mut body = String::new();
+    for _ in 0..100 {
+        if let Some(response) = http_get(port, "/metrics").await {
+            body = response;
+            if body.contains("truenas_up 1") {
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Then: Metrics from the fake NAS are served
+    assert!(body.contains("truenas_up 1"), "{}", body);
+    assert!(
+        body.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 1"),
+        "{}",
+        body
+    );
+    assert!(
+        body.contains("truenas_version=\"TrueNAS-25.04.0\""),
+        "{}",
+        body
+    );
+
+    // And: Each collector reports its own outcome; unscripted methods fail
+    assert!(
+        body.contains("truenas_exporter_collector_success{collector=\"pool\"} 1"),
+        "{}",
+        body
+    );
+    assert!(
+        body.contains("truenas_exporter_collector_success{collector=\"smart\"} 0"),
+        "{}",
+        body
+    );
+}
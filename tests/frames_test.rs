@@ -0,0 +1,102 @@
+//! WebSocket control frame tests
+//!
+//! Runs the `ConnectionManager` against the fake TrueNAS middleware set up to surround its
+//! replies with Ping, Pong and Binary frames, and to close or drop the socket on request.
+
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use truenas_exporter::error::ExporterError;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::ConnectionManager;
+
+/// Start a server
+///
+/// `test.ping` is answered after a Ping, a non-UTF-8 Binary frame and an unsolicited
+/// Pong. `test.close` is answered with a Close frame (1013, "restarting middleware"),
+/// `test.drop` by dropping the socket.
+async fn start_server() -> FakeTrueNas {
+    let nas = FakeTrueNas::start().await;
+    nas.respond("test.ping", json!("test.ping"));
+    nas.respond("test.after", json!("test.after"));
+    nas.interleave_frames(true);
+    nas.close_on("test.close", 1013, "restarting middleware");
+    nas.disconnect_on("test.drop");
+    nas
+}
+
+fn manager(nas: &FakeTrueNas) -> ConnectionManager {
+    ConnectionManager::new(Arc::new(nas.config()))
+}
+
+#[tokio::test]
+async fn test_control_frames_are_skipped_and_pings_answered() {
+    // Given: A server that sends Ping, Binary and Pong frames ahead of the reply
+    let nas = start_server().await;
+    let manager = manager(&nas);
+
+    // When: A call is made
+    let result: String = manager.execute_query("test.ping", None).await.unwrap();
+
+    // Then: The reply still arrives
+    assert_eq!(result, "test.ping");
+
+    // And: The ping was answered with a matching pong
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while nas.pongs() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("server never received a pong");
+}
+
+#[tokio::test]
+async fn test_close_frame_becomes_typed_error_and_reconnects() {
+    // Given: A server that closes the socket with a reason
+    let nas = start_server().await;
+    let manager = manager(&nas);
+
+    // When: The pending call is cut off by the Close frame
+    let err = manager
+        .execute_query::<String>("test.close", None)
+        .await
+        .unwrap_err();
+
+    // Then: The close code and reason are reported
+    match err {
+        ExporterError::ConnectionClosed { code, reason } => {
+            assert_eq!(code, 1013);
+            assert_eq!(reason, "restarting middleware");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    // And: The next query reconnects
+    let result: String = manager.execute_query("test.after", None).await.unwrap();
+    assert_eq!(result, "test.after");
+}
+
+#[tokio::test]
+async fn test_dropped_socket_is_abnormal_closure() {
+    // Given: A server that drops the socket without a Close frame
+    let nas = start_server().await;
+    let manager = manager(&nas);
+
+    // When: The pending call loses its connection
+    let err = manager
+        .execute_query::<String>("test.drop", None)
+        .await
+        .unwrap_err();
+
+    // Then: It is reported as code 1006
+    assert!(
+        matches!(err, ExporterError::ConnectionClosed { code: 1006, .. }),
+        "{:?}",
+        err
+    );
+
+    // And: The next query reconnects
+    let result: String = manager.execute_query("test.after", None).await.unwrap();
+    assert_eq!(result, "test.after");
+}
@@ -1,4 +1,11 @@
-use truenas_exporter::metrics::MetricsCollector;
+use serde_json::json;
+use std::time::Duration;
+use truenas_exporter::collectors::{self, CollectionContext};
+use truenas_exporter::config::MetricsConfig;
+use truenas_exporter::exposition::Format;
+use truenas_exporter::metrics::{HostsRendering, MetricsCollector};
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::TrueNasClient;
 
 #[test]
 fn test_metrics_registration() {
@@ -90,3 +97,169 @@ fn test_metrics_reset() {
     let rendered = metrics.render();
     assert!(rendered.is_ok(), "Failed to render after reset");
 }
+
+#[test]
+fn test_collector_self_metrics() {
+    // Given: A fresh metrics collector
+    let metrics = MetricsCollector::new().expect("Failed to create metrics collector");
+
+    // When: One collector succeeds and another fails
+    metrics.record_collector_run("pool", true, std::time::Duration::from_millis(250));
+    metrics.record_collector_run("smart", false, std::time::Duration::from_secs(2));
+
+    // Then: Success and duration are exported for both
+    let rendered = metrics.render().unwrap();
+    assert!(rendered.contains("truenas_exporter_collector_success{collector=\"pool\"} 1"));
+    assert!(rendered.contains("truenas_exporter_collector_success{collector=\"smart\"} 0"));
+    assert!(
+        rendered.contains("truenas_exporter_collector_duration_seconds{collector=\"pool\"} 0.25")
+    );
+    assert!(rendered.contains("truenas_exporter_collector_duration_seconds{collector=\"smart\"} 2"));
+
+    // And: Only the successful collector has a last-success timestamp
+    let timestamp = metrics
+        .exporter_collector_last_success_timestamp_seconds
+        .with_label_values(&["pool"])
+        .get();
+    assert!(timestamp > 1_600_000_000.0);
+    assert!(!rendered.contains(
+        "truenas_exporter_collector_last_success_timestamp_seconds{collector=\"smart\"}"
+    ));
+}
+
+#[test]
+fn test_scrapes_see_published_snapshot() {
+    // Given: A published pool capacity
+    let metrics = MetricsCollector::new().expect("Failed to create metrics collector");
+    metrics
+        .pool_capacity_bytes
+        .with_label_values(&["tank"])
+        .set(1000.0);
+    metrics.publish(std::time::Duration::ZERO);
+
+    // When: A cycle is half way through updating it
+    metrics
+        .pool_capacity_bytes
+        .with_label_values(&["tank"])
+        .set(2000.0);
+    metrics.up.set(1.0);
+
+    // Then: Scrapes keep seeing the published value, self-metrics are live
+    let rendered = metrics.render().unwrap();
+    assert!(rendered.contains("truenas_pool_capacity_bytes{pool=\"tank\"} 1000"));
+    assert!(rendered.contains("truenas_up 1"));
+
+    // And: The new value appears once the cycle is published
+    metrics.publish(std::time::Duration::ZERO);
+    let rendered = metrics.render().unwrap();
+    assert!(rendered.contains("truenas_pool_capacity_bytes{pool=\"tank\"} 2000"));
+}
+
+#[test]
+fn test_multi_host_rendering_is_reused_until_a_host_publishes() {
+    // Given: Two hosts that have each published a pool
+    let (nas1, nas2) = (
+        MetricsCollector::new().unwrap(),
+        MetricsCollector::new().unwrap(),
+    );
+    for (metrics, pool) in [(&nas1, "tank"), (&nas2, "backup")] {
+        metrics
+            .pool_capacity_bytes
+            .with_label_values(&[pool])
+            .set(1000.0);
+        metrics.publish(Duration::ZERO);
+    }
+    let hosts = [("nas1", &nas1), ("nas2", &nas2)];
+    let rendering = HostsRendering::default();
+
+    // When: Two scrapes in a row render them, with a self-metric changing in between
+    let first = rendering.render(&hosts, Format::Text).unwrap();
+    nas2.up.set(1.0);
+    let second = rendering.render(&hosts, Format::Text).unwrap();
+
+    // Then: The TrueNAS series are encoded once, while self-metrics stay live
+    assert_eq!(rendering.encoded(), 1);
+    assert!(first.contains("truenas_pool_capacity_bytes{host=\"nas2\",pool=\"backup\"} 1000"));
+    assert!(second.contains("truenas_pool_capacity_bytes{host=\"nas2\",pool=\"backup\"} 1000"));
+    assert!(second.contains("truenas_up{host=\"nas2\"} 1"), "{}", second);
+
+    // And: Each format is encoded on its own
+    let openmetrics = rendering.render(&hosts, Format::OpenMetrics).unwrap();
+    assert!(openmetrics.ends_with("# EOF\n"));
+    assert_eq!(rendering.encoded(), 2);
+
+    // And: A publish by either host invalidates the rendering
+    nas1.pool_capacity_bytes
+        .with_label_values(&["tank"])
+        .set(2000.0);
+    nas1.publish(Duration::ZERO);
+    let third = rendering.render(&hosts, Format::Text).unwrap();
+    assert_eq!(rendering.encoded(), 3);
+    assert!(third.contains("truenas_pool_capacity_bytes{host=\"nas1\",pool=\"tank\"} 2000"));
+}
+
+#[test]
+fn test_disappeared_series_kept_for_grace_period() {
+    // Given: Two published datasets
+    let metrics = MetricsCollector::new().expect("Failed to create metrics collector");
+    let grace = std::time::Duration::from_millis(200);
+    for dataset in ["tank/a", "tank/b"] {
+        metrics
+            .dataset_used_bytes
+            .with_label_values(&[dataset, "tank"])
+            .set(1.0);
+    }
+    metrics.publish(grace);
+
+    // When: The next cycle only reports one of them
+    metrics.dataset_used_bytes.reset();
+    metrics
+        .dataset_used_bytes
+        .with_label_values(&["tank/a", "tank"])
+        .set(2.0);
+    metrics.publish(grace);
+
+    // Then: The missing one keeps its last value within the grace period
+    let rendered = metrics.render().unwrap();
+    assert!(rendered.contains("truenas_dataset_used_bytes{dataset=\"tank/a\",pool=\"tank\"} 2"));
+    assert!(rendered.contains("truenas_dataset_used_bytes{dataset=\"tank/b\",pool=\"tank\"} 1"));
+
+    // And: It is dropped once the grace period has passed
+    std::thread::sleep(grace);
+    metrics.publish(grace);
+    let rendered = metrics.render().unwrap();
+    assert!(rendered.contains("dataset=\"tank/a\""));
+    assert!(!rendered.contains("dataset=\"tank/b\""));
+}
+
+#[tokio::test]
+async fn test_deleted_objects_disappear() {
+    // Given: A NAS with two datasets, collected and published
+    let nas = FakeTrueNas::start().await;
+    let dataset = |name: &str| json!({"name": name, "encrypted": false, "used": {"parsed": 1}});
+    nas.respond(
+        "pool.dataset.query",
+        json!([dataset("tank/keep"), dataset("tank/gone")]),
+    );
+    let client = TrueNasClient::new(nas.config());
+    let metrics = MetricsCollector::new().unwrap();
+    let config = MetricsConfig::default();
+    let ctx = CollectionContext {
+        client: &client,
+        metrics: &metrics,
+        config: &config,
+    };
+    collectors::collect_dataset_metrics(&ctx).await.unwrap();
+    metrics.publish(Duration::ZERO);
+    assert!(metrics.render().unwrap().contains("tank/gone"));
+
+    // When: One dataset is deleted and the next cycle is published
+    nas.respond("pool.dataset.query", json!([dataset("tank/keep")]));
+    collectors::collect_dataset_metrics(&ctx).await.unwrap();
+    metrics.publish(Duration::ZERO);
+
+    // Then: Only the remaining dataset is exported
+    let output = metrics.render().unwrap();
+    assert!(output.contains("tank/keep"), "{}", output);
+    assert!(!output.contains("tank/gone"), "{}", output);
+}
@@ -0,0 +1,69 @@
+//! `[[truenas]]` host list tests
+//!
+//! Collects from several fake TrueNAS servers into one `/metrics` response.
+
+mod common;
+
+use common::{http_get, http_request, start_exporter};
+use serde_json::json;
+use truenas_exporter::config::{CollectionMode, MetricsConfig, TrueNasHosts};
+use truenas_exporter::test_support::FakeTrueNas;
+
+#[tokio::test]
+async fn test_host_list_is_labelled_per_host() {
+    // Given: Two NASes configured as a [[truenas]] list, one of which rejects the login
+    let (nas1, nas2) = (FakeTrueNas::start().await, FakeTrueNas::start().await);
+    nas1.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    nas2.reject_auth(true);
+    let port = start_exporter(
+        TrueNasHosts::Multiple(vec![nas1.config(), nas2.config()]),
+        MetricsConfig {
+            collection_mode: CollectionMode::OnScrape,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    // When: /metrics is scraped
+    let body = http_get(port, "/metrics").await.unwrap();
+
+    // Then: Every series names its host, and each host has its own up gauge
+    let (host1, host2) = (nas1.host(), nas2.host());
+    assert!(
+        body.contains(&format!("truenas_up{{host=\"{}\"}} 1", host1)),
+        "{}",
+        body
+    );
+    assert!(
+        body.contains(&format!("truenas_up{{host=\"{}\"}} 0", host2)),
+        "{}",
+        body
+    );
+    assert!(
+        body.contains(&format!(
+            "truenas_pool_health{{host=\"{}\",pool=\"tank\",status=\"ONLINE\"}} 1",
+            host1
+        )),
+        "{}",
+        body
+    );
+
+    // And: Each metric family is declared once
+    assert_eq!(
+        body.matches("# TYPE truenas_up gauge").count(),
+        1,
+        "{}",
+        body
+    );
+    assert!(body
+        .lines()
+        .filter(|line| line.starts_with("truenas_"))
+        .all(|line| line.contains("host=\"")));
+
+    // And: The exporter stays healthy while one NAS is reachable
+    let (head, _) = http_request(port, "GET", "/health", "").await.unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+}
@@ -0,0 +1,85 @@
+//! On-scrape collection tests
+//!
+//! Runs the exporter in `collection_mode = "on_scrape"` against the fake TrueNAS
+//! middleware.
+
+mod common;
+
+use common::{http_get, http_request, start_exporter};
+use serde_json::json;
+use std::time::Duration;
+use truenas_exporter::config::{CollectionMode, MetricsConfig};
+use truenas_exporter::test_support::FakeTrueNas;
+
+#[tokio::test]
+async fn test_on_scrape_collection_is_shared_and_cached() {
+    // Given: An exporter in on-scrape mode and a slow pool query
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    nas.delay("pool.query", Duration::from_millis(300));
+    let port = start_exporter(
+        nas.config(),
+        MetricsConfig {
+            collection_mode: CollectionMode::OnScrape,
+            min_refresh_age_seconds: 60,
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(nas.call_count("pool.query"), 0);
+
+    // When: Three scrapes arrive at once
+    let (a, b, c) = tokio::join!(
+        http_request(port, "GET", "/metrics", ""),
+        http_request(port, "GET", "/metrics", ""),
+        http_request(port, "GET", "/metrics", ""),
+    );
+
+    // Then: They share one collection and all see its result
+    assert_eq!(nas.call_count("pool.query"), 1);
+    for (head, body) in [a.unwrap(), b.unwrap(), c.unwrap()] {
+        assert!(
+            body.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 1"),
+            "{}",
+            body
+        );
+        assert!(head.to_lowercase().contains("\r\nage: 0"), "{}", head);
+        assert!(
+            body.contains("truenas_exporter_cache_age_seconds"),
+            "{}",
+            body
+        );
+    }
+
+    // And: A later scrape within the minimum refresh age is served from cache
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let (head, _) = http_request(port, "GET", "/metrics", "").await.unwrap();
+    assert_eq!(nas.call_count("pool.query"), 1);
+    assert!(head.to_lowercase().contains("\r\nage: 1"), "{}", head);
+}
+
+#[tokio::test]
+async fn test_on_scrape_collects_when_cache_is_stale() {
+    // Given: An exporter in on-scrape mode that never reuses a collection
+    let nas = FakeTrueNas::start().await;
+    nas.respond("pool.query", json!([]));
+    let port = start_exporter(
+        nas.config(),
+        MetricsConfig {
+            collection_mode: CollectionMode::OnScrape,
+            min_refresh_age_seconds: 0,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    // When: It is scraped twice in a row
+    http_get(port, "/metrics").await.unwrap();
+    http_get(port, "/metrics").await.unwrap();
+
+    // Then: Each scrape collected
+    assert_eq!(nas.call_count("pool.query"), 2);
+}
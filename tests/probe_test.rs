@@ -0,0 +1,143 @@
+//! `/probe` endpoint tests
+//!
+//! Scrapes several fake TrueNAS servers through one exporter.
+
+mod common;
+
+use common::{exporter_config, free_port, http_get, http_request, start_exporter_with};
+use serde_json::json;
+use truenas_exporter::config::{MetricsConfig, ProbeConfig, ProbeModule, TrueNasConfig};
+use truenas_exporter::test_support::FakeTrueNas;
+
+/// A "lab" module with the credentials of `nas` that may probe `targets`
+fn lab_module(nas: &FakeTrueNas, targets: Vec<String>) -> ProbeModule {
+    ProbeModule {
+        truenas: TrueNasConfig {
+            host: String::new(),
+            ..nas.config()
+        },
+        targets,
+    }
+}
+
+/// Start an exporter without a host of its own that probes with the "lab" module
+async fn start_prober(lab: ProbeModule, max_targets: usize) -> u16 {
+    let mut config = exporter_config(
+        TrueNasConfig::default(),
+        free_port().await,
+        MetricsConfig::default(),
+    );
+    config.probe = ProbeConfig {
+        modules: [("lab".to_string(), lab)].into(),
+        max_targets,
+        ..Default::default()
+    };
+    start_exporter_with(config).await
+}
+
+#[tokio::test]
+async fn test_probe_serves_each_target_separately() {
+    // Given: Two NASes and an exporter without a host of its own, whose "lab" module
+    // holds the connection settings
+    let (nas1, nas2) = (FakeTrueNas::start().await, FakeTrueNas::start().await);
+    nas1.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    nas2.respond(
+        "pool.query",
+        json!([{"name": "backup", "status": "DEGRADED", "healthy": false}]),
+    );
+    let port = start_prober(lab_module(&nas1, vec![nas1.host(), nas2.host()]), 100).await;
+
+    // When: Each NAS is probed twice
+    let probe_path = |nas: &FakeTrueNas| format!("/probe?target={}&module=lab", nas.host());
+    http_get(port, &probe_path(&nas1)).await.unwrap();
+    http_get(port, &probe_path(&nas2)).await.unwrap();
+    let body1 = http_get(port, &probe_path(&nas1)).await.unwrap();
+    let body2 = http_get(port, &probe_path(&nas2)).await.unwrap();
+
+    // Then: Each probe returns only its own target's metrics
+    assert!(body1.contains("truenas_up 1"), "{}", body1);
+    assert!(body1.contains("pool=\"tank\""), "{}", body1);
+    assert!(!body1.contains("pool=\"backup\""), "{}", body1);
+    assert!(body2.contains("pool=\"backup\""), "{}", body2);
+    assert!(!body2.contains("pool=\"tank\""), "{}", body2);
+
+    // And: Each target kept one authenticated connection across probes
+    assert_eq!(nas1.connections(), 1);
+    assert_eq!(nas2.connections(), 1);
+    assert_eq!(nas1.call_count("auth.login_with_api_key"), 1);
+
+    // And: Nothing was collected for /metrics
+    assert!(!http_get(port, "/metrics").await.unwrap().contains("pool="));
+}
+
+#[tokio::test]
+async fn test_probe_rejects_bad_requests() {
+    // Given: A "lab" module allowed to probe one NAS, and another NAS it does not list
+    let (nas, other) = (FakeTrueNas::start().await, FakeTrueNas::start().await);
+    let port = start_prober(lab_module(&nas, vec![nas.host()]), 100).await;
+    let probe = |query: String| async move {
+        http_request(port, "GET", &format!("/probe{}", query), "")
+            .await
+            .unwrap()
+    };
+
+    // When: A probe names no target, no module, an unknown module or an unlisted target
+    let (missing, _) = probe(String::new()).await;
+    let (default, _) = probe(format!("?target={}", other.host())).await;
+    let (unknown, body) = probe(format!("?target={}&module=nope", other.host())).await;
+    let (unlisted, unlisted_body) = probe(format!("?target={}&module=lab", other.host())).await;
+
+    // Then: Each is rejected, as there is no implicit default module
+    for head in [&missing, &default, &unknown, &unlisted] {
+        assert!(head.starts_with("HTTP/1.1 400"), "{}", head);
+    }
+    assert!(body.contains("Unknown module \"nope\""), "{}", body);
+    assert!(
+        unlisted_body.contains("is not allowed for module \"lab\""),
+        "{}",
+        unlisted_body
+    );
+
+    // And: The unlisted system was never contacted with the module's credentials
+    assert_eq!(other.connections(), 0);
+    assert_eq!(nas.connections(), 0);
+}
+
+#[tokio::test]
+async fn test_probe_targets_are_capped() {
+    // Given: A module listing two NASes, with room for one probe target
+    let (nas1, nas2) = (FakeTrueNas::start().await, FakeTrueNas::start().await);
+    let port = start_prober(lab_module(&nas1, vec![nas1.host(), nas2.host()]), 1).await;
+
+    // When: Both are probed
+    let probe_path = |nas: &FakeTrueNas| format!("/probe?target={}&module=lab", nas.host());
+    let (first, _) = http_request(port, "GET", &probe_path(&nas1), "")
+        .await
+        .unwrap();
+    let (second, _) = http_request(port, "GET", &probe_path(&nas2), "")
+        .await
+        .unwrap();
+
+    // Then: The second target is refused without connecting
+    assert!(first.starts_with("HTTP/1.1 200"), "{}", first);
+    assert!(second.starts_with("HTTP/1.1 503"), "{}", second);
+    assert_eq!(nas2.connections(), 0);
+}
+
+#[tokio::test]
+async fn test_health_is_ok_without_polled_targets() {
+    // Given: An exporter without a host of its own, only serving probes
+    let nas = FakeTrueNas::start().await;
+    let port = start_prober(lab_module(&nas, vec![nas.host()]), 100).await;
+
+    // When: The health check is requested before anything was probed
+    let (head, body) = http_request(port, "GET", "/health", "").await.unwrap();
+
+    // Then: It is healthy, since there is no NAS it is expected to reach
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert_eq!(body, "OK");
+    assert_eq!(nas.connections(), 0);
+}
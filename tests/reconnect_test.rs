@@ -0,0 +1,152 @@
+//! Reconnect backoff and circuit breaker tests
+
+use secrecy::SecretString;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use truenas_exporter::config::{ProtocolMode, ReconnectConfig, TrueNasConfig};
+use truenas_exporter::error::ExporterError;
+use truenas_exporter::metrics::MetricsCollector;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::reconnect::Backoff;
+use truenas_exporter::truenas::{ConnectionManager, ConnectionState, ConnectionStats};
+
+fn reconnect_config(threshold: u32) -> ReconnectConfig {
+    ReconnectConfig {
+        initial_backoff_seconds: 2,
+        max_backoff_seconds: 10,
+        circuit_breaker_threshold: threshold,
+    }
+}
+
+fn manager(host: String, reconnect: ReconnectConfig) -> ConnectionManager {
+    ConnectionManager::new(Arc::new(TrueNasConfig {
+        host,
+        api_key: SecretString::from("test-key"),
+        protocol: ProtocolMode::JsonRpc2,
+        reconnect,
+        ..Default::default()
+    }))
+}
+
+/// An address nothing is listening on
+async fn closed_port() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+#[test]
+fn test_backoff_delay_grows_with_jitter_and_cap() {
+    // Given: A 2s initial delay capped at 10s
+    let backoff = Backoff::new(reconnect_config(3));
+
+    for _ in 0..50 {
+        // When/Then: Each delay lies between half and all of the exponential value
+        for (failures, full) in [(1, 2.0), (2, 4.0), (3, 8.0), (4, 10.0), (30, 10.0)] {
+            let delay = backoff.delay_for(failures).as_secs_f64();
+            assert!(
+                (full / 2.0..=full).contains(&delay),
+                "failure {}: {}s outside [{}, {}]",
+                failures,
+                delay,
+                full / 2.0,
+                full
+            );
+        }
+    }
+}
+
+#[test]
+fn test_circuit_opens_at_threshold_and_closes_on_success() {
+    // Given: A circuit breaker threshold of two failures
+    let mut backoff = Backoff::new(reconnect_config(2));
+    let now = Instant::now();
+
+    // When: Failures accumulate
+    backoff.record_failure(now);
+    assert!(!backoff.circuit_open(now), "opened after one failure");
+    backoff.record_failure(now);
+
+    // Then: The circuit is open until the retry time, and a success resets it
+    assert!(backoff.circuit_open(now));
+    assert!(!backoff.circuit_open(now + Duration::from_secs(11)));
+    assert_eq!(backoff.record_success(), 2);
+    assert!(!backoff.circuit_open(now));
+    assert!(backoff.remaining(now).is_none());
+}
+
+#[tokio::test]
+async fn test_requests_fail_fast_while_backing_off() {
+    // Given: A manager pointing at a port nobody listens on
+    let manager = manager(closed_port().await, reconnect_config(1));
+
+    // When: The first request fails to connect
+    let first = manager.execute_query::<Value>("test.echo", None).await;
+    assert!(
+        matches!(first, Err(ExporterError::WebSocket(_))),
+        "{:?}",
+        first
+    );
+
+    // Then: Follow-up requests fail immediately without another attempt
+    let started = Instant::now();
+    let second = manager.execute_query::<Value>("test.echo", None).await;
+    assert!(
+        matches!(second, Err(ExporterError::Unavailable(_))),
+        "{:?}",
+        second
+    );
+    assert!(started.elapsed() < Duration::from_millis(100));
+
+    // And: The state reflects the backoff and the open circuit
+    let stats = manager.stats();
+    assert_eq!(stats.state, ConnectionState::BackingOff);
+    assert_eq!(stats.reconnects, 0);
+    assert!(manager.circuit_open());
+}
+
+#[tokio::test]
+async fn test_auth_failures_are_counted() {
+    // Given: A server that rejects the API key
+    let nas = FakeTrueNas::start().await;
+    nas.reject_auth(true);
+    let manager = manager(nas.host(), reconnect_config(3));
+
+    // When: A request is made
+    let err = manager
+        .execute_query::<Value>("test.echo", None)
+        .await
+        .unwrap_err();
+
+    // Then: The auth failure is counted and the circuit is not yet open
+    assert!(matches!(err, ExporterError::Auth(_)), "{:?}", err);
+    let stats = manager.stats();
+    assert_eq!(stats.auth_failures, 1);
+    assert_eq!(stats.state, ConnectionState::BackingOff);
+    assert!(!manager.circuit_open());
+}
+
+#[test]
+fn test_connection_stats_are_exported() {
+    // Given: Metrics that have already seen some reconnects
+    let metrics = MetricsCollector::new().unwrap();
+    metrics.update_connection_stats(&ConnectionStats {
+        state: ConnectionState::BackingOff,
+        reconnects: 2,
+        auth_failures: 1,
+    });
+
+    // When: Newer stats are synced
+    metrics.update_connection_stats(&ConnectionStats {
+        state: ConnectionState::Connected,
+        reconnects: 5,
+        auth_failures: 1,
+    });
+
+    // Then: Counters match the totals and the state is current
+    let output = metrics.render().unwrap();
+    assert!(output.contains("truenas_exporter_connection_state 1"));
+    assert!(output.contains("truenas_exporter_reconnects_total 5"));
+    assert!(output.contains("truenas_exporter_auth_failures_total 1"));
+}
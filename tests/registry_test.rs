@@ -0,0 +1,158 @@
+//! Collector registry tests
+
+use futures_util::future::{BoxFuture, FutureExt};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+use truenas_exporter::collectors::events::event_collections;
+use truenas_exporter::collectors::{
+    registry, CollectionContext, CollectionResult, CollectionStatus, Collector, Registry,
+};
+use truenas_exporter::config::MetricsConfig;
+use truenas_exporter::metrics::MetricsCollector;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::TrueNasClient;
+
+fn switches(collectors: &[(&str, bool)]) -> HashMap<String, bool> {
+    collectors
+        .iter()
+        .map(|(name, enabled)| (name.to_string(), *enabled))
+        .collect()
+}
+
+fn enabled(registry: &Registry) -> Vec<&'static str> {
+    registry
+        .enabled()
+        .map(|collector| collector.name())
+        .collect()
+}
+
+#[test]
+fn test_all_collectors_enabled_by_default() {
+    // Given/When: A registry from the default configuration
+    let registry = Registry::new(&MetricsConfig::default());
+
+    // Then: Every built-in collector is registered and enabled, with the scrape interval
+    assert_eq!(enabled(&registry), registry::names().collect::<Vec<_>>());
+    assert_eq!(registry.all().count(), 16);
+    let smart = registry.get("smart").unwrap();
+    assert_eq!(smart.interval(), Duration::from_secs(60));
+}
+
+#[test]
+fn test_collectors_switched_off_by_name() {
+    // Given: SMART and iSCSI disabled, and a custom SMART interval
+    let registry = Registry::new(&MetricsConfig {
+        collectors: switches(&[("smart", false), ("iscsi", false), ("disk", true)]),
+        intervals: HashMap::from([("smart".to_string(), 86400)]),
+        ..Default::default()
+    });
+
+    // When/Then: They are registered but not enabled
+    let names = enabled(&registry);
+    assert!(!names.contains(&"smart"));
+    assert!(!names.contains(&"iscsi"));
+    assert!(names.contains(&"disk"));
+    let smart = registry.get("smart").unwrap();
+    assert!(!smart.enabled());
+    assert_eq!(smart.interval(), Duration::from_secs(86400));
+}
+
+#[test]
+fn test_legacy_switches_are_overridden_by_name() {
+    // Given: Pool and system metrics disabled, but system_info switched back on
+    let config = MetricsConfig {
+        collect_pool_metrics: false,
+        collect_system_metrics: false,
+        collectors: switches(&[("system_info", true), ("alert", false)]),
+        ..Default::default()
+    };
+
+    // When: The registry is built
+    let names = enabled(&Registry::new(&config));
+
+    // Then: The legacy switches cover their collectors, explicit entries win
+    assert!(!names.contains(&"pool"));
+    assert!(!names.contains(&"dataset"));
+    assert!(!names.contains(&"system_reporting"));
+    assert!(names.contains(&"system_info"));
+
+    // And: Event subscriptions follow the same switches
+    let collections = event_collections(&config);
+    assert!(!collections.contains(&"pool.query"));
+    assert!(!collections.contains(&"alert.list"));
+    assert!(collections.contains(&"service.query"));
+}
+
+#[test]
+fn test_help_lists_every_collector() {
+    // Given/When: The --help collector section
+    let help = registry::help();
+
+    // Then: Each collector appears on its own line
+    for name in registry::names() {
+        assert!(
+            help.lines().any(|line| line.trim_start().starts_with(name)),
+            "{} missing from:\n{}",
+            name,
+            help
+        );
+    }
+}
+
+/// A collector outside the built-in set
+struct Custom;
+
+impl Collector for Custom {
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(5)
+    }
+
+    fn collect<'a>(&'a self, ctx: &'a CollectionContext<'a>) -> BoxFuture<'a, CollectionResult> {
+        async move {
+            ctx.metrics.up.set(1.0);
+            Ok(CollectionStatus::Success)
+        }
+        .boxed()
+    }
+}
+
+#[tokio::test]
+async fn test_registered_collectors_run_through_the_trait() {
+    // Given: A registry with a custom collector and a fake NAS with one pool
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    let client = TrueNasClient::new(nas.config());
+    let metrics = MetricsCollector::new().unwrap();
+    let config = MetricsConfig::default();
+    let ctx = CollectionContext {
+        client: &client,
+        metrics: &metrics,
+        config: &config,
+    };
+    let mut registry = Registry::new(&config);
+    registry.register(Box::new(Custom));
+
+    // When: The pool and custom collectors are run
+    let pool = registry.get("pool").unwrap().collect(&ctx).await.unwrap();
+    let custom = registry.get("custom").unwrap().collect(&ctx).await.unwrap();
+
+    // Then: Both update their metrics
+    assert_eq!(pool, CollectionStatus::Success);
+    assert_eq!(custom, CollectionStatus::Success);
+    assert_eq!(enabled(&registry).last(), Some(&"custom"));
+    let output = metrics.render().unwrap();
+    assert!(output.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 1"));
+    assert!(output.contains("truenas_up 1"));
+}
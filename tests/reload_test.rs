@@ -0,0 +1,160 @@
+//! Configuration reload tests
+//!
+//! Runs the exporter from a configuration file against the fake TrueNAS middleware and
+//! changes the file while it runs.
+
+mod common;
+
+use common::{free_port, scratch_path, wait_for_port};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use truenas_exporter::config::{Config, ConfigSource};
+use truenas_exporter::server;
+use truenas_exporter::test_support::FakeTrueNas;
+
+/// A configuration file collecting on scrape from `nas`
+fn config_file(nas: &FakeTrueNas, port: u16, api_key: &str, pool: bool) -> String {
+    format!(
+        r#"
+[truenas]
+host = "{}"
+api_key = "{}"
+use_tls = false
+protocol = "jsonrpc2"
+
+[server]
+addr = "127.0.0.1"
+port = {}
+
+[metrics]
+collection_mode = "on_scrape"
+min_refresh_age_seconds = 0
+
+[metrics.collectors]
+pool = {}
+"#,
+        nas.host(),
+        api_key,
+        port,
+        pool
+    )
+}
+
+/// Send a request to the exporter and return the response head and body
+async fn http_request(port: u16, method: &str, path: &str) -> (String, String) {
+    common::http_request(port, method, path, "").await.unwrap()
+}
+
+/// Start the exporter from `path`, reloading it from there, and wait until it listens
+async fn start_exporter(path: &Path, port: u16) {
+    let load_path = path.to_str().unwrap().to_string();
+    let config = Config::load(&load_path).unwrap();
+    let source = ConfigSource::new(vec![path.to_path_buf()], move || Config::load(&load_path));
+    tokio::spawn(server::start_with_shutdown(
+        config,
+        Some(source),
+        std::future::pending(),
+    ));
+    wait_for_port(port).await;
+}
+
+fn scratch_file(name: &str) -> PathBuf {
+    scratch_path(name).with_extension("toml")
+}
+
+#[tokio::test]
+async fn test_reload_endpoint_applies_valid_config() {
+    // Given: An exporter collecting pools, started from a configuration file
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    let port = free_port().await;
+    let path = scratch_file("reload");
+    std::fs::write(&path, config_file(&nas, port, "fake-api-key", true)).unwrap();
+    start_exporter(&path, port).await;
+    let (_, body) = http_request(port, "GET", "/metrics").await;
+    assert!(body.contains("truenas_pool_health{"), "{}", body);
+    assert!(
+        body.contains("truenas_exporter_config_last_reload_successful 1"),
+        "{}",
+        body
+    );
+
+    // When: The pool collector is switched off and a reload is requested
+    std::fs::write(&path, config_file(&nas, port, "fake-api-key", false)).unwrap();
+    let (head, _) = http_request(port, "POST", "/-/reload").await;
+    let (_, body) = http_request(port, "GET", "/metrics").await;
+
+    // Then: Pools are no longer collected, over the same connection
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(!body.contains("truenas_pool_health{"), "{}", body);
+    assert_eq!(nas.connections(), 1);
+
+    // And: An invalid configuration is rejected and the running one kept
+    std::fs::write(&path, "[metrics]\nscrape_interval_seconds = \"often\"\n").unwrap();
+    let (head, error) = http_request(port, "POST", "/-/reload").await;
+    let (_, body) = http_request(port, "GET", "/metrics").await;
+    assert!(head.starts_with("HTTP/1.1 500"), "{}", head);
+    assert!(
+        error.contains("Failed to reload configuration"),
+        "{}",
+        error
+    );
+    assert!(
+        body.contains("truenas_exporter_config_last_reload_successful 0"),
+        "{}",
+        body
+    );
+    assert!(!body.contains("truenas_pool_health{"), "{}", body);
+
+    // And: So is one that parses but would collect in a busy loop
+    let busy = config_file(&nas, port, "fake-api-key", true)
+        .replace("[metrics]\n", "[metrics]\nscrape_interval_seconds = 0\n");
+    std::fs::write(&path, busy).unwrap();
+    let (head, error) = http_request(port, "POST", "/-/reload").await;
+    let (_, body) = http_request(port, "GET", "/metrics").await;
+    assert!(head.starts_with("HTTP/1.1 500"), "{}", head);
+    assert!(error.contains("scrape_interval_seconds"), "{}", error);
+    assert!(!body.contains("truenas_pool_health{"), "{}", body);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_changed_file_reconnects_with_new_credentials() {
+    // Given: A running exporter started from a configuration file
+    let nas = FakeTrueNas::start().await;
+    let port = free_port().await;
+    let path = scratch_file("reload-watch");
+    std::fs::write(&path, config_file(&nas, port, "old-key", true)).unwrap();
+    start_exporter(&path, port).await;
+    http_request(port, "GET", "/metrics").await;
+    assert_eq!(nas.connections(), 1);
+
+    // When: The API key in the file changes
+    std::fs::write(&path, config_file(&nas, port, "new-key", true)).unwrap();
+    let mut reloaded = false;
+    for _ in 0..100 {
+        let (_, body) = http_request(port, "GET", "/metrics").await;
+        if nas.connections() == 2 {
+            reloaded = body.contains("truenas_exporter_config_last_reload_successful 1");
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Then: The change is picked up without a request, and the old session is closed
+    assert!(reloaded, "configuration was not reloaded");
+    for _ in 0..50 {
+        if nas.closed_connections() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(nas.closed_connections(), 1);
+
+    std::fs::remove_file(path).unwrap();
+}
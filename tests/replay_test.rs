@@ -0,0 +1,177 @@
+//! Record and replay tests
+
+mod common;
+
+use common::scratch_dir;
+use secrecy::SecretString;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use truenas_exporter::collectors::{self, CollectionContext, CollectionStatus};
+use truenas_exporter::config::{MetricsConfig, ProtocolMode, TrueNasConfig};
+use truenas_exporter::error::ExporterError;
+use truenas_exporter::metrics::MetricsCollector;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::replay::fixture_name;
+use truenas_exporter::truenas::{Capability, ConnectionManager, TrueNasClient};
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay")
+}
+
+/// A 24.10 server answering `pool.query` with one pool and `test.fail` with an error
+async fn start_server() -> FakeTrueNas {
+    let nas = FakeTrueNas::start().await;
+    nas.respond("system.version", json!("TrueNAS-SCALE-24.10.2"));
+    nas.respond("pool.query", json!([{"name": "tank"}]));
+    nas.respond("test.echo", json!(["a", 1]));
+    nas.respond_error("test.fail", "ENOENT", "Dataset does not exist");
+    nas
+}
+
+fn config(host: String) -> TrueNasConfig {
+    TrueNasConfig {
+        host,
+        api_key: SecretString::from("test-key"),
+        protocol: ProtocolMode::JsonRpc2,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_fixture_names() {
+    // Given/When/Then: Calls without params are named after the method
+    assert_eq!(fixture_name("pool.query", None), "pool.query.json");
+    assert_eq!(
+        fixture_name("pool.query", Some(&Value::Null)),
+        "pool.query.json"
+    );
+
+    // And: Params add a stable hash that tells different calls apart
+    let a = fixture_name("reporting.get_data", Some(&json!([{"name": "cpu"}])));
+    let b = fixture_name("reporting.get_data", Some(&json!([{"name": "memory"}])));
+    assert_eq!(
+        a,
+        fixture_name("reporting.get_data", Some(&json!([{"name": "cpu"}])))
+    );
+    assert_ne!(a, b);
+    assert!(a.starts_with("reporting.get_data-") && a.ends_with(".json"));
+
+    // And: The time window moves with the clock, so it does not change the name
+    let queries = json!([{"name": "cpu"}]);
+    assert_eq!(
+        fixture_name(
+            "reporting.get_data",
+            Some(&json!([queries, {"start": 1727000000}]))
+        ),
+        fixture_name(
+            "reporting.get_data",
+            Some(&json!([queries, {"start": 1727000060, "end": 1727000360}]))
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_recorded_session_replays_offline() {
+    // Given: A session recorded against a live server
+    let dir = scratch_dir("record");
+    let nas = start_server().await;
+    let recording = ConnectionManager::new(Arc::new(TrueNasConfig {
+        record_dir: Some(dir.clone()),
+        ..config(nas.host())
+    }));
+    let pools: Value = recording.execute_query("pool.query", None).await.unwrap();
+    let echoed: Value = recording
+        .execute_query("test.echo", Some(json!(["a", 1])))
+        .await
+        .unwrap();
+    let failure = recording
+        .execute_query::<Value>("test.fail", None)
+        .await
+        .unwrap_err();
+
+    // When: The recording is replayed with nothing listening
+    let replay = ConnectionManager::new(Arc::new(TrueNasConfig {
+        replay_dir: Some(dir.clone()),
+        ..config("127.0.0.1:1".to_string())
+    }));
+
+    // Then: Results, params-specific replies and API errors come back unchanged
+    let replayed: Value = replay.execute_query("pool.query", None).await.unwrap();
+    assert_eq!(replayed, pools);
+    let replayed: Value = replay
+        .execute_query("test.echo", Some(json!(["a", 1])))
+        .await
+        .unwrap();
+    assert_eq!(replayed, echoed);
+    let replayed = replay
+        .execute_query::<Value>("test.fail", None)
+        .await
+        .unwrap_err();
+    assert_eq!(replayed.to_string(), failure.to_string());
+
+    // And: The version detected while recording is known without connecting
+    assert_eq!(
+        replay.server_version().unwrap().raw,
+        "TrueNAS-SCALE-24.10.2"
+    );
+
+    // And: Calls that were never recorded fail clearly
+    let missing = replay
+        .execute_query::<Value>("disk.query", None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(missing, ExporterError::TrueNasApi(_)),
+        "{:?}",
+        missing
+    );
+    assert!(missing
+        .to_string()
+        .contains("no recorded response for disk.query"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_collectors_run_against_replayed_appliance() {
+    // Given: Fixtures captured from a 24.04 system with a degraded pool
+    let client = TrueNasClient::new(TrueNasConfig {
+        replay_dir: Some(fixtures()),
+        ..config("127.0.0.1:1".to_string())
+    });
+    let metrics = MetricsCollector::new().unwrap();
+    let ctx = CollectionContext {
+        client: &client,
+        metrics: &metrics,
+        config: &MetricsConfig::default(),
+    };
+
+    // When: Collectors run
+    let pool = collectors::collect_pool_metrics(&ctx).await.unwrap();
+    let boot = collectors::collect_boot_pool_metrics(&ctx).await.unwrap();
+    let reporting = collectors::collect_system_reporting_metrics(&ctx)
+        .await
+        .unwrap();
+
+    // Then: Metrics reflect the recorded payloads
+    assert!(matches!(pool, CollectionStatus::Success));
+    assert!(matches!(boot, CollectionStatus::Success));
+    assert!(matches!(reporting, CollectionStatus::Success));
+    let output = metrics.render().unwrap();
+    assert!(output.contains("truenas_pool_health{pool=\"tank\",status=\"DEGRADED\"} 0"));
+    assert!(output.contains("truenas_boot_pool_health 1"));
+
+    // And: Reporting data recorded minutes earlier is found despite its time window
+    assert!(
+        output.contains("truenas_system_cpu_usage_percent{mode=\"user\"} 12.5"),
+        "{}",
+        output
+    );
+    assert!(output.contains("truenas_disk_temperature_celsius{device=\"sda\"} 36"));
+    assert!(output.contains("truenas_network_receive_bytes_per_second{interface=\"eno1\"} 1250"));
+
+    // And: Version gating follows the recorded release
+    assert!(!client.supports(Capability::Apps));
+    assert!(client.supports(Capability::NfsClients));
+}
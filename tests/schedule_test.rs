@@ -0,0 +1,137 @@
+//! Per-collector schedule tests
+
+mod common;
+
+use common::{http_request, start_exporter};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use truenas_exporter::collectors::{registry, schedule::Schedule};
+use truenas_exporter::config::MetricsConfig;
+use truenas_exporter::test_support::FakeTrueNas;
+
+fn schedule(intervals: &[(&str, u64)], jitter: u64) -> Schedule {
+    Schedule::new(&MetricsConfig {
+        scrape_interval_seconds: 60,
+        intervals: intervals
+            .iter()
+            .map(|(name, seconds)| (name.to_string(), *seconds))
+            .collect::<HashMap<_, _>>(),
+        interval_jitter_seconds: jitter,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_intervals_override_scrape_interval() {
+    // Given: Overrides for pool and smart only
+    let schedule = schedule(&[("pool", 30), ("smart", 86400)], 0);
+
+    // When/Then: Listed collectors use their own interval, others the default
+    assert_eq!(schedule.interval("pool"), Duration::from_secs(30));
+    assert_eq!(schedule.interval("smart"), Duration::from_secs(86400));
+    assert_eq!(schedule.interval("disk"), Duration::from_secs(60));
+}
+
+#[test]
+fn test_collectors_run_when_due() {
+    // Given: A fresh schedule with a 30s pool interval
+    let mut schedule = schedule(&[("pool", 30)], 0);
+    let start = Instant::now();
+
+    // Then: Everything is due before the first run, and nothing is scheduled yet
+    assert!(schedule.is_due("pool", start));
+    assert!(schedule.is_due("disk", start));
+    assert_eq!(schedule.next_wakeup(), None);
+
+    // When: Both run
+    schedule.mark_run("pool", start);
+    schedule.mark_run("disk", start);
+
+    // Then: Each becomes due again after its own interval
+    assert_eq!(
+        schedule.next_wakeup(),
+        Some(start + Duration::from_secs(30))
+    );
+    let later = start + Duration::from_secs(30);
+    assert!(schedule.is_due("pool", later));
+    assert!(!schedule.is_due("disk", later));
+    assert!(schedule.is_due("disk", start + Duration::from_secs(60)));
+}
+
+#[test]
+fn test_jitter_delays_next_run_within_bound() {
+    // Given: Up to 10s of jitter on a 60s interval
+    let mut schedule = schedule(&[], 10);
+    let start = Instant::now();
+
+    for _ in 0..50 {
+        // When: A collector runs
+        schedule.mark_run("disk", start);
+
+        // Then: The next run is 60-70s later
+        let next = schedule.next_wakeup().unwrap();
+        assert!(next >= start + Duration::from_secs(60));
+        assert!(next <= start + Duration::from_secs(70));
+    }
+}
+
+#[test]
+fn test_postponed_collectors_are_retried_at_scrape_interval() {
+    // Given: A daily collector that is due
+    let mut schedule = schedule(&[("smart", 86400)], 0);
+    let start = Instant::now();
+    schedule.mark_run("smart", start);
+    let due = start + Duration::from_secs(86400);
+
+    // When: The cycle is skipped
+    schedule.postpone_due(due);
+
+    // Then: It is retried after the scrape interval, not a day later
+    assert!(!schedule.is_due("smart", due));
+    assert_eq!(schedule.next_wakeup(), Some(due + Duration::from_secs(60)));
+}
+
+#[test]
+fn test_failed_collector_is_retried_then_rescheduled() {
+    // Given: A daily collector and a pool collector faster than the scrape interval
+    let mut schedule = schedule(&[("smart", 86400), ("pool", 30)], 0);
+    let start = Instant::now();
+
+    // When: Both fail
+    schedule.mark_failed("smart", start);
+    schedule.mark_failed("pool", start);
+
+    // Then: Each is retried after the scrape interval or its own, if shorter
+    assert!(schedule.is_due("pool", start + Duration::from_secs(30)));
+    let retry = start + Duration::from_secs(60);
+    assert!(!schedule.is_due("smart", retry - Duration::from_secs(1)));
+    assert!(schedule.is_due("smart", retry));
+
+    // When: The retry succeeds
+    schedule.mark_run("smart", retry);
+
+    // Then: The full interval applies again
+    assert!(!schedule.is_due("smart", retry + Duration::from_secs(60)));
+    assert!(schedule.is_due("smart", retry + Duration::from_secs(86400)));
+}
+
+#[tokio::test]
+async fn test_loop_idles_when_no_collector_is_scheduled() {
+    // Given: An exporter collecting in the background with every collector disabled
+    let nas = FakeTrueNas::start().await;
+    let metrics = MetricsConfig {
+        collectors: registry::names()
+            .map(|name| (name.to_string(), false))
+            .collect(),
+        ..Default::default()
+    };
+    let port = start_exporter(nas.config(), metrics).await;
+
+    // When: It is scraped after more than a second
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let (head, _) = http_request(port, "GET", "/metrics", "").await.unwrap();
+
+    // Then: The first cycle is still the latest, instead of one per loop iteration
+    assert!(head.to_lowercase().contains("\r\nage: 1"), "{}", head);
+    assert!(nas.connections() <= 1);
+}
@@ -0,0 +1,58 @@
+//! Graceful shutdown tests
+
+mod common;
+
+use common::{eventually, exporter_config, free_port, http_request, wait_for_port};
+use serde_json::json;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use truenas_exporter::config::{CollectionMode, MetricsConfig};
+use truenas_exporter::server;
+use truenas_exporter::test_support::FakeTrueNas;
+
+#[tokio::test]
+async fn test_shutdown_finishes_scrapes_and_closes_connection() {
+    // Given: An exporter collecting on scrape from a NAS with a slow pool query
+    let nas = FakeTrueNas::start().await;
+    nas.respond(
+        "pool.query",
+        json!([{"name": "tank", "status": "ONLINE", "healthy": true}]),
+    );
+    nas.delay("pool.query", Duration::from_millis(500));
+    let port = free_port().await;
+    let config = exporter_config(
+        nas.config(),
+        port,
+        MetricsConfig {
+            collection_mode: CollectionMode::OnScrape,
+            ..Default::default()
+        },
+    );
+    let (shutdown, signal) = tokio::sync::oneshot::channel::<()>();
+    let exporter = tokio::spawn(server::start_with_shutdown(config, None, async move {
+        let _ = signal.await;
+    }));
+    wait_for_port(port).await;
+
+    // When: Shutdown is requested while a scrape waits for TrueNAS
+    let scrape = tokio::spawn(http_request(port, "GET", "/metrics", ""));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    shutdown.send(()).unwrap();
+    let stopped = tokio::time::timeout(Duration::from_secs(5), exporter).await;
+
+    // Then: The exporter stops in time
+    assert!(matches!(stopped, Ok(Ok(Ok(())))), "{:?}", stopped);
+
+    // And: The in-flight scrape was answered
+    let (head, body) = scrape.await.unwrap().unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(
+        body.contains("truenas_pool_health{pool=\"tank\",status=\"ONLINE\"} 1"),
+        "{}",
+        body
+    );
+
+    // And: The WebSocket was closed with a Close frame and no longer listens
+    assert!(eventually(|| nas.closed_connections() == 1).await);
+    assert!(TcpStream::connect(("127.0.0.1", port)).await.is_err());
+}
@@ -0,0 +1,234 @@
+//! TLS connection tests
+//!
+//! Runs the `ConnectionManager` against an in-process `wss://` server whose certificate
+//! is issued for `truenas.test` by the test CA in `tests/fixtures/tls`.
+
+use futures_util::{SinkExt, StreamExt};
+use secrecy::SecretString;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use truenas_exporter::config::{ProtocolMode, TlsConfig, TrueNasConfig};
+use truenas_exporter::error::ExporterError;
+use truenas_exporter::truenas::{tls, ConnectionManager};
+
+/// SHA-256 fingerprint of `tests/fixtures/tls/server.pem`
+const SERVER_FINGERPRINT: &str =
+    "7D:ED:5E:68:64:1C:E1:90:0D:3D:7C:22:2F:6F:71:ED:50:B6:BD:42:A9:8E:51:79:7A:F2:4C:95:4F:4C:12:F4";
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tls")
+        .join(name)
+}
+
+/// Start a JSON-RPC 2.0 server over TLS and return its address
+async fn start_tls_server() -> String {
+    let cert = std::fs::read(fixture("server.pem")).unwrap();
+    let key = std::fs::read(fixture("server-key.pem")).unwrap();
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key).unwrap();
+    let acceptor = tokio_native_tls::TlsAcceptor::from(
+        native_tls::TlsAcceptor::builder(identity).build().unwrap(),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(tls) = acceptor.accept(tcp).await else {
+                    return;
+                };
+                let Ok(mut ws) = tokio_tungstenite::accept_async(tls).await else {
+                    return;
+                };
+                while let Some(Ok(Message::Text(text))) = ws.next().await {
+                    let request: Value = serde_json::from_str(&text).unwrap();
+                    let result = match request["method"].as_str() {
+                        Some("auth.login_with_api_key") => json!(true),
+                        other => json!(other),
+                    };
+                    let reply = json!({"jsonrpc": "2.0", "id": request["id"], "result": result});
+                    if ws
+                        .send(Message::Text(reply.to_string().into()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    addr
+}
+
+fn tls_config(host: String, verify_ssl: bool, tls: TlsConfig) -> TrueNasConfig {
+    TrueNasConfig {
+        host,
+        api_key: SecretString::from("test-key"),
+        use_tls: true,
+        verify_ssl,
+        protocol: ProtocolMode::JsonRpc2,
+        tls,
+        ..Default::default()
+    }
+}
+
+async fn query(config: TrueNasConfig) -> Result<String, ExporterError> {
+    ConnectionManager::new(Arc::new(config))
+        .execute_query("test.echo", None)
+        .await
+}
+
+#[tokio::test]
+async fn test_custom_ca_with_server_name_override() {
+    // Given: The test CA as trust anchor and the certificate's name as SNI override
+    let config = tls_config(
+        start_tls_server().await,
+        true,
+        TlsConfig {
+            ca_file: Some(fixture("ca.pem")),
+            server_name: Some("truenas.test".to_string()),
+            ..Default::default()
+        },
+    );
+
+    // When: A query is executed over the verified connection
+    let result = query(config).await;
+
+    // Then: The certificate is accepted
+    assert_eq!(result.unwrap(), "test.echo");
+}
+
+#[tokio::test]
+async fn test_custom_ca_still_checks_hostname() {
+    // Given: The test CA but no server name override (certificate is not for 127.0.0.1)
+    let config = tls_config(
+        start_tls_server().await,
+        true,
+        TlsConfig {
+            ca_file: Some(fixture("ca.pem")),
+            ..Default::default()
+        },
+    );
+
+    // When: A query is executed
+    let err = query(config).await.unwrap_err();
+
+    // Then: The handshake is rejected as a TLS error, not a configuration error
+    assert!(matches!(err, ExporterError::Tls(_)), "{:?}", err);
+    assert!(
+        err.to_string().contains("TLS connection failed"),
+        "unexpected error: {}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_matching_pin_trusts_certificate_without_ca() {
+    // Given: Only a fingerprint pin, in lowercase without separators
+    let pin = SERVER_FINGERPRINT.replace(':', "").to_lowercase();
+    let config = tls_config(
+        start_tls_server().await,
+        true,
+        TlsConfig {
+            pin_sha256: Some(pin),
+            ..Default::default()
+        },
+    );
+
+    // When: A query is executed
+    let result = query(config).await;
+
+    // Then: The pinned certificate is accepted
+    assert_eq!(result.unwrap(), "test.echo");
+}
+
+#[tokio::test]
+async fn test_mismatched_pin_is_rejected() {
+    // Given: A pin for a different certificate
+    let config = tls_config(
+        start_tls_server().await,
+        true,
+        TlsConfig {
+            pin_sha256: Some("00".repeat(32)),
+            ..Default::default()
+        },
+    );
+
+    // When: A query is executed
+    let err = query(config).await.unwrap_err();
+
+    // Then: The connection is refused and the actual fingerprint is reported
+    assert!(matches!(err, ExporterError::Tls(_)), "{:?}", err);
+    let message = err.to_string();
+    assert!(message.contains("does not match pin_sha256"), "{}", message);
+    assert!(message.contains(SERVER_FINGERPRINT), "{}", message);
+}
+
+#[test]
+fn test_client_identity_is_loaded() {
+    // Given: A client certificate and key issued by the test CA
+    let config = tls_config(
+        "truenas.test:443".to_string(),
+        true,
+        TlsConfig {
+            client_cert: Some(fixture("client.pem")),
+            client_key: Some(fixture("client-key.pem")),
+            ..Default::default()
+        },
+    );
+
+    // When: The connector is built
+    let connector = tls::connector(&config);
+
+    // Then: The identity is accepted
+    assert!(connector.is_ok(), "{:?}", connector.err());
+}
+
+#[test]
+fn test_invalid_tls_settings_are_config_errors() {
+    let cases = [
+        TlsConfig {
+            client_cert: Some(fixture("client.pem")),
+            ..Default::default()
+        },
+        TlsConfig {
+            ca_file: Some(fixture("missing.pem")),
+            ..Default::default()
+        },
+        TlsConfig {
+            ca_file: Some(fixture("server-key.pem")),
+            ..Default::default()
+        },
+    ];
+
+    for tls_settings in cases {
+        // Given: An incomplete or unreadable TLS setting
+        let config = tls_config("truenas.test:443".to_string(), true, tls_settings);
+
+        // When: The connector is built
+        let err = tls::connector(&config).unwrap_err();
+
+        // Then: A configuration error is reported
+        assert!(matches!(err, ExporterError::Config(_)), "{:?}", err);
+    }
+}
+
+#[test]
+fn test_fingerprint_parsing() {
+    // Given/When/Then: Colon-separated and plain hex parse to the same bytes
+    let with_colons = tls::parse_fingerprint(SERVER_FINGERPRINT).unwrap();
+    let plain = tls::parse_fingerprint(&SERVER_FINGERPRINT.replace(':', "")).unwrap();
+    assert_eq!(with_colons, plain);
+
+    // And: Truncated or non-hex values are rejected
+    assert!(tls::parse_fingerprint("7D:ED:5E").is_err());
+    assert!(tls::parse_fingerprint(&"zz".repeat(32)).is_err());
+}
@@ -186,3 +186,69 @@ fn test_deserialize_alert() {
     assert_eq!(alert.level, "CRITICAL");
     assert!(!alert.dismissed);
 }
+
+#[test]
+fn test_request_envelopes_per_protocol() {
+    // Given: The same call built for each endpoint
+    let ddp = JsonRpcRequest::ddp("1".to_string(), "pool.query", Some(json!(null)));
+    let rpc2 = JsonRpcRequest::jsonrpc2("1".to_string(), "pool.query", Some(json!(null)));
+    let rpc2_scalar =
+        JsonRpcRequest::jsonrpc2("2".to_string(), "auth.generate_token", Some(json!(600)));
+
+    // When: serialized
+    let ddp = serde_json::to_value(&ddp).unwrap();
+    let rpc2 = serde_json::to_value(&rpc2).unwrap();
+    let rpc2_scalar = serde_json::to_value(&rpc2_scalar).unwrap();
+
+    // Then: DDP keeps its envelope, JSON-RPC 2.0 only carries structured params
+    assert_eq!(
+        ddp,
+        json!({"id": "1", "msg": "method", "method": "pool.query", "params": null})
+    );
+    assert_eq!(
+        rpc2,
+        json!({"jsonrpc": "2.0", "id": "1", "method": "pool.query"})
+    );
+    assert_eq!(rpc2_scalar["params"], json!([600]));
+}
+
+#[test]
+fn test_deserialize_error_from_both_protocols() {
+    // Given: the same failure reported by the legacy and JSON-RPC 2.0 endpoints
+    let ddp: JsonRpcResponse = serde_json::from_value(json!({
+        "id": "7",
+        "msg": "result",
+        "error": {"error": 13, "errname": "ENOTAUTHENTICATED", "reason": "Not authenticated"}
+    }))
+    .expect("Failed to parse DDP error");
+    let rpc2: JsonRpcResponse = serde_json::from_value(json!({
+        "jsonrpc": "2.0",
+        "id": "7",
+        "error": {
+            "code": -32001,
+            "message": "Method call error",
+            "data": {"error": 13, "errname": "ENOTAUTHENTICATED", "reason": "Not authenticated"}
+        }
+    }))
+    .expect("Failed to parse JSON-RPC 2.0 error");
+
+    // Then: both expose the same reason and error name
+    for response in [ddp, rpc2] {
+        let error = response.error.expect("error should be present");
+        assert_eq!(error.reason(), "Not authenticated");
+        assert_eq!(error.errname(), Some("ENOTAUTHENTICATED"));
+        assert!(error.is_not_authenticated());
+    }
+}
+
+#[test]
+fn test_error_reason_falls_back_to_message() {
+    // Given: a JSON-RPC 2.0 protocol error without middleware details
+    let error: JsonRpcError =
+        serde_json::from_value(json!({"code": -32601, "message": "Method not found"}))
+            .expect("Failed to parse error");
+
+    // Then: the top-level message is used
+    assert_eq!(error.reason(), "Method not found");
+    assert_eq!(error.errname(), None);
+}
@@ -0,0 +1,139 @@
+//! Server version detection and capability tests
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+use truenas_exporter::metrics::MetricsCollector;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::{Capability, ConnectionManager, ServerVersion};
+
+/// Start a server reporting the given `system.version` reply
+///
+/// `nfs.get_nfs4_clients` is left unscripted, so it is answered with "method not found".
+async fn start_server(version: Value) -> FakeTrueNas {
+    let nas = FakeTrueNas::start().await;
+    nas.respond("system.version", version);
+    nas.respond("system.info", json!({"version": "TrueNAS-SCALE-23.10.2"}));
+    nas.respond("test.echo", json!("test.echo"));
+    nas
+}
+
+fn manager(nas: &FakeTrueNas) -> ConnectionManager {
+    ConnectionManager::new(Arc::new(nas.config()))
+}
+
+#[test]
+fn test_version_parsing() {
+    // Given/When/Then: Release numbers are found in the formats TrueNAS reports
+    for (raw, expected) in [
+        ("TrueNAS-SCALE-24.10.2", (24, 10)),
+        ("TrueNAS-25.04.0", (25, 4)),
+        ("25.10-MASTER-20250101-120000", (25, 10)),
+        ("TrueNAS-SCALE-22.12.4.2", (22, 12)),
+    ] {
+        let version = ServerVersion::parse(raw).unwrap();
+        assert_eq!((version.major, version.minor), expected, "{}", raw);
+        assert_eq!(version.to_string(), raw);
+    }
+
+    // And: Strings without a release number are rejected
+    assert!(ServerVersion::parse("TrueNAS-SCALE").is_none());
+    assert!(ServerVersion::parse("13").is_none());
+}
+
+#[test]
+fn test_capability_matrix() {
+    // Given: A release from before the apps rewrite
+    let dragonfish = ServerVersion::parse("TrueNAS-SCALE-24.04.2").unwrap();
+
+    // When/Then: Older APIs are supported, newer ones are not
+    assert!(dragonfish.supports(Capability::BootPoolState));
+    assert!(dragonfish.supports(Capability::NfsClients));
+    assert!(!dragonfish.supports(Capability::Apps));
+    assert!(!dragonfish.supports(Capability::JsonRpc2Api));
+
+    // And: A current release supports everything
+    let fangtooth = ServerVersion::parse("TrueNAS-25.04.0").unwrap();
+    assert!(fangtooth.supports(Capability::Apps));
+    assert!(fangtooth.supports(Capability::JsonRpc2Api));
+
+    // And: Methods map to the capability they depend on
+    assert_eq!(Capability::of_method("app.query"), Some(Capability::Apps));
+    assert_eq!(Capability::of_method("pool.query"), None);
+}
+
+#[tokio::test]
+async fn test_version_detected_on_connect() {
+    // Given: A server running 24.04
+    let nas = start_server(json!("TrueNAS-SCALE-24.04.2")).await;
+    let manager = manager(&nas);
+
+    // Then: Nothing is known before connecting, so everything is assumed supported
+    assert!(manager.server_version().is_none());
+    assert!(manager.supports(Capability::Apps));
+
+    // When: A query connects
+    let _: String = manager.execute_query("test.echo", None).await.unwrap();
+
+    // Then: The release gates capabilities
+    let version = manager.server_version().unwrap();
+    assert_eq!((version.major, version.minor), (24, 4));
+    assert!(!manager.supports(Capability::Apps));
+    assert!(manager.supports(Capability::IscsiClients));
+}
+
+#[tokio::test]
+async fn test_version_falls_back_to_system_info() {
+    // Given: A server whose system.version reply carries no release number
+    let nas = start_server(json!(null)).await;
+    let manager = manager(&nas);
+
+    // When: A query connects
+    let _: String = manager.execute_query("test.echo", None).await.unwrap();
+
+    // Then: The version comes from system.info
+    assert_eq!(
+        manager.server_version().unwrap().raw,
+        "TrueNAS-SCALE-23.10.2"
+    );
+}
+
+#[tokio::test]
+async fn test_method_not_found_disables_capability() {
+    // Given: A release that should offer NFS client listing but does not
+    let nas = start_server(json!("TrueNAS-SCALE-24.10.0")).await;
+    let manager = manager(&nas);
+    assert!(manager.supports(Capability::NfsClients));
+
+    // When: The method is reported as not found
+    let result = manager
+        .execute_query::<Value>("nfs.get_nfs4_clients", None)
+        .await;
+
+    // Then: The capability is switched off, others are unaffected
+    assert!(result.is_err());
+    assert!(!manager.supports(Capability::NfsClients));
+    assert!(manager.supports(Capability::Apps));
+}
+
+#[test]
+fn test_build_info_carries_truenas_version() {
+    // Given: Metrics before any connection
+    let metrics = MetricsCollector::new().unwrap();
+    metrics.update_build_info(None);
+    assert!(metrics
+        .render()
+        .unwrap()
+        .contains("truenas_version=\"unknown\""));
+
+    // When: The version becomes known
+    let version = ServerVersion::parse("TrueNAS-25.04.0").unwrap();
+    metrics.update_build_info(Some(&version));
+
+    // Then: Only the detected version is exported
+    let output = metrics.render().unwrap();
+    assert!(output.contains(&format!(
+        "truenas_exporter_build_info{{truenas_version=\"TrueNAS-25.04.0\",version=\"{}\"}} 1",
+        env!("CARGO_PKG_VERSION")
+    )));
+    assert!(!output.contains("truenas_version=\"unknown\""));
+}
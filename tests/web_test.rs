@@ -0,0 +1,222 @@
+//! Exporter web configuration tests
+//!
+//! Runs the exporter with an exporter-toolkit web configuration file and checks
+//! authentication and HTTPS, using the certificates in `tests/fixtures/tls`.
+
+mod common;
+
+use base64::Engine;
+use common::{exporter_config, free_port, http_request, scratch_dir, start_exporter_with};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use truenas_exporter::config::MetricsConfig;
+use truenas_exporter::test_support::FakeTrueNas;
+use truenas_exporter::truenas::tls;
+use truenas_exporter::web::WebConfig;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tls")
+        .join(name)
+}
+
+/// Start the exporter with a web configuration file and wait until it accepts connections
+async fn start_exporter(nas: &FakeTrueNas, web_config_file: &Path) -> u16 {
+    let mut config = exporter_config(nas.config(), free_port().await, MetricsConfig::default());
+    config.server.web_config_file = Some(web_config_file.to_path_buf());
+    start_exporter_with(config).await
+}
+
+/// GET a path, optionally with an `Authorization` header, and return the response head
+async fn http_get(port: u16, path: &str, authorization: Option<&str>) -> String {
+    let headers = authorization
+        .map(|value| format!("Authorization: {}\r\n", value))
+        .unwrap_or_default();
+    http_request(port, "GET", path, &headers).await.unwrap().0
+}
+
+/// Open a TLS connection and return the certificate the server presented
+async fn tls_connect(
+    port: u16,
+) -> (
+    tokio_native_tls::TlsStream<TcpStream>,
+    native_tls::Certificate,
+) {
+    // The tests compare fingerprints; the replacement is a client certificate
+    let connector = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let tcp = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let stream = tokio_native_tls::TlsConnector::from(connector)
+        .connect("truenas.test", tcp)
+        .await
+        .unwrap();
+    let cert = stream.get_ref().peer_certificate().unwrap().unwrap();
+    (stream, cert)
+}
+
+#[tokio::test]
+async fn test_basic_auth_and_bearer_tokens() {
+    // Given: An exporter that accepts one user and one bearer token
+    let nas = FakeTrueNas::start().await;
+    nas.respond("pool.query", json!([]));
+    let dir = scratch_dir("web-auth");
+    let web_config = dir.join("web.yml");
+    std::fs::write(
+        &web_config,
+        format!(
+            "basic_auth_users:\n  Prometheus: {}\nbearer_tokens:\n  - {}\n",
+            bcrypt::hash("s3cret", 4).unwrap(),
+            bcrypt::hash("scrape-token", 4).unwrap()
+        ),
+    )
+    .unwrap();
+    let port = start_exporter(&nas, &web_config).await;
+    let basic = |credentials: &str| {
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        )
+    };
+
+    // When: /metrics is requested with and without credentials
+    let anonymous = http_get(port, "/metrics", None).await;
+    let wrong = http_get(port, "/metrics", Some(&basic("Prometheus:wrong"))).await;
+    let user = http_get(port, "/metrics", Some(&basic("Prometheus:s3cret"))).await;
+    let token = http_get(port, "/metrics", Some("Bearer scrape-token")).await;
+    let bad_token = http_get(port, "/metrics", Some("Bearer other")).await;
+
+    // Then: Only valid credentials are let through
+    assert!(anonymous.starts_with("HTTP/1.1 401"), "{}", anonymous);
+    assert!(
+        anonymous.to_lowercase().contains("www-authenticate: basic"),
+        "{}",
+        anonymous
+    );
+    assert!(wrong.starts_with("HTTP/1.1 401"), "{}", wrong);
+    assert!(user.starts_with("HTTP/1.1 200"), "{}", user);
+    assert!(token.starts_with("HTTP/1.1 200"), "{}", token);
+    assert!(bad_token.starts_with("HTTP/1.1 401"), "{}", bad_token);
+
+    // And: Health checks need no credentials
+    let health = http_get(port, "/health", None).await;
+    assert!(!health.starts_with("HTTP/1.1 401"), "{}", health);
+
+    // And: Changed credentials apply without a restart, once the file is checked again
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    std::fs::write(
+        &web_config,
+        format!(
+            "basic_auth_users:\n  Prometheus: {}\n",
+            bcrypt::hash("rotated", 4).unwrap()
+        ),
+    )
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let old = http_get(port, "/metrics", Some(&basic("Prometheus:s3cret"))).await;
+    let new = http_get(port, "/metrics", Some(&basic("Prometheus:rotated"))).await;
+    assert!(old.starts_with("HTTP/1.1 401"), "{}", old);
+    assert!(new.starts_with("HTTP/1.1 200"), "{}", new);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_https_reloads_certificate() {
+    // Given: An exporter serving HTTPS with the test server certificate
+    let nas = FakeTrueNas::start().await;
+    let dir = scratch_dir("web-tls");
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::copy(fixture("server.pem"), &cert).unwrap();
+    std::fs::copy(fixture("server-key.pem"), &key).unwrap();
+    let web_config = dir.join("web.yml");
+    std::fs::write(
+        &web_config,
+        format!(
+            "tls_server_config:\n  cert_file: {}\n  key_file: {}\n",
+            cert.display(),
+            key.display()
+        ),
+    )
+    .unwrap();
+    let port = start_exporter(&nas, &web_config).await;
+
+    // When: /metrics is requested over TLS
+    let (stream, served) = tls_connect(port).await;
+    let (head, _) = common::send_request(stream, "GET", "/metrics", "")
+        .await
+        .unwrap();
+
+    // Then: It is served with the configured certificate
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    let original = std::fs::read(fixture("server.pem")).unwrap();
+    assert_eq!(
+        tls::fingerprint(&served.to_der().unwrap()),
+        tls::fingerprint(
+            &native_tls::Certificate::from_pem(&original)
+                .unwrap()
+                .to_der()
+                .unwrap()
+        )
+    );
+
+    // And: A replaced certificate is used for new connections without a restart
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    std::fs::copy(fixture("client.pem"), &cert).unwrap();
+    std::fs::copy(fixture("client-key.pem"), &key).unwrap();
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let (_, served) = tls_connect(port).await;
+    let replacement = std::fs::read(fixture("client.pem")).unwrap();
+    assert_eq!(
+        tls::fingerprint(&served.to_der().unwrap()),
+        tls::fingerprint(
+            &native_tls::Certificate::from_pem(&replacement)
+                .unwrap()
+                .to_der()
+                .unwrap()
+        )
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_invalid_web_config_is_rejected() {
+    // Given: Web configurations with a malformed hash and unsupported client auth
+    let dir = scratch_dir("web-invalid");
+    let bad_hash = dir.join("bad-hash.yml");
+    std::fs::write(&bad_hash, "basic_auth_users:\n  prometheus: not-a-hash\n").unwrap();
+    let client_auth = dir.join("client-auth.yml");
+    std::fs::write(
+        &client_auth,
+        format!(
+            "tls_server_config:\n  cert_file: {}\n  key_file: {}\n  client_auth_type: RequireAndVerifyClientCert\n",
+            fixture("server.pem").display(),
+            fixture("server-key.pem").display()
+        ),
+    )
+    .unwrap();
+
+    // When: They are loaded
+    let bad_hash = WebConfig::load(&bad_hash).unwrap_err();
+    let client_auth = WebConfig::load(&client_auth).unwrap_err();
+
+    // Then: Both are rejected with the reason
+    assert!(
+        format!("{:#}", bad_hash).contains("Invalid bcrypt hash"),
+        "{:#}",
+        bad_hash
+    );
+    assert!(
+        client_auth
+            .to_string()
+            .contains("RequireAndVerifyClientCert"),
+        "{:#}",
+        client_auth
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}